@@ -1,15 +1,26 @@
 use std::error::Error;
+use std::time::Duration;
 
 use buzz::error::{BuzzError, Result as BuzzResult};
 use buzz::example_catalog;
 use buzz::services::fuse::{
     FargateHCombManager, FuseService, HttpHCombScheduler, LambdaHBeeScheduler,
-    QueryPlanner,
+    LocalDiskStore, QueryPlanner, QueryStats, ResultCache,
 };
 use lambda_runtime::{error::HandlerError, lambda, Context};
 use serde_json::Value;
 
-pub async fn start_fuse(event: Value) -> BuzzResult<()> {
+/// Environment variable with the directory where the results are cached, the cache
+/// is disabled if it is not set. On the local disk of the lambda container (e.g under
+/// `/tmp`), the cache is shared by the invocations that reuse the container.
+const RESULT_CACHE_DIR_VAR: &str = "BUZZ_RESULT_CACHE_DIR";
+/// Environment variable with the number of seconds the cached results are served
+const RESULT_CACHE_TTL_VAR: &str = "BUZZ_RESULT_CACHE_TTL";
+const DEFAULT_RESULT_CACHE_TTL: Duration = Duration::from_secs(300);
+/// Environment variable with the path of a file that defines additional catalogs
+const CATALOG_FILE_VAR: &str = "BUZZ_CATALOG_FILE";
+
+pub async fn start_fuse(event: Value) -> BuzzResult<QueryStats> {
     let hbee_scheduler = LambdaHBeeScheduler::try_new()?;
     let hcomb_manager = FargateHCombManager::try_new()?;
     let hcomb_scheduler = HttpHCombScheduler {};
@@ -20,6 +31,21 @@ pub async fn start_fuse(event: Value) -> BuzzResult<()> {
         Box::new(hcomb_scheduler),
         query_planner,
     );
+    if let Ok(dir) = std::env::var(RESULT_CACHE_DIR_VAR) {
+        let ttl = match std::env::var(RESULT_CACHE_TTL_VAR) {
+            Ok(secs) => Duration::from_secs(secs.parse().map_err(|_| {
+                BuzzError::BadRequest(format!(
+                    "{} should be a number of seconds, found {}",
+                    RESULT_CACHE_TTL_VAR, secs
+                ))
+            })?),
+            Err(_) => DEFAULT_RESULT_CACHE_TTL,
+        };
+        service = service.with_result_cache(ResultCache::new(
+            Box::new(LocalDiskStore::try_new(dir)?),
+            ttl,
+        ));
+    }
 
     service.add_catalog("nyc_taxi_ursa", example_catalog::nyc_taxi_ursa());
     service.add_catalog("nyc_taxi_cloudfuse", example_catalog::nyc_taxi_cloudfuse());
@@ -27,14 +53,17 @@ pub async fn start_fuse(event: Value) -> BuzzResult<()> {
         "nyc_taxi_cloudfuse_sample",
         example_catalog::nyc_taxi_cloudfuse_sample(),
     );
+    service.add_broadcast_table(example_catalog::nyc_taxi_payment_types())?;
+    if let Ok(path) = std::env::var(CATALOG_FILE_VAR) {
+        service.add_catalogs_from_file(&path).await?;
+    }
 
     println!("[fuse] initialized, starting query...");
 
     let query = serde_json::from_value(event)
         .map_err(|e| BuzzError::BadRequest(format!("{}", e)))?;
 
-    service.run(query).await?;
-    Ok(())
+    service.run(query).await
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -42,11 +71,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn my_handler(event: Value, _: Context) -> Result<Value, HandlerError> {
+fn my_handler(event: Value, ctx: Context) -> Result<Value, HandlerError> {
     println!("Input Event: {:?}", event);
-    tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(start_fuse(event))
-        .unwrap();
-    Ok(Value::String("Ok!".to_owned()))
+    let mut runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| ctx.new_error(&format!("Runtime could not be started: {}", e)))?;
+    let stats = runtime.block_on(start_fuse(event)).map_err(|e| {
+        println!("[fuse] query failed: {}", e);
+        ctx.new_error(&e.to_string())
+    })?;
+    serde_json::to_value(stats)
+        .map_err(|e| ctx.new_error(&format!("Stats could not be serialized: {}", e)))
 }
@@ -24,9 +24,16 @@ const QUERY: &'static str = r#"
 }
 "#;
 
+/// The environment variable with the directory of the `nyc_taxi` Parquet files,
+/// to run the query without any AWS resource
+pub const LOCAL_DATA_DIR_VAR: &str = "LOCAL_DATA_DIR";
+
+/// Run the query on the hbee and hcomb servers at the given addresses. The `nyc_taxi`
+/// files are read from `local_data_dir` if it is specified, from S3 otherwise.
 pub async fn start_fuse(
     hbee_addr: &str,
     hcomb_addr: &str,
+    local_data_dir: Option<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // a fuse is all about the right delay, otherwise everything explodes to your face :)
     tokio::time::delay_for(std::time::Duration::new(1, 0)).await;
@@ -46,15 +53,31 @@ pub async fn start_fuse(
         query_planner,
     );
 
-    service.add_catalog("nyc_taxi", example_catalog::nyc_taxi_cloudfuse_sample());
+    match local_data_dir {
+        Some(dir) => {
+            service.add_catalog("nyc_taxi", example_catalog::nyc_taxi_local(&dir))
+        }
+        None => {
+            service.add_catalog("nyc_taxi", example_catalog::nyc_taxi_cloudfuse_sample())
+        }
+    }
+    if let Ok(path) = std::env::var("BUZZ_CATALOG_FILE") {
+        service.add_catalogs_from_file(&path).await?;
+    }
 
     let query = serde_json::from_str(QUERY)?;
 
-    service.run(query).await?;
+    let stats = service.run(query).await?;
+    if let Some(explain) = &stats.explain {
+        println!("{}", explain);
+    }
+    if let Some(estimate) = &stats.estimate {
+        println!("{}", serde_json::to_string_pretty(estimate)?);
+    }
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    start_fuse("hbee", "hcomb").await
+    start_fuse("hbee", "hcomb", std::env::var(LOCAL_DATA_DIR_VAR).ok()).await
 }
@@ -7,7 +7,8 @@ use serde_json::Value;
 
 async fn exec(event: Value) -> Result<(), Box<dyn Error>> {
     let hbee_event: HBeeEvent = serde_json::from_value(event)?;
-    let (hbee_table_desc, sql, source) = hbee_event.plan.parse()?;
+    let (hbee_table_desc, sql, source, broadcasts, shuffle_keys) =
+        hbee_event.plan.parse()?;
     let collector = Box::new(HttpCollector {});
     let mut hbee_service = HBeeService::new(collector).await;
     hbee_service
@@ -16,7 +17,9 @@ async fn exec(event: Value) -> Result<(), Box<dyn Error>> {
             hbee_table_desc,
             sql,
             source,
-            hbee_event.hcomb_address,
+            broadcasts,
+            shuffle_keys,
+            hbee_event.hcomb_addresses,
         )
         .await
         .map_err(|e| Box::new(e).into())
@@ -27,11 +30,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn my_handler(event: Value, _: Context) -> Result<Value, HandlerError> {
+fn my_handler(event: Value, ctx: Context) -> Result<Value, HandlerError> {
     println!("Input Event: {:?}", event);
-    tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(exec(event))
-        .unwrap();
+    let mut runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| ctx.new_error(&format!("Runtime could not be started: {}", e)))?;
+    runtime.block_on(exec(event)).map_err(|e| {
+        println!("[hbee] query failed: {}", e);
+        ctx.new_error(&e.to_string())
+    })?;
     Ok(Value::String("Ok!".to_owned()))
 }
@@ -15,7 +15,8 @@ async fn exec(mut req: Request<Body>) -> Result<Response<Body>, DynError> {
         body.extend_from_slice(&chunk?);
     }
     let hbee_event: HBeeEvent = serde_json::from_slice(&body)?;
-    let (hbee_table_desc, sql, source) = hbee_event.plan.parse()?;
+    let (hbee_table_desc, sql, source, broadcasts, shuffle_keys) =
+        hbee_event.plan.parse()?;
     tokio::spawn(async {
         let collector = Box::new(HttpCollector {});
         let mut hbee_service = HBeeService::new(collector).await;
@@ -25,7 +26,9 @@ async fn exec(mut req: Request<Body>) -> Result<Response<Body>, DynError> {
                 hbee_table_desc,
                 sql,
                 source,
-                hbee_event.hcomb_address,
+                broadcasts,
+                shuffle_keys,
+                hbee_event.hcomb_addresses,
             )
             .await;
         match res {
@@ -21,6 +21,10 @@ async fn new_plan(event: Value) -> Result<HBeePlan, Box<dyn Error>> {
         .await
         .map(|dist_plan| {
             dist_plan
+                .stages
+                .into_iter()
+                .next()
+                .unwrap()
                 .zones
                 .into_iter()
                 .next()
@@ -43,7 +47,9 @@ async fn exec(event: Value) -> Result<(), Box<dyn Error>> {
             plan.table,
             plan.sql,
             plan.source,
-            "http://mock_endpoint".to_owned(),
+            plan.broadcasts,
+            plan.shuffle_keys,
+            vec!["http://mock_endpoint".to_owned()],
         )
         .await
         .map_err(|e| Box::new(e).into())
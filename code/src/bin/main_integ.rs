@@ -4,8 +4,14 @@ mod main_hcomb;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // the hbee server runs in this process, so it sees the same directory as the fuse
+    let local_data_dir = std::env::var(main_fuse_local::LOCAL_DATA_DIR_VAR).ok();
+    match &local_data_dir {
+        Some(dir) => println!("[integ] reading the nyc_taxi files from {}", dir),
+        None => println!("[integ] reading the nyc_taxi files from S3"),
+    }
     tokio::select! {
-        res = main_fuse_local::start_fuse("localhost", "localhost") => {
+        res = main_fuse_local::start_fuse("localhost", "localhost", local_data_dir) => {
             println!("[integ] fuse result: {:?}", res);
         }
         res = main_hbee_local::start_hbee_server() => {
@@ -12,6 +12,9 @@ use rusoto_ecs::{
 };
 use tokio::time::timeout;
 
+/// The maximum number of tasks that can be started by a single RunTask request
+const MAX_TASKS_PER_RUN: usize = 10;
+
 pub struct FargateCreationClient {
     client: Arc<EcsClient>,
     config: Arc<FargateConfig>,
@@ -28,84 +31,103 @@ impl FargateCreationClient {
 }
 
 impl FargateCreationClient {
-    /// Create a new fargate task and returns private IP.
-    /// The task might not be ready to receive requests yet.
-    pub async fn create_new(&self) -> Result<String> {
+    /// Find or create `n` distinct fargate tasks and return their private IPs.
+    /// The running tasks are reused first, only the missing ones are started.
+    /// The tasks might not be ready to receive requests yet.
+    pub async fn create_n(&self, n: usize) -> Result<Vec<String>> {
         let start = Instant::now();
         let config = Arc::clone(&self.config);
 
-        let mut task_arn = self
-            .get_existing_task(config.hcomb_cluster_name.clone())
+        let mut task_arns = self
+            .get_existing_tasks(config.hcomb_cluster_name.clone(), n)
             .await;
 
-        if task_arn.is_none() {
-            task_arn = Some(
-                self.start_task(
+        while task_arns.len() < n {
+            // ECS starts at most 10 tasks per request
+            let count = std::cmp::min(n - task_arns.len(), MAX_TASKS_PER_RUN);
+            let new_arns = self
+                .start_tasks(
                     config.hcomb_task_def_arn.clone(),
                     config.hcomb_cluster_name.clone(),
                     config.public_subnets.clone(),
                     config.hcomb_task_sg_id.clone(),
+                    count,
                 )
-                .await?,
-            );
+                .await?;
+            task_arns.extend(new_arns);
         }
 
-        println!("[fuse] task started");
+        println!("[fuse] {} task(s) started", task_arns.len());
 
         tokio::time::delay_for(Duration::from_secs(1)).await;
 
-        let result = self
-            .wait_for_provisioning(task_arn.unwrap(), config.hcomb_cluster_name.clone())
-            .await?;
+        let provisionings = task_arns.into_iter().map(|task_arn| {
+            self.wait_for_provisioning(task_arn, config.hcomb_cluster_name.clone())
+        });
+        let result = futures::future::try_join_all(provisionings).await?;
 
         println!(
-            "[fuse] took {}ms to create new task",
-            start.elapsed().as_millis()
+            "[fuse] took {}ms to create {} task(s)",
+            start.elapsed().as_millis(),
+            n
         );
 
         Ok(result)
     }
 
-    /// Get existing task ARN if their is one
-    /// /// TODO better error management
-    async fn get_existing_task(&self, cluster_name: String) -> Option<String> {
-        let request = ListTasksRequest {
-            cluster: Some(cluster_name),
-            container_instance: None,
-            desired_status: Some("RUNNING".to_owned()),
-            family: None,
-            launch_type: None,
-            max_results: Some(1),
-            next_token: None,
-            service_name: None,
-            started_by: None,
-        };
-
-        let result =
-            timeout(Duration::from_secs(2), self.client.list_tasks(request)).await;
+    /// Get the ARNs of at most `max` existing tasks
+    /// TODO better error management
+    async fn get_existing_tasks(&self, cluster_name: String, max: usize) -> Vec<String> {
+        let mut arns = vec![];
+        let mut next_token = None;
+        while arns.len() < max {
+            let request = ListTasksRequest {
+                cluster: Some(cluster_name.clone()),
+                container_instance: None,
+                desired_status: Some("RUNNING".to_owned()),
+                family: None,
+                launch_type: None,
+                max_results: None,
+                next_token: next_token.take(),
+                service_name: None,
+                started_by: None,
+            };
 
-        // if request for existing task failed for any reason, return None
-        match result {
-            Ok(Ok(ListTasksResponse {
-                task_arns: Some(arns),
-                ..
-            })) if arns.len() > 0 => Some(arns[0].clone()),
-            _ => None,
+            let result =
+                timeout(Duration::from_secs(2), self.client.list_tasks(request)).await;
+
+            // if request for existing tasks failed for any reason, start new ones
+            match result {
+                Ok(Ok(ListTasksResponse {
+                    task_arns,
+                    next_token: token,
+                })) => {
+                    arns.extend(task_arns.unwrap_or_default());
+                    match token {
+                        Some(token) => next_token = Some(token),
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
         }
+        arns.truncate(max);
+        arns
     }
 
-    /// Start new task and return its arn
+    /// Start `count` new tasks and return their arns
     /// TODO better error management
-    async fn start_task(
+    async fn start_tasks(
         &self,
         task_definition: String,
         cluster_name: String,
         subnets: Vec<String>,
         security_group: String,
-    ) -> Result<String> {
+        count: usize,
+    ) -> Result<Vec<String>> {
         let input = RunTaskRequest {
             task_definition,
-            count: Some(1),
+            count: Some(count as i64),
             cluster: Some(cluster_name),
             group: None,
             network_configuration: Some(NetworkConfiguration {
@@ -140,14 +162,20 @@ impl FargateCreationClient {
             }
         }
 
-        Ok(result
+        let task_arns = result
             .tasks
-            .unwrap()
+            .unwrap_or_default()
             .into_iter()
-            .next()
-            .unwrap()
-            .task_arn
-            .unwrap())
+            .filter_map(|task| task.task_arn)
+            .collect::<Vec<_>>();
+        if task_arns.len() != count {
+            return Err(BuzzError::CloudClient(format!(
+                "AWS Fargate started {} task(s) instead of {}",
+                task_arns.len(),
+                count
+            )));
+        }
+        Ok(task_arns)
     }
 
     /// Wait for the given task to be provisioned and attributed a private IP
@@ -3,6 +3,7 @@ use std::pin::Pin;
 
 use crate::datasource::HCombTableDesc;
 use crate::flight_utils;
+use crate::internal_err;
 use crate::models::{actions, HCombAddress};
 use crate::serde;
 use arrow::error::Result as ArrowResult;
@@ -16,14 +17,13 @@ use prost::Message;
 /// Calls the hcomb do_get endpoint, expecting the first message to be the schema
 pub async fn call_do_get(
     address: &HCombAddress,
-    hcomb_table: &HCombTableDesc,
+    inputs: &[(HCombTableDesc, String)],
     sql: String,
-    source: String,
 ) -> Result<Pin<Box<dyn Stream<Item = ArrowResult<RecordBatch>>>>, Box<dyn Error>> {
     // Create Flight client
     let mut client = FlightServiceClient::connect(address.clone()).await?;
 
-    let proto_plan = serde::serialize_hcomb(hcomb_table, sql, source);
+    let proto_plan = serde::serialize_hcomb(inputs, sql);
 
     let mut buf = vec![];
     proto_plan.encode(&mut buf)?;
@@ -62,7 +62,8 @@ pub async fn call_fail_action(
     reason: String,
 ) -> Result<(), Box<dyn Error>> {
     let action = arrow_flight::Action {
-        body: serde_json::to_vec(&actions::Fail { query_id, reason }).unwrap(),
+        body: serde_json::to_vec(&actions::Fail { query_id, reason })
+            .map_err(|e| internal_err!("failed to serialize fail action: {}", e))?,
         r#type: actions::ActionType::Fail.to_string(),
     };
 
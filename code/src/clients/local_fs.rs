@@ -0,0 +1,137 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::range_cache::Downloader;
+use super::s3::ObjectLister;
+use crate::error::{BuzzError, Result};
+use crate::internal_err;
+use crate::models::SizedFile;
+use async_trait::async_trait;
+
+//// Implementation of the `download` function used by the range cache to read local files
+
+#[derive(Clone)]
+struct FsDownloader {}
+
+#[async_trait]
+impl Downloader for FsDownloader {
+    async fn download(
+        &self,
+        file_id: String,
+        start: u64,
+        length: usize,
+    ) -> Result<Vec<u8>> {
+        // file system calls are blocking so they should run on a specific thread
+        let path = file_id.clone();
+        let read_res =
+            tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u8>> {
+                let mut result = vec![0; length];
+                let mut file = File::open(&path)?;
+                file.seek(SeekFrom::Start(start))?;
+                file.read_exact(&mut result)?;
+                Ok(result)
+            })
+            .await
+            .map_err(|e| internal_err!("File reading task failed: {}", e))?;
+        read_res.map_err(|e| BuzzError::Download(format!("{}: {}", file_id, e)))
+    }
+}
+
+pub fn downloader_creator() -> (String, Box<dyn Fn() -> Arc<dyn Downloader>>) {
+    let creator: Box<dyn Fn() -> Arc<dyn Downloader>> =
+        Box::new(|| Arc::new(FsDownloader {}));
+    ("fs".to_owned(), creator)
+}
+
+/// The path of the file with the given `key` (relative path with `/` separators) in `dir`
+pub fn file_id(dir: &str, key: &str) -> String {
+    key.split('/')
+        .fold(Path::new(dir).to_path_buf(), |path, segment| {
+            path.join(segment)
+        })
+        .to_string_lossy()
+        .into_owned()
+}
+
+//// Listing of the files of a directory, the directory plays the role of the bucket
+
+pub struct FsLister {}
+
+#[async_trait]
+impl ObjectLister for FsLister {
+    /// All the files under `dir` whose path relative to `dir` starts with `prefix`
+    async fn list(&self, dir: &str, prefix: &str) -> Result<Vec<SizedFile>> {
+        let mut files = vec![];
+        list_recursive(Path::new(dir), "", &mut files)?;
+        files.retain(|file| file.key.starts_with(prefix));
+        files.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(files)
+    }
+}
+
+fn list_recursive(
+    dir: &Path,
+    key_prefix: &str,
+    files: &mut Vec<SizedFile>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let key = format!("{}{}", key_prefix, entry.file_name().to_string_lossy());
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            list_recursive(&entry.path(), &format!("{}/", key), files)?;
+        } else {
+            files.push(SizedFile {
+                key,
+                length: metadata.len(),
+                row_groups: None,
+                row_groups_length: None,
+                partitions: vec![],
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_and_download() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "buzz-local-fs-{}",
+            chrono::Utc::now().timestamp_nanos()
+        ));
+        fs::create_dir_all(dir.join("part=1"))?;
+        fs::write(dir.join("part=1").join("data.parquet"), b"0123456789")?;
+        fs::write(dir.join("other.parquet"), b"abc")?;
+        let dir_str = dir.to_string_lossy().into_owned();
+
+        let files = FsLister {}.list(&dir_str, "").await?;
+        let keys = files
+            .iter()
+            .map(|file| (file.key.as_str(), file.length))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![("other.parquet", 3), ("part=1/data.parquet", 10)]
+        );
+        assert_eq!(FsLister {}.list(&dir_str, "part=").await?.len(), 1);
+
+        let (_, creator) = downloader_creator();
+        let downloaded = creator()
+            .download(file_id(&dir_str, "part=1/data.parquet"), 2, 5)
+            .await?;
+        assert_eq!(downloaded, b"23456".to_vec());
+        creator()
+            .download(file_id(&dir_str, "part=1/data.parquet"), 8, 5)
+            .await
+            .expect_err("Reading past the end of the file should fail");
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
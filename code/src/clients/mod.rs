@@ -4,6 +4,7 @@ mod cached_file;
 pub mod fargate;
 pub mod flight_client;
 pub mod lambda;
+pub mod local_fs;
 mod range_cache;
 pub mod s3;
 
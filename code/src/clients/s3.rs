@@ -3,9 +3,12 @@ use std::sync::Arc;
 
 use super::range_cache::Downloader;
 use crate::error::{BuzzError, Result};
+use crate::models::SizedFile;
 use async_trait::async_trait;
 use rusoto_core::Region;
-use rusoto_s3::{GetObjectOutput, GetObjectRequest, S3Client, S3};
+use rusoto_s3::{
+  GetObjectOutput, GetObjectRequest, ListObjectsV2Request, S3Client, S3,
+};
 use tokio::io::AsyncReadExt;
 
 //// Implementation of the `download` function used by the range cache to fetch data
@@ -70,6 +73,66 @@ pub fn file_id(bucket: &str, key: &str) -> String {
   format!("{}/{}", bucket, key)
 }
 
+//// Listing of the objects of a bucket, used by the catalogs that discover their files
+
+#[async_trait]
+pub trait ObjectLister: Send + Sync {
+  /// All the objects of the bucket whose key starts with `prefix`, with their length
+  async fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<SizedFile>>;
+}
+
+pub struct S3Lister {
+  client: Arc<S3Client>,
+}
+
+impl S3Lister {
+  pub fn new(region: &str) -> Self {
+    Self {
+      client: new_client(region),
+    }
+  }
+}
+
+#[async_trait]
+impl ObjectLister for S3Lister {
+  async fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<SizedFile>> {
+    let mut files = vec![];
+    let mut continuation_token = None;
+    // the listing is paginated, 1000 keys per page
+    loop {
+      let list_req = ListObjectsV2Request {
+        bucket: bucket.to_owned(),
+        prefix: Some(prefix.to_owned()),
+        continuation_token,
+        ..Default::default()
+      };
+      let page = self
+        .client
+        .list_objects_v2(list_req)
+        .await
+        .map_err(|e| BuzzError::CloudClient(format!("{}", e)))?;
+      for object in page.contents.unwrap_or_default() {
+        if let (Some(key), Some(size)) = (object.key, object.size) {
+          files.push(SizedFile {
+            key,
+            length: size as u64,
+            row_groups: None,
+            row_groups_length: None,
+            partitions: vec![],
+          });
+        }
+      }
+      match page.next_continuation_token {
+        Some(token) if page.is_truncated == Some(true) => {
+          continuation_token = Some(token)
+        }
+        _ => break,
+      }
+    }
+    Ok(files)
+  }
+}
+
 //// S3 Client ////
 
 fn new_client(region: &str) -> Arc<S3Client> {
@@ -0,0 +1,67 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use arrow::datatypes::*;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use datafusion::datasource::MemTable;
+
+/// A small table that is sent along with the hbee plan,
+/// so that each hbee can join it locally with its part of the catalog.
+#[derive(Debug, Clone)]
+pub struct BroadcastTable {
+    name: String,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+}
+
+impl BroadcastTable {
+    pub fn new(name: String, schema: SchemaRef, batches: Vec<RecordBatch>) -> Self {
+        Self {
+            name,
+            schema,
+            batches,
+        }
+    }
+
+    /// Read the table from its Arrow IPC stream representation
+    pub fn try_from_ipc(name: String, data: &[u8]) -> Result<Self> {
+        let reader = StreamReader::try_new(Cursor::new(data))?;
+        let schema = reader.schema();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Self::new(name, schema, batches))
+    }
+
+    /// Serialize the table to the Arrow IPC stream format
+    pub fn to_ipc(&self) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        {
+            let mut writer = StreamWriter::try_new(&mut data, &self.schema)?;
+            for batch in &self.batches {
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+        }
+        Ok(data)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    pub fn batches(&self) -> &[RecordBatch] {
+        &self.batches
+    }
+
+    pub fn to_mem_table(&self) -> Result<MemTable> {
+        Ok(MemTable::try_new(
+            self.schema.clone(),
+            vec![self.batches.clone()],
+        )?)
+    }
+}
@@ -0,0 +1,324 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::hive_catalog::HiveCatalogTable;
+use super::local_catalog::LocalCatalogTable;
+use super::static_catalog::{CatalogFile, StaticCatalogTable};
+use super::{CatalogTable, DEFAULT_SCHEMA_SAMPLE_FILES};
+use crate::error::{BuzzError, Result};
+use arrow::datatypes::*;
+use serde::Deserialize;
+
+/// A file with the definitions of the catalogs that should be registered,
+/// in JSON or in YAML (if the extension is `.yaml` or `.yml`)
+#[derive(Deserialize, Debug)]
+pub struct CatalogConfig {
+    pub catalogs: Vec<CatalogDefinition>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CatalogDefinition {
+    /// The name of the table in the queries
+    pub name: String,
+    /// The columns of the data files, partition columns excluded.
+    /// If empty, the schema is inferred from the footers of a sample of the files.
+    #[serde(default)]
+    pub schema: Vec<FieldDefinition>,
+    /// Check the declared schema against the footers of a sample of the files
+    #[serde(default)]
+    pub validate_schema: bool,
+    #[serde(default)]
+    pub partition_cols: Vec<PartitionDefinition>,
+    /// Overrides the amount of data each hbee should read from this catalog
+    #[serde(default)]
+    pub bytes_per_hbee: Option<u64>,
+    /// Where the files of the catalog are found, depending on its `type`
+    #[serde(flatten)]
+    pub location: CatalogLocation,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CatalogLocation {
+    /// A `StaticCatalogTable`, all its files are listed in the definition
+    Static {
+        region: String,
+        bucket: String,
+        files: Vec<FileDefinition>,
+    },
+    /// A `HiveCatalogTable` that lists the files under the prefix
+    Hive {
+        region: String,
+        bucket: String,
+        prefix: String,
+    },
+    /// A `LocalCatalogTable` that lists the files of the directory
+    Local { dir: String },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FieldDefinition {
+    pub name: String,
+    /// The Arrow type of the column, e.g `utf8`, `int64` or `timestamp[us]`
+    #[serde(rename = "type")]
+    pub data_type: String,
+    #[serde(default = "default_nullable")]
+    pub nullable: bool,
+}
+
+fn default_nullable() -> bool {
+    true
+}
+
+/// A partition column, given by its name only if its values are strings
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum PartitionDefinition {
+    Utf8(String),
+    Typed {
+        name: String,
+        #[serde(rename = "type")]
+        data_type: String,
+    },
+}
+
+impl PartitionDefinition {
+    fn to_field(&self) -> Result<Field> {
+        Ok(match self {
+            PartitionDefinition::Utf8(name) => Field::new(name, DataType::Utf8, false),
+            PartitionDefinition::Typed { name, data_type } => {
+                Field::new(name, parse_data_type(data_type)?, false)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FileDefinition {
+    pub key: String,
+    pub length: u64,
+    /// The values of the partition columns for this file, in the same order
+    #[serde(default)]
+    pub partitions: Vec<String>,
+}
+
+impl CatalogConfig {
+    /// Read the definitions from a JSON or YAML file
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+        match extension {
+            Some("yaml") | Some("yml") => Self::from_yaml(&content),
+            _ => Self::from_json(&content),
+        }
+        .map_err(|e| {
+            BuzzError::BadRequest(format!("Invalid catalog file {}: {}", path, e))
+        })
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        serde_json::from_str(content).map_err(|e| BuzzError::BadRequest(format!("{}", e)))
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        serde_yaml::from_str(content).map_err(|e| BuzzError::BadRequest(format!("{}", e)))
+    }
+
+    /// Build the catalog tables with their names, reading the footers of the files
+    /// of the catalogs whose schema should be inferred or validated
+    pub async fn into_tables(self) -> Result<Vec<(String, CatalogTable)>> {
+        let mut tables = vec![];
+        for def in self.catalogs {
+            let name = def.name.clone();
+            let table = def.into_table().await.map_err(|e| {
+                BuzzError::BadRequest(format!("Invalid catalog {}: {}", name, e))
+            })?;
+            tables.push((name, table));
+        }
+        Ok(tables)
+    }
+}
+
+impl CatalogDefinition {
+    pub async fn into_table(self) -> Result<CatalogTable> {
+        let fields = self
+            .schema
+            .iter()
+            .map(|field| {
+                Ok(Field::new(
+                    &field.name,
+                    parse_data_type(&field.data_type)?,
+                    field.nullable,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let schema = Arc::new(Schema::new(fields));
+        let partition_cols = self
+            .partition_cols
+            .iter()
+            .map(PartitionDefinition::to_field)
+            .collect::<Result<Vec<_>>>()?;
+        let table = match self.location {
+            CatalogLocation::Static {
+                region,
+                bucket,
+                files,
+            } => StaticCatalogTable::new(
+                schema,
+                region,
+                bucket,
+                partition_cols,
+                files
+                    .into_iter()
+                    .map(|file| CatalogFile::new(&file.key, file.length, file.partitions))
+                    .collect(),
+            ),
+            CatalogLocation::Hive {
+                region,
+                bucket,
+                prefix,
+            } => HiveCatalogTable::new(schema, region, bucket, prefix, partition_cols),
+            CatalogLocation::Local { dir } => {
+                LocalCatalogTable::new(schema, dir, partition_cols)
+            }
+        };
+        let table = match self.bytes_per_hbee {
+            Some(bytes_per_hbee) => table.with_bytes_per_hbee(bytes_per_hbee),
+            None => table,
+        };
+        if self.schema.is_empty() {
+            table
+                .with_inferred_schema(DEFAULT_SCHEMA_SAMPLE_FILES)
+                .await
+        } else {
+            if self.validate_schema {
+                table.validate_schema(DEFAULT_SCHEMA_SAMPLE_FILES).await?;
+            }
+            Ok(table)
+        }
+    }
+}
+
+/// Parse the name of an Arrow type, e.g `int32`, `utf8`, `date32` or `timestamp[ms]`
+pub fn parse_data_type(name: &str) -> Result<DataType> {
+    let data_type = match name.trim().to_lowercase().as_str() {
+        "null" => DataType::Null,
+        "bool" | "boolean" => DataType::Boolean,
+        "int8" => DataType::Int8,
+        "int16" => DataType::Int16,
+        "int32" => DataType::Int32,
+        "int64" => DataType::Int64,
+        "uint8" => DataType::UInt8,
+        "uint16" => DataType::UInt16,
+        "uint32" => DataType::UInt32,
+        "uint64" => DataType::UInt64,
+        "float32" | "float" => DataType::Float32,
+        "float64" | "double" => DataType::Float64,
+        "utf8" | "string" => DataType::Utf8,
+        "binary" => DataType::Binary,
+        "date32" | "date" => DataType::Date32(DateUnit::Day),
+        "date64" => DataType::Date64(DateUnit::Millisecond),
+        "timestamp[s]" => DataType::Timestamp(TimeUnit::Second, None),
+        "timestamp[ms]" => DataType::Timestamp(TimeUnit::Millisecond, None),
+        "timestamp[us]" | "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, None),
+        "timestamp[ns]" => DataType::Timestamp(TimeUnit::Nanosecond, None),
+        _ => {
+            return Err(BuzzError::BadRequest(format!(
+                "Unsupported data type: {}",
+                name
+            )))
+        }
+    };
+    Ok(data_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::datasource::TableProvider;
+
+    #[tokio::test]
+    async fn test_yaml_catalogs() -> Result<()> {
+        let config = CatalogConfig::from_yaml(
+            r#"
+catalogs:
+  - name: gifts
+    type: static
+    region: north-pole-1
+    bucket: santas-bucket
+    schema:
+      - { name: id, type: int64, nullable: false }
+      - { name: delivered_at, type: "timestamp[ms]" }
+    partition_cols: [year]
+    files:
+      - { key: gifts/2020.parquet, length: 1000, partitions: ["2020"] }
+  - name: elves
+    type: local
+    dir: /data/elves
+    schema:
+      - { name: name, type: utf8 }
+    bytes_per_hbee: 1000000
+"#,
+        )?;
+        let tables = config.into_tables().await?;
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].0, "gifts");
+        let schema = tables[0].1.schema();
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert!(!schema.field(0).is_nullable());
+        assert_eq!(
+            schema.field(1).data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, None)
+        );
+        assert!(schema.field(1).is_nullable());
+        assert_eq!(schema.field(2).name(), "year");
+        assert_eq!(tables[1].0, "elves");
+        assert_eq!(tables[1].1.schema().fields().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_json_catalogs() -> Result<()> {
+        let config = CatalogConfig::from_json(
+            r#"{
+                "catalogs": [{
+                    "name": "gifts",
+                    "type": "hive",
+                    "region": "north-pole-1",
+                    "bucket": "santas-bucket",
+                    "prefix": "gifts",
+                    "schema": [{ "name": "id", "type": "int64" }],
+                    "partition_cols": ["city", { "name": "year", "type": "int32" }]
+                }]
+            }"#,
+        )?;
+        let tables = config.into_tables().await?;
+        let schema = tables[0].1.schema();
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(schema.field(1).data_type(), &DataType::Utf8);
+        assert_eq!(schema.field(2).data_type(), &DataType::Int32);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_catalogs() {
+        let config = CatalogConfig::from_json(
+            r#"{"catalogs": [{"name": "gifts", "type": "local", "dir": "/data",
+                "schema": [{"name": "id", "type": "bigint"}]}]}"#,
+        )
+        .unwrap();
+        config
+            .into_tables()
+            .await
+            .err()
+            .expect("Unknown types should be rejected");
+
+        CatalogConfig::from_json(
+            r#"{"catalogs": [{"name": "gifts", "type": "redshift", "schema": []}]}"#,
+        )
+        .err()
+        .expect("Unknown catalog types should be rejected");
+    }
+}
@@ -0,0 +1,317 @@
+use super::parquet_footer::FooterCache;
+use super::static_catalog::{
+    files_table, s3_file_schemas, s3_row_group_sizes, split_s3_parquet,
+};
+use super::{CatalogTable, SplittableTable};
+use crate::clients::s3::{ObjectLister, S3Lister};
+use crate::datasource::{CatalogFile, HBeeTableDesc};
+use crate::error::Result;
+use crate::models::SizedFile;
+use arrow::datatypes::*;
+use async_trait::async_trait;
+use datafusion::datasource::TableProvider;
+
+/// A catalog table that lists the files under an S3 prefix each time it is queried.
+/// The files are expected to be organized in Hive style `col=value/` directories,
+/// one level for each partition column, in order.
+/// Only supports S3 parquet files for now.
+pub struct HiveCatalogTable {
+    schema: SchemaRef,
+    region: String,
+    bucket: String,
+    prefix: String,
+    partition_cols: Vec<Field>,
+    lister: Box<dyn ObjectLister>,
+    footer_cache: FooterCache,
+}
+
+impl HiveCatalogTable {
+    pub fn new(
+        schema: SchemaRef,
+        region: String,
+        bucket: String,
+        prefix: String,
+        partition_cols: Vec<Field>,
+    ) -> CatalogTable {
+        let lister = Box::new(S3Lister::new(&region));
+        Self::with_lister(schema, region, bucket, prefix, partition_cols, lister)
+    }
+
+    /// Create a catalog that uses the given lister to discover its files
+    pub fn with_lister(
+        schema: SchemaRef,
+        region: String,
+        bucket: String,
+        prefix: String,
+        partition_cols: Vec<Field>,
+        lister: Box<dyn ObjectLister>,
+    ) -> CatalogTable {
+        // without the trailing `/`, the listing would include sibling prefixes
+        let prefix = if prefix.is_empty() || prefix.ends_with('/') {
+            prefix
+        } else {
+            format!("{}/", prefix)
+        };
+        CatalogTable::new(Box::new(Self {
+            schema,
+            region,
+            bucket,
+            prefix,
+            partition_cols,
+            lister,
+            footer_cache: FooterCache::default(),
+        }))
+    }
+}
+
+/// Parse the partition values from the key of a file under `prefix` (empty or ending
+/// with `/`) that is organized in Hive style directories. Returns `None` for the files
+/// that are not data files and for the keys that do not match the layout of the
+/// partition columns, which are logged and skipped.
+pub(crate) fn parse_hive_key(
+    file: SizedFile,
+    prefix: &str,
+    partition_cols: &[Field],
+) -> Option<CatalogFile> {
+    let relative_key = match file.key.strip_prefix(prefix) {
+        Some(relative_key) => relative_key,
+        None => return skip_invalid_key(&file.key, prefix, partition_cols),
+    };
+    let segments = relative_key.split('/').collect::<Vec<_>>();
+    // skip markers (e.g `_SUCCESS`), temporary directories and hidden files
+    if file.length == 0
+        || segments
+            .iter()
+            .any(|seg| seg.is_empty() || seg.starts_with('_') || seg.starts_with('.'))
+    {
+        return None;
+    }
+    let directories = &segments[..segments.len() - 1];
+    if directories.len() != partition_cols.len() {
+        return skip_invalid_key(&file.key, prefix, partition_cols);
+    }
+    let partitions = directories
+        .iter()
+        .zip(partition_cols)
+        .map(|(dir, col)| match dir.find('=') {
+            Some(pos) if &dir[..pos] == col.name() => Some(unescape(&dir[pos + 1..])),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    match partitions {
+        Some(partitions) => Some(CatalogFile::new(&file.key, file.length, partitions)),
+        None => skip_invalid_key(&file.key, prefix, partition_cols),
+    }
+}
+
+fn skip_invalid_key(
+    key: &str,
+    prefix: &str,
+    partition_cols: &[Field],
+) -> Option<CatalogFile> {
+    println!(
+        "[fuse] skipping {}, the files should be under {} with one directory for each partition column ({})",
+        key,
+        prefix,
+        partition_cols
+            .iter()
+            .map(|col| format!("{}=<value>", col.name()))
+            .collect::<Vec<_>>()
+            .join("/")
+    );
+    None
+}
+
+/// Decode the `%XX` sequences that Hive uses to escape special characters
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+            std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        });
+        match hex {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[async_trait]
+impl SplittableTable for HiveCatalogTable {
+    fn split(&self, file_groups: Vec<Vec<SizedFile>>) -> Vec<HBeeTableDesc> {
+        split_s3_parquet(&self.region, &self.bucket, &self.schema, file_groups)
+    }
+    async fn row_group_sizes(&self, files: &[SizedFile]) -> Result<Vec<Vec<u64>>> {
+        s3_row_group_sizes(&self.region, &self.bucket, &self.footer_cache, files).await
+    }
+    async fn file_schemas(&self, files: &[SizedFile]) -> Result<Vec<Schema>> {
+        s3_file_schemas(&self.region, &self.bucket, &self.footer_cache, files).await
+    }
+    fn partition_columns(&self) -> &[Field] {
+        &self.partition_cols
+    }
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+    fn set_schema(&mut self, schema: SchemaRef) {
+        self.schema = schema;
+    }
+    /// List the files under the prefix, so that new files are picked up by the next query
+    async fn file_table(&self) -> Result<Box<dyn TableProvider + Send + Sync>> {
+        let mut files = vec![];
+        for file in self.lister.list(&self.bucket, &self.prefix).await? {
+            if let Some(catalog_file) =
+                parse_hive_key(file, &self.prefix, &self.partition_cols)
+            {
+                files.push(catalog_file);
+            }
+        }
+        files_table(&files, &self.partition_cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct MockLister {
+        keys: Vec<&'static str>,
+        nb_lists: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ObjectLister for MockLister {
+        async fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<SizedFile>> {
+            assert_eq!(bucket, "santas-bucket");
+            self.nb_lists.fetch_add(1, Ordering::SeqCst);
+            Ok(self
+                .keys
+                .iter()
+                .filter(|key| key.starts_with(prefix))
+                .map(|key| SizedFile {
+                    key: key.to_string(),
+                    length: if key.ends_with('/') { 0 } else { 100 },
+                    row_groups: None,
+                    row_groups_length: None,
+                    partitions: vec![],
+                })
+                .collect())
+        }
+    }
+
+    /// A catalog on the given keys, with the number of times they were listed
+    fn hive_catalog(keys: Vec<&'static str>) -> (CatalogTable, Arc<AtomicUsize>) {
+        let nb_lists = Arc::new(AtomicUsize::new(0));
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let catalog = HiveCatalogTable::with_lister(
+            schema,
+            "north-pole-1".to_owned(),
+            "santas-bucket".to_owned(),
+            "gifts".to_owned(),
+            vec![
+                Field::new("year", DataType::Int32, false),
+                Field::new("city", DataType::Utf8, false),
+            ],
+            Box::new(MockLister {
+                keys,
+                nb_lists: Arc::clone(&nb_lists),
+            }),
+        );
+        (catalog, nb_lists)
+    }
+
+    fn keys(tables: &[HBeeTableDesc]) -> Vec<String> {
+        let mut keys = tables
+            .iter()
+            .flat_map(|table| table.files())
+            .map(|file| file.key.clone())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_discover_partitions() {
+        let (catalog, nb_lists) = hive_catalog(vec![
+            "gifts/year=2020/city=Rovaniemi/part-0.parquet",
+            "gifts/year=2020/city=Rovaniemi/part-1.parquet",
+            "gifts/year=2020/city=New%20York/part-0.parquet",
+            "gifts/year=2021/city=Rovaniemi/part-0.parquet",
+            "gifts/year=2021/city=Rovaniemi/_SUCCESS",
+            "gifts/year=2021/_temporary/part-0.parquet",
+            "gifts/year=2021/",
+            "gifts/README",
+            "gifts2/year=2021/city=Rovaniemi/part-0.parquet",
+            "toys/year=2021/city=Rovaniemi/part-0.parquet",
+        ]);
+
+        let split = catalog.split(&None, &[], None).await.unwrap();
+        assert_eq!(
+            keys(&split.tables),
+            vec![
+                "gifts/year=2020/city=New%20York/part-0.parquet",
+                "gifts/year=2020/city=Rovaniemi/part-0.parquet",
+                "gifts/year=2020/city=Rovaniemi/part-1.parquet",
+                "gifts/year=2021/city=Rovaniemi/part-0.parquet",
+            ]
+        );
+
+        let split = catalog
+            .split(
+                &Some("year = 2020 AND city='New York'".to_owned()),
+                &[],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            keys(&split.tables),
+            vec!["gifts/year=2020/city=New%20York/part-0.parquet"]
+        );
+        assert_eq!(
+            split.pruned_partitions,
+            vec![
+                "year=2020/city=Rovaniemi".to_owned(),
+                "year=2021/city=Rovaniemi".to_owned(),
+            ]
+        );
+        // the prefix is listed once per query
+        assert_eq!(nb_lists.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_layout() {
+        let (catalog, _) = hive_catalog(vec![
+            "gifts/year=2020/part-0.parquet",
+            "gifts/city=Rovaniemi/year=2020/part-0.parquet",
+            "gifts/year=2020/city=Rovaniemi/part-0.parquet",
+        ]);
+        let split = catalog.split(&None, &[], None).await.unwrap();
+        assert_eq!(
+            keys(&split.tables),
+            vec!["gifts/year=2020/city=Rovaniemi/part-0.parquet"],
+            "Keys with missing or misordered partition directories should be skipped"
+        );
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("New%20York"), "New York");
+        assert_eq!(unescape("a%3Db%2Fc"), "a=b/c");
+        assert_eq!(unescape("100%"), "100%");
+        assert_eq!(unescape("%zz"), "%zz");
+    }
+}
@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use super::hive_catalog::parse_hive_key;
+use super::parquet_footer::{self, FooterCache};
+use super::static_catalog::files_table;
+use super::{CatalogTable, SplittableTable};
+use crate::clients::local_fs::{self, FsLister};
+use crate::clients::s3::ObjectLister;
+use crate::clients::CachedFile;
+use crate::datasource::{HBeeTableDesc, LocalParquetTable};
+use crate::error::Result;
+use crate::models::SizedFile;
+use arrow::datatypes::*;
+use async_trait::async_trait;
+use datafusion::datasource::TableProvider;
+
+/// A catalog table that lists the parquet files of a local directory each time it is queried.
+/// The files are expected to be organized in Hive style `col=value/` directories,
+/// like for the `HiveCatalogTable`. The hbees should have access to the same directory,
+/// which makes it possible to run queries without any cloud resource.
+pub struct LocalCatalogTable {
+    schema: SchemaRef,
+    dir: String,
+    partition_cols: Vec<Field>,
+    footer_cache: FooterCache,
+}
+
+impl LocalCatalogTable {
+    pub fn new(
+        schema: SchemaRef,
+        dir: String,
+        partition_cols: Vec<Field>,
+    ) -> CatalogTable {
+        CatalogTable::new(Box::new(Self {
+            schema,
+            dir,
+            partition_cols,
+            footer_cache: FooterCache::default(),
+        }))
+    }
+
+    /// The files of the directory, read through the footer cache of the catalog
+    async fn cached_files(&self, files: &[SizedFile]) -> Vec<CachedFile> {
+        let cache = self.footer_cache.get().await;
+        files
+            .iter()
+            .map(|file| {
+                let (dler_id, dler_creator) = local_fs::downloader_creator();
+                CachedFile::new(
+                    local_fs::file_id(&self.dir, &file.key),
+                    file.length,
+                    Arc::clone(&cache),
+                    dler_id,
+                    dler_creator,
+                )
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SplittableTable for LocalCatalogTable {
+    fn split(&self, file_groups: Vec<Vec<SizedFile>>) -> Vec<HBeeTableDesc> {
+        file_groups
+            .into_iter()
+            .map(|files| {
+                LocalParquetTable::new(self.dir.clone(), files, Arc::clone(&self.schema))
+            })
+            .collect()
+    }
+    /// Read the footers of the files to get the size of their row groups
+    async fn row_group_sizes(&self, files: &[SizedFile]) -> Result<Vec<Vec<u64>>> {
+        let cached_files = self.cached_files(files).await;
+        parquet_footer::all_row_group_sizes(cached_files).await
+    }
+    async fn file_schemas(&self, files: &[SizedFile]) -> Result<Vec<Schema>> {
+        let cached_files = self.cached_files(files).await;
+        parquet_footer::all_arrow_schemas(cached_files).await
+    }
+    fn partition_columns(&self) -> &[Field] {
+        &self.partition_cols
+    }
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+    fn set_schema(&mut self, schema: SchemaRef) {
+        self.schema = schema;
+    }
+    /// List the files of the directory, so that new files are picked up by the next query
+    async fn file_table(&self) -> Result<Box<dyn TableProvider + Send + Sync>> {
+        let listed_files = FsLister {}.list(&self.dir, "").await?;
+        let mut files = vec![];
+        for file in listed_files {
+            if let Some(catalog_file) = parse_hive_key(file, "", &self.partition_cols) {
+                files.push(catalog_file);
+            }
+        }
+        files_table(&files, &self.partition_cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::BuzzError;
+    use crate::models::query::{BuzzStep, BuzzStepType};
+    use crate::models::{HBeePlanBytes, HCombAddress};
+    use crate::services::fuse::QueryPlanner;
+    use crate::services::hbee::{Collector, HBeeService};
+    use arrow::array::*;
+    use arrow::record_batch::RecordBatch;
+    use arrow_parquet::arrow::ArrowWriter;
+    use async_trait::async_trait;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_local_partitions() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "buzz-local-catalog-{}",
+            chrono::Utc::now().timestamp_nanos()
+        ));
+        for month in &["month=2020-01", "month=2020-02"] {
+            fs::create_dir_all(dir.join(month))?;
+            fs::write(
+                dir.join(month).join("part-0.parquet"),
+                b"not really parquet",
+            )?;
+        }
+        fs::write(dir.join("month=2020-02").join("_SUCCESS"), b"")?;
+        let dir_str = dir.to_string_lossy().into_owned();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let catalog = LocalCatalogTable::new(
+            schema,
+            dir_str.clone(),
+            vec![Field::new("month", DataType::Utf8, false)],
+        );
+        let split = catalog
+            .split(&Some("month='2020-02'".to_owned()), &[], None)
+            .await?;
+
+        assert_eq!(split.tables.len(), 1);
+        assert_eq!(split.tables[0].location(), format!("file://{}", dir_str));
+        let files = split.tables[0].files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].key, "month=2020-02/part-0.parquet");
+        assert_eq!(files[0].partitions, vec!["2020-02".to_owned()]);
+        assert_eq!(split.tables[0].schema().field(1).name(), "month");
+        assert_eq!(split.pruned_partitions, vec!["month=2020-01".to_owned()]);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_infer_schema() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "buzz-local-schema-{}",
+            chrono::Utc::now().timestamp_nanos()
+        ));
+        let int_column: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        let int_batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)])),
+            vec![int_column],
+        )?;
+        write_parquet(&dir.join("month=2020-01"), &int_batch)?;
+        write_parquet(&dir.join("month=2020-02"), &int_batch)?;
+        let dir_str = dir.to_string_lossy().into_owned();
+        let partition_cols = vec![Field::new("month", DataType::Utf8, false)];
+
+        let catalog = LocalCatalogTable::new(
+            Arc::new(Schema::empty()),
+            dir_str.clone(),
+            partition_cols.clone(),
+        )
+        .with_inferred_schema(10)
+        .await?;
+        let schema = catalog.schema();
+        assert_eq!(schema.fields().len(), 2);
+        assert_eq!(schema.field(0), &Field::new("a", DataType::Int64, false));
+        assert_eq!(schema.field(1).name(), "month");
+
+        let wrong_schema =
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let catalog =
+            LocalCatalogTable::new(wrong_schema, dir_str.clone(), partition_cols.clone());
+        let err = catalog
+            .validate_schema(10)
+            .await
+            .err()
+            .expect("The declared type does not match the files");
+        assert!(matches!(err, BuzzError::Plan(_)));
+
+        // a file with a narrower type and an added column is merged into the schema
+        let evolved_batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("a", DataType::Int32, false),
+                Field::new("b", DataType::Utf8, false),
+            ])),
+            vec![
+                Arc::new(Int32Array::from(vec![4])),
+                Arc::new(StringArray::from(vec!["x"])),
+            ],
+        )?;
+        write_parquet(&dir.join("month=2020-03"), &evolved_batch)?;
+        let catalog = LocalCatalogTable::new(
+            Arc::new(Schema::empty()),
+            dir_str.clone(),
+            partition_cols.clone(),
+        );
+        let schema = catalog.infer_schema(10).await?;
+        assert_eq!(schema.field(0), &Field::new("a", DataType::Int64, false));
+        assert_eq!(schema.field(1), &Field::new("b", DataType::Utf8, true));
+
+        let conflicting_batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Utf8, false)])),
+            vec![Arc::new(StringArray::from(vec!["x"]))],
+        )?;
+        write_parquet(&dir.join("month=2020-04"), &conflicting_batch)?;
+        let err = catalog
+            .infer_schema(10)
+            .await
+            .err()
+            .expect("Files with conflicting types should be reported");
+        assert!(matches!(err, BuzzError::Plan(_)));
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    /// Plan a query on a local catalog, then run its hbees on the same directory
+    #[tokio::test(threaded_scheduler)]
+    async fn test_local_query() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "buzz-local-query-{}",
+            chrono::Utc::now().timestamp_nanos()
+        ));
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        for (month, values) in &[("2020-01", vec![1, 2, 3]), ("2020-02", vec![4, 5])] {
+            let column: ArrayRef = Arc::new(Int64Array::from(values.clone()));
+            let batch = RecordBatch::try_new(Arc::clone(&schema), vec![column])?;
+            write_parquet(&dir.join(format!("month={}", month)), &batch)?;
+        }
+        let catalog = LocalCatalogTable::new(
+            schema,
+            dir.to_string_lossy().into_owned(),
+            vec![Field::new("month", DataType::Utf8, false)],
+        );
+
+        let mut planner = QueryPlanner::new();
+        planner.add_catalog("local", catalog);
+        let steps = vec![
+            BuzzStep {
+                sql: "SELECT month, SUM(a) AS total FROM local WHERE month = '2020-02' GROUP BY month"
+                    .to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT * FROM mapper".to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+        let plan = planner.plan("local_query".to_owned(), steps, 1).await?;
+        let hbees = &plan.stages[0].zones[0].hbee;
+        assert_eq!(hbees.len(), 1);
+
+        // the plan goes through the same serialization as the hbee invocations
+        let results = Arc::new(Mutex::new(vec![]));
+        let collector = MemoryCollector {
+            results: Arc::clone(&results),
+        };
+        let mut hbee_service = HBeeService::new(Box::new(collector)).await;
+        for hbee in hbees {
+            let (table, sql, source, broadcasts, shuffle_keys) = HBeePlanBytes::try_new(
+                &hbee.table,
+                hbee.sql.clone(),
+                hbee.source.clone(),
+                &hbee.broadcasts,
+                &hbee.shuffle_keys,
+            )?
+            .parse()?;
+            hbee_service
+                .execute_query(
+                    hbee.query_id.clone(),
+                    table,
+                    sql,
+                    source,
+                    broadcasts,
+                    shuffle_keys,
+                    vec!["local_hcomb".to_owned()],
+                )
+                .await?;
+        }
+
+        let results = results.lock().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].num_rows(), 1);
+        let month = results[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(month.value(0), "2020-02");
+        let total = results[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(total.value(0), 9);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    /// Keeps the results of the hbees instead of sending them to an hcomb
+    struct MemoryCollector {
+        results: Arc<Mutex<Vec<RecordBatch>>>,
+    }
+
+    #[async_trait]
+    impl Collector for MemoryCollector {
+        async fn send_back(
+            &self,
+            _query_id: String,
+            data: Result<Vec<RecordBatch>>,
+            _address: HCombAddress,
+        ) -> Result<()> {
+            self.results.lock().unwrap().extend(data?);
+            Ok(())
+        }
+    }
+
+    fn write_parquet(dir: &Path, rec_batch: &RecordBatch) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let file = File::create(dir.join("part-0.parquet"))?;
+        let mut writer = ArrowWriter::try_new(file, rec_batch.schema(), None)?;
+        writer.write(rec_batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
@@ -1,71 +1,294 @@
 use std::any::Any;
+use std::collections::{BTreeSet, HashSet};
 use std::sync::Arc;
 
 use crate::datasource::HBeeTableDesc;
 use crate::error::{BuzzError, Result};
+use crate::execution_plan::can_read_as;
 use crate::models::SizedFile;
+use crate::not_impl_err;
+use crate::plan_utils;
 use arrow::array::*;
 use arrow::datatypes::*;
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::array_value_to_string;
+use async_trait::async_trait;
 use datafusion::datasource::datasource::Statistics;
-use datafusion::datasource::TableProvider;
+use datafusion::datasource::{MemTable, TableProvider};
 use datafusion::error::{DataFusionError, Result as DataFusionResult};
 use datafusion::execution::context::ExecutionContext;
 use datafusion::logical_plan::Expr;
+use datafusion::optimizer::utils;
 use datafusion::physical_plan::ExecutionPlan;
 
 /// A specific type of TableProvider that cannot be converted to a physical plan
 /// but can be splitted to be distributed to hbees
+#[async_trait]
 pub trait SplittableTable {
-    fn split(&self, files: Vec<SizedFile>) -> Vec<HBeeTableDesc>;
-    /// Get the names of the partitioning columns, in order of evaluation.
-    fn partition_columns(&self) -> &[String];
+    /// Create one HBee table for each group of files
+    fn split(&self, file_groups: Vec<Vec<SizedFile>>) -> Vec<HBeeTableDesc>;
+    /// Get the size of each row group of the given files, so that they can be
+    /// distributed among several hbees. An empty vec means that the file cannot be split.
+    async fn row_group_sizes(&self, files: &[SizedFile]) -> Result<Vec<Vec<u64>>> {
+        Ok(files.iter().map(|_| vec![]).collect())
+    }
+    /// Read the Arrow schema of each of the given files from its footer
+    async fn file_schemas(&self, _files: &[SizedFile]) -> Result<Vec<Schema>> {
+        Err(not_impl_err!(
+            "This catalog cannot read the schema of its files"
+        ))
+    }
+    /// Get the partitioning columns, in order of evaluation.
+    /// Their values are parsed from the catalog entries according to the type of the field.
+    fn partition_columns(&self) -> &[Field];
+    /// The schema of the data files, partition columns excluded
     fn schema(&self) -> SchemaRef;
+    /// Replace the schema of the data files, e.g with the one inferred from their footers
+    fn set_schema(&mut self, schema: SchemaRef);
     fn statistics(&self) -> Statistics {
         Statistics::default()
     }
-    fn file_table(&self) -> Box<dyn TableProvider + Send + Sync>;
+    /// The table listing the files (`key` and `length` columns) and their partition values
+    async fn file_table(&self) -> Result<Box<dyn TableProvider + Send + Sync>>;
+}
+
+/// The result of the exploration of the catalog for a given query
+pub struct CatalogSplit {
+    /// The tables to be processed by each hbee
+    pub tables: Vec<HBeeTableDesc>,
+    /// The partitions excluded by the filters, formatted as `col1=val1/col2=val2`
+    pub pruned_partitions: Vec<String>,
 }
 
+/// The default amount of data that each hbee should read
+pub const DEFAULT_BYTES_PER_HBEE: u64 = 256 * 1024 * 1024;
+
+/// The default number of footers that are read to infer or validate the schema of a catalog
+pub const DEFAULT_SCHEMA_SAMPLE_FILES: usize = 10;
+
 /// A generic catalog table that wraps splittable tables
 pub struct CatalogTable {
     source_table: Box<dyn SplittableTable + Send + Sync>,
+    bytes_per_hbee: u64,
 }
 
 impl CatalogTable {
     pub fn new(source_table: Box<dyn SplittableTable + Send + Sync>) -> Self {
-        Self { source_table }
+        Self {
+            source_table,
+            bytes_per_hbee: DEFAULT_BYTES_PER_HBEE,
+        }
+    }
+
+    /// Customize the amount of data that each hbee should read from this catalog
+    pub fn with_bytes_per_hbee(mut self, bytes_per_hbee: u64) -> Self {
+        self.bytes_per_hbee = bytes_per_hbee;
+        self
+    }
+
+    /// Infer the schema of the data files from the footers of up to `max_files` of them,
+    /// evenly spread over the catalog. The schemas of the files are merged the way the
+    /// hbees reconcile them: the columns are matched by name, a column that is not in all
+    /// the files is nullable and compatible types are widened (see `ParquetExec`).
+    /// Fails if the sampled files have conflicting types for the same column.
+    pub async fn infer_schema(&self, max_files: usize) -> Result<SchemaRef> {
+        let files = self.sample_files(max_files).await?;
+        let schemas = self.source_table.file_schemas(&files).await?;
+        let mut sampled = files.iter().zip(schemas);
+        let (first_file, first_schema) = match sampled.next() {
+            Some(first) => first,
+            None => return Err(BuzzError::Plan("No schema was read".to_owned())),
+        };
+        let mut fields = first_schema.fields().clone();
+        for (file, schema) in sampled {
+            fields = merge_fields(&fields, schema.fields()).map_err(|conflict| {
+                BuzzError::Plan(format!(
+                    "Conflicting schemas in the catalog, the file {} has the {} (sampled from {})",
+                    file.key, conflict, first_file.key
+                ))
+            })?;
+        }
+        let partition_cols = self.source_table.partition_columns();
+        if let Some(col) = partition_cols
+            .iter()
+            .find(|col| fields.iter().any(|field| field.name() == col.name()))
+        {
+            return Err(BuzzError::Plan(format!(
+                "The partition column {} is also a column of the files",
+                col.name(),
+            )));
+        }
+        Ok(Arc::new(Schema::new(fields)))
+    }
+
+    /// Replace the schema of the catalog with the one inferred from the footers of its files
+    /// (see `infer_schema`)
+    pub async fn with_inferred_schema(mut self, max_files: usize) -> Result<Self> {
+        let schema = self.infer_schema(max_files).await?;
+        self.source_table.set_schema(schema);
+        Ok(self)
+    }
+
+    /// Check that the columns of up to `max_files` files of the catalog can be read with
+    /// its schema, so that a wrong type is reported before it makes the hbees fail.
+    /// The columns that are missing in the files or that are not in the schema are fine.
+    pub async fn validate_schema(&self, max_files: usize) -> Result<()> {
+        let files = self.sample_files(max_files).await?;
+        let schemas = self.source_table.file_schemas(&files).await?;
+        let expected_schema = self.source_table.schema();
+        for (file, schema) in files.iter().zip(schemas) {
+            for field in expected_schema.fields() {
+                if let Ok(file_field) = schema.field_with_name(field.name()) {
+                    if !can_read_as(file_field.data_type(), field.data_type()) {
+                        return Err(BuzzError::Plan(format!(
+                            "The column {} of the catalog has the type {:?} but it is {:?} in the file {}",
+                            field.name(),
+                            field.data_type(),
+                            file_field.data_type(),
+                            file.key
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Up to `max_files` files of the catalog, evenly spread over its listing
+    async fn sample_files(&self, max_files: usize) -> Result<Vec<SizedFile>> {
+        let entries = self.list_entries().await?;
+        let files = self.filter_catalog(&entries, &None, &[]).await?;
+        if files.is_empty() {
+            return Err(BuzzError::Plan(
+                "The catalog has no file to read the schema from".to_owned(),
+            ));
+        }
+        let nb_samples = max_files.max(1).min(files.len());
+        Ok((0..nb_samples)
+            .map(|i| files[i * files.len() / nb_samples].clone())
+            .collect())
     }
 
     /// Explore the catalog with the given `partition_filter` and generate the tables to be processed by each hbee.
+    /// The `query_filters` are the conjuncts of the query predicate, those that only refer to
+    /// partition columns are also used to prune the catalog.
+    /// The files are packed so that each hbee reads up to `bytes_per_hbee` (or the catalog default),
+    /// files that are larger than that are split by row groups.
     pub async fn split(
         &self,
         partition_filters: &Option<String>,
-    ) -> Result<Vec<HBeeTableDesc>> {
-        let files = self.filter_catalog(partition_filters).await?;
-        Ok(self.source_table.split(files))
+        query_filters: &[Expr],
+        bytes_per_hbee: Option<u64>,
+    ) -> Result<CatalogSplit> {
+        let pruning_filters = self.pruning_filters(query_filters);
+        // the catalog is listed once, then queried in memory
+        let entries = self.list_entries().await?;
+        let files = self
+            .filter_catalog(&entries, partition_filters, &pruning_filters)
+            .await?;
+        let pruned_partitions =
+            if partition_filters.is_none() && pruning_filters.is_empty() {
+                vec![]
+            } else {
+                let all_files = self.filter_catalog(&entries, &None, &[]).await?;
+                self.pruned_partitions(&all_files, &files)
+            };
+        let bytes_per_hbee = bytes_per_hbee.unwrap_or(self.bytes_per_hbee);
+        let (large_files, small_files): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|file| file.length > bytes_per_hbee);
+
+        let mut file_groups = pack_files(small_files, bytes_per_hbee);
+        if !large_files.is_empty() {
+            let row_group_sizes = self.source_table.row_group_sizes(&large_files).await?;
+            for (file, sizes) in large_files.into_iter().zip(row_group_sizes) {
+                file_groups.extend(
+                    split_row_groups(file, &sizes, bytes_per_hbee)
+                        .into_iter()
+                        .map(|part| vec![part]),
+                );
+            }
+        }
+        let partition_cols = self.source_table.partition_columns();
+        let tables = self
+            .source_table
+            .split(file_groups)
+            .into_iter()
+            .map(|table| table.with_partition_cols(partition_cols.to_vec()))
+            .collect();
+        Ok(CatalogSplit {
+            tables,
+            pruned_partitions,
+        })
+    }
+
+    /// The partitions of `all_files` that have no file in `selected_files`,
+    /// formatted as `col1=val1/col2=val2`
+    fn pruned_partitions(
+        &self,
+        all_files: &[SizedFile],
+        selected_files: &[SizedFile],
+    ) -> Vec<String> {
+        let partition_cols = self.source_table.partition_columns();
+        if partition_cols.is_empty() {
+            return vec![];
+        }
+        let partition_names = |files: &[SizedFile]| {
+            files
+                .iter()
+                .map(|file| {
+                    partition_cols
+                        .iter()
+                        .zip(&file.partitions)
+                        .map(|(col, value)| format!("{}={}", col.name(), value))
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+                .collect::<BTreeSet<_>>()
+        };
+        partition_names(all_files)
+            .difference(&partition_names(selected_files))
+            .cloned()
+            .collect()
+    }
+
+    /// Selects the expressions that can be evaluated on the partition columns only
+    fn pruning_filters(&self, query_filters: &[Expr]) -> Vec<Expr> {
+        let partition_cols = self.source_table.partition_columns();
+        query_filters
+            .iter()
+            .filter(|expr| {
+                let mut columns = HashSet::new();
+                utils::expr_to_column_names(expr, &mut columns).is_ok()
+                    && !columns.is_empty()
+                    && columns
+                        .iter()
+                        .all(|col| partition_cols.iter().any(|field| field.name() == col))
+            })
+            .cloned()
+            .collect()
     }
 
-    /// Applies the given filters
+    /// Applies the given filters to the entries of the catalog,
+    /// the selected files carry their partition values
     async fn filter_catalog(
         &self,
+        entries: &[RecordBatch],
         partition_filters: &Option<String>,
+        expr_filters: &[Expr],
     ) -> Result<Vec<SizedFile>> {
-        let phys_plan;
-        {
-            let mut context = ExecutionContext::new();
-            context.register_table("catalog", self.source_table.file_table());
-            let sql_pattern = "SELECT * FROM catalog";
-            let sql_statement = match partition_filters {
-                Some(sql_where) => format!("{} WHERE {}", sql_pattern, sql_where),
-                None => sql_pattern.to_owned(),
-            };
-            let df = context.sql(&sql_statement)?;
-            phys_plan = context.create_physical_plan(&df.to_logical_plan())?;
-        }
+        let partition_cols = self.source_table.partition_columns();
+        let file_rec = if partition_filters.is_none() && expr_filters.is_empty() {
+            entries.to_vec()
+        } else if entries.is_empty() {
+            vec![]
+        } else {
+            let entry_table =
+                MemTable::try_new(entries[0].schema(), vec![entries.to_vec()])?;
+            query_catalog(Box::new(entry_table), partition_filters, expr_filters).await?
+        };
 
-        let file_rec = datafusion::physical_plan::collect(phys_plan).await?;
-
-        file_rec
+        let files = file_rec
             .iter()
             .map(|rec_batch| {
                 let key_array = rec_batch
@@ -82,20 +305,164 @@ impl CatalogTable {
                     .ok_or(BuzzError::Execution(format!(
                         "Invalid type for catalog lengths"
                     )))?;
-                let sized_files = (0..rec_batch.num_rows()).map(move |i| SizedFile {
-                    key: key_array.value(i).to_owned(),
-                    length: length_array.value(i),
-                });
+                let partition_arrays = partition_cols
+                    .iter()
+                    .map(|col| {
+                        Ok(rec_batch.column(rec_batch.schema().index_of(col.name())?))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let sized_files = (0..rec_batch.num_rows())
+                    .map(|i| {
+                        Ok(SizedFile {
+                            key: key_array.value(i).to_owned(),
+                            length: length_array.value(i),
+                            row_groups: None,
+                            row_groups_length: None,
+                            partitions: partition_arrays
+                                .iter()
+                                .map(|array| Ok(array_value_to_string(array, i)?))
+                                .collect::<Result<Vec<_>>>()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
                 Ok(sized_files)
             })
-            .flat_map(|rec_iter_res| match rec_iter_res {
-                Ok(rec_iter) => rec_iter.map(|rec| Ok(rec)).collect(),
-                Err(er) => vec![Err(er)],
-            })
-            .collect::<Result<Vec<_>>>()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(files.into_iter().flatten().collect())
+    }
+
+    /// List all the entries of the catalog (one row per file with its `key`,
+    /// its `length` and its partition values)
+    async fn list_entries(&self) -> Result<Vec<RecordBatch>> {
+        let file_table = self.source_table.file_table().await?;
+        query_catalog(file_table, &None, &[]).await
     }
 }
 
+/// Get the entries of the catalog table that match the filters
+async fn query_catalog(
+    file_table: Box<dyn TableProvider + Send + Sync>,
+    partition_filters: &Option<String>,
+    expr_filters: &[Expr],
+) -> Result<Vec<RecordBatch>> {
+    let phys_plan;
+    {
+        let mut context = ExecutionContext::new();
+        context.register_table("catalog", file_table);
+        let sql_pattern = "SELECT * FROM catalog";
+        let sql_statement = match partition_filters {
+            Some(sql_where) => format!("{} WHERE {}", sql_pattern, sql_where),
+            None => sql_pattern.to_owned(),
+        };
+        let mut df = context.sql(&sql_statement)?;
+        if let Some(filter) = plan_utils::merge_expr(expr_filters) {
+            df = df.filter(filter)?;
+        }
+        phys_plan = context.create_physical_plan(&df.to_logical_plan())?;
+    }
+
+    Ok(datafusion::physical_plan::collect(phys_plan).await?)
+}
+
+/// Merge the fields of a file into the fields inferred from the previous files.
+/// Returns the description of the conflict if a column has incompatible types.
+fn merge_fields(
+    merged: &[Field],
+    file_fields: &[Field],
+) -> std::result::Result<Vec<Field>, String> {
+    let mut result = vec![];
+    for field in merged {
+        match file_fields
+            .iter()
+            .find(|other| other.name() == field.name())
+        {
+            None => {
+                result.push(Field::new(field.name(), field.data_type().clone(), true))
+            }
+            Some(other) => {
+                let data_type = if can_read_as(other.data_type(), field.data_type()) {
+                    field.data_type()
+                } else if can_read_as(field.data_type(), other.data_type()) {
+                    other.data_type()
+                } else {
+                    return Err(format!(
+                        "column {} of type {:?} instead of {:?}",
+                        field.name(),
+                        other.data_type(),
+                        field.data_type()
+                    ));
+                };
+                result.push(Field::new(
+                    field.name(),
+                    data_type.clone(),
+                    field.is_nullable() || other.is_nullable(),
+                ));
+            }
+        }
+    }
+    for other in file_fields {
+        if !merged.iter().any(|field| field.name() == other.name()) {
+            result.push(Field::new(other.name(), other.data_type().clone(), true));
+        }
+    }
+    Ok(result)
+}
+
+/// Groups the files so that the total size of each group does not exceed `bytes_per_group`.
+/// Uses the first fit decreasing heuristic, files bigger than the budget get their own group.
+pub fn pack_files(
+    mut files: Vec<SizedFile>,
+    bytes_per_group: u64,
+) -> Vec<Vec<SizedFile>> {
+    // stable sort to keep the catalog order for files with the same size
+    files.sort_by(|a, b| b.length.cmp(&a.length));
+    let mut groups: Vec<(u64, Vec<SizedFile>)> = vec![];
+    for file in files {
+        let fitting_group = groups
+            .iter_mut()
+            .find(|(group_bytes, _)| group_bytes + file.length <= bytes_per_group);
+        match fitting_group {
+            Some((group_bytes, group)) => {
+                *group_bytes += file.length;
+                group.push(file);
+            }
+            None => groups.push((file.length, vec![file])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Splits the file into parts made of consecutive row groups, with up to `bytes_per_part` each.
+/// If the row group sizes are unknown, the file is kept whole.
+pub fn split_row_groups(
+    file: SizedFile,
+    row_group_sizes: &[u64],
+    bytes_per_part: u64,
+) -> Vec<SizedFile> {
+    if row_group_sizes.len() <= 1 {
+        return vec![file];
+    }
+    // the row groups of each part with their total size
+    let mut parts: Vec<(Vec<usize>, u64)> = vec![];
+    for (i, size) in row_group_sizes.iter().enumerate() {
+        match parts.last_mut() {
+            Some((part, part_bytes)) if *part_bytes + size <= bytes_per_part => {
+                *part_bytes += size;
+                part.push(i);
+            }
+            _ => parts.push((vec![i], *size)),
+        }
+    }
+    parts
+        .into_iter()
+        .map(|(row_groups, part_bytes)| SizedFile {
+            row_groups: Some(row_groups),
+            row_groups_length: Some(part_bytes),
+            ..file.clone()
+        })
+        .collect()
+}
+
 impl TableProvider for CatalogTable {
     fn as_any(&self) -> &dyn Any {
         self
@@ -103,9 +470,7 @@ impl TableProvider for CatalogTable {
 
     fn schema(&self) -> SchemaRef {
         let mut fields = self.source_table.schema().fields().clone();
-        for partition_col in self.source_table.partition_columns() {
-            fields.push(Field::new(partition_col, DataType::Utf8, false))
-        }
+        fields.extend_from_slice(self.source_table.partition_columns());
         Arc::new(Schema::new_with_metadata(
             fields,
             self.source_table.schema().metadata().clone(),
@@ -130,6 +495,11 @@ impl TableProvider for CatalogTable {
 
 //// Implems ////
 
+pub mod catalog_config;
+pub mod hive_catalog;
+pub mod local_catalog;
+mod parquet_footer;
+pub(crate) mod partition_values;
 pub mod static_catalog;
 pub(crate) mod test_catalog;
 
@@ -137,6 +507,7 @@ pub(crate) mod test_catalog;
 mod tests {
     use super::*;
     use crate::datasource::CatalogTable;
+    use datafusion::logical_plan::{col, lit};
 
     #[tokio::test]
     async fn test_filter_catalog() {
@@ -145,7 +516,11 @@ mod tests {
             test_catalog::MockSplittableTable::new(nb_split, 0),
         ));
 
-        let result = catalog_table.filter_catalog(&None).await.unwrap();
+        let entries = catalog_table.list_entries().await.unwrap();
+        let result = catalog_table
+            .filter_catalog(&entries, &None, &[])
+            .await
+            .unwrap();
         assert_eq!(result.len(), 5);
     }
 
@@ -156,14 +531,158 @@ mod tests {
             test_catalog::MockSplittableTable::new(nb_split, 1),
         ));
 
-        let result = catalog_table.filter_catalog(&None).await.unwrap();
+        let entries = catalog_table.list_entries().await.unwrap();
+        let result = catalog_table
+            .filter_catalog(&entries, &None, &[])
+            .await
+            .unwrap();
         assert_eq!(result.len(), 5);
 
         let result = catalog_table
-            .filter_catalog(&Some("part_key_1='part_value_002'".to_owned()))
+            .filter_catalog(
+                &entries,
+                &Some("part_key_1='part_value_002'".to_owned()),
+                &[],
+            )
             .await
             .unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].key, "file_2");
     }
+
+    #[tokio::test]
+    async fn test_split_with_query_filters() {
+        let nb_split = 5;
+        let catalog_table = CatalogTable::new(Box::new(
+            test_catalog::MockSplittableTable::new(nb_split, 1),
+        ));
+
+        let partition_filter = col("part_key_1").lt_eq(lit("part_value_003"));
+        let data_filter = col("data_col").eq(lit(0));
+        let mixed_filter = col("part_key_1").eq(col("data_col"));
+
+        let pruning_filters = catalog_table.pruning_filters(&[
+            partition_filter.clone(),
+            data_filter,
+            mixed_filter,
+        ]);
+        assert_eq!(
+            format!("{:?}", pruning_filters),
+            format!("{:?}", vec![partition_filter.clone()])
+        );
+
+        let result = catalog_table
+            .split(&None, &[partition_filter], None)
+            .await
+            .unwrap();
+        assert_eq!(result.tables.len(), 3);
+        assert_eq!(
+            result.pruned_partitions,
+            vec![
+                "part_key_1=part_value_004".to_owned(),
+                "part_key_1=part_value_005".to_owned()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_split_with_bytes_per_hbee() {
+        let nb_split = 5;
+        // the mock files are 999999999 bytes long
+        let catalog_table = CatalogTable::new(Box::new(
+            test_catalog::MockSplittableTable::new(nb_split, 0),
+        ))
+        .with_bytes_per_hbee(2_000_000_000);
+
+        let result = catalog_table.split(&None, &[], None).await.unwrap();
+        assert_eq!(result.tables.len(), 3);
+
+        let result = catalog_table
+            .split(&None, &[], Some(5_000_000_000))
+            .await
+            .unwrap();
+        assert_eq!(result.tables.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_split_large_files() {
+        let nb_split = 5;
+        // the mock files are 999999999 bytes long with 4 row groups
+        let catalog_table = CatalogTable::new(Box::new(
+            test_catalog::MockSplittableTable::new(nb_split, 0).with_row_groups(4),
+        ))
+        .with_bytes_per_hbee(500_000_000);
+
+        let result = catalog_table.split(&None, &[], None).await.unwrap();
+        assert_eq!(result.tables.len(), 2 * nb_split);
+        let files = result.tables[1].files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].key, "file_1");
+        assert_eq!(files[0].length, 999999999);
+        assert_eq!(files[0].row_groups, Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn test_split_row_groups() {
+        let file = SizedFile {
+            key: "file".to_owned(),
+            length: 1000,
+            row_groups: None,
+            row_groups_length: None,
+            partitions: vec![],
+        };
+
+        let parts = split_row_groups(file.clone(), &[300, 300, 500, 100, 100], 600);
+        let row_groups = parts
+            .iter()
+            .map(|part| part.row_groups.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(row_groups, vec![vec![0, 1], vec![2, 3], vec![4]]);
+        assert!(parts.iter().all(|part| part.length == 1000));
+        let lengths = parts
+            .iter()
+            .map(|part| part.read_length())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, vec![600, 600, 100]);
+
+        // a single row group or an unknown layout cannot be split
+        assert_eq!(split_row_groups(file.clone(), &[1000], 600).len(), 1);
+        let parts = split_row_groups(file, &[], 600);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].row_groups, None);
+    }
+
+    #[test]
+    fn test_pack_files() {
+        let file = |key: &str, length: u64| SizedFile {
+            key: key.to_owned(),
+            length,
+            row_groups: None,
+            row_groups_length: None,
+            partitions: vec![],
+        };
+        let files = vec![
+            file("small_1", 10),
+            file("big", 120),
+            file("medium_1", 60),
+            file("small_2", 30),
+            file("medium_2", 70),
+        ];
+
+        let groups = pack_files(files, 100);
+        let group_keys = groups
+            .iter()
+            .map(|group| group.iter().map(|f| f.key.as_str()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            group_keys,
+            vec![
+                vec!["big"],
+                vec!["medium_2", "small_2"],
+                vec!["medium_1", "small_1"],
+            ]
+        );
+
+        assert_eq!(pack_files(vec![], 100).len(), 0);
+    }
 }
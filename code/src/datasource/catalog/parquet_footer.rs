@@ -0,0 +1,78 @@
+//! Helpers to read the metadata of Parquet files without downloading them completely
+
+use std::sync::Arc;
+
+use crate::clients::{CachedFile, RangeCache};
+use crate::error::Result;
+use crate::execution_plan::ParquetExec;
+use crate::internal_err;
+use arrow::datatypes::Schema;
+use arrow_parquet::arrow::{ArrowReader, ParquetFileArrowReader};
+use arrow_parquet::file::reader::{FileReader, SerializedFileReader};
+use futures::{StreamExt, TryStreamExt};
+use tokio::sync::Mutex;
+
+/// The maximum number of footers that are downloaded concurrently while planning
+const MAX_CONCURRENT_FOOTERS: usize = 10;
+
+/// A range cache created on first use and shared by all the footer reads of a catalog,
+/// so that the footers already read (e.g. for the schema) are not downloaded again
+#[derive(Default)]
+pub struct FooterCache {
+    cache: Mutex<Option<Arc<RangeCache>>>,
+}
+
+impl FooterCache {
+    pub async fn get(&self) -> Arc<RangeCache> {
+        let mut cache = self.cache.lock().await;
+        if cache.is_none() {
+            *cache = Some(Arc::new(RangeCache::new().await));
+        }
+        Arc::clone(cache.as_ref().unwrap())
+    }
+}
+
+/// Download the end of the file and parse its footer
+pub async fn read_footer(
+    file: CachedFile,
+) -> Result<Arc<SerializedFileReader<CachedFile>>> {
+    ParquetExec::download_footer(file.clone());
+    // Reading the footer is blocking so it should be started on a specific thread
+    let file_reader =
+        tokio::task::spawn_blocking(move || SerializedFileReader::new(file))
+            .await
+            .map_err(|e| internal_err!("Footer reading task failed: {}", e))??;
+    Ok(Arc::new(file_reader))
+}
+
+/// The compressed size of each row group of the file
+pub async fn row_group_sizes(file: CachedFile) -> Result<Vec<u64>> {
+    let file_reader = read_footer(file).await?;
+    let metadata = file_reader.metadata();
+    Ok((0..metadata.num_row_groups())
+        .map(|i| metadata.row_group(i).compressed_size() as u64)
+        .collect())
+}
+
+/// The Arrow schema of the file, converted from its Parquet schema
+pub async fn arrow_schema(file: CachedFile) -> Result<Schema> {
+    let file_reader = read_footer(file).await?;
+    let mut arrow_reader = ParquetFileArrowReader::new(file_reader);
+    Ok(arrow_reader.get_schema()?)
+}
+
+/// The row group sizes of each file, in order, with a bounded number of concurrent downloads
+pub async fn all_row_group_sizes(files: Vec<CachedFile>) -> Result<Vec<Vec<u64>>> {
+    futures::stream::iter(files.into_iter().map(row_group_sizes))
+        .buffered(MAX_CONCURRENT_FOOTERS)
+        .try_collect()
+        .await
+}
+
+/// The Arrow schema of each file, in order, with a bounded number of concurrent downloads
+pub async fn all_arrow_schemas(files: Vec<CachedFile>) -> Result<Vec<Schema>> {
+    futures::stream::iter(files.into_iter().map(arrow_schema))
+        .buffered(MAX_CONCURRENT_FOOTERS)
+        .try_collect()
+        .await
+}
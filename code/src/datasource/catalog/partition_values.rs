@@ -0,0 +1,174 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::error::{BuzzError, Result};
+use arrow::array::*;
+use arrow::datatypes::*;
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// Parse the values of a partition column into an array of the type of the column.
+/// Dates are expected as `YYYY-MM-DD` and timestamps as `YYYY-MM-DD[ HH:MM:SS[.f]]`
+/// (with a space or a `T` as separator).
+pub(crate) fn parse_partition_values(field: &Field, values: &[&str]) -> Result<ArrayRef> {
+    let array: ArrayRef = match field.data_type() {
+        DataType::Utf8 => Arc::new(StringArray::from(values.to_vec())),
+        DataType::Boolean => {
+            Arc::new(BooleanArray::from(parse_all::<bool>(field, values)?))
+        }
+        DataType::Int8 => Arc::new(Int8Array::from(parse_all(field, values)?)),
+        DataType::Int16 => Arc::new(Int16Array::from(parse_all(field, values)?)),
+        DataType::Int32 => Arc::new(Int32Array::from(parse_all(field, values)?)),
+        DataType::Int64 => Arc::new(Int64Array::from(parse_all(field, values)?)),
+        DataType::UInt8 => Arc::new(UInt8Array::from(parse_all(field, values)?)),
+        DataType::UInt16 => Arc::new(UInt16Array::from(parse_all(field, values)?)),
+        DataType::UInt32 => Arc::new(UInt32Array::from(parse_all(field, values)?)),
+        DataType::UInt64 => Arc::new(UInt64Array::from(parse_all(field, values)?)),
+        DataType::Float32 => Arc::new(Float32Array::from(parse_all(field, values)?)),
+        DataType::Float64 => Arc::new(Float64Array::from(parse_all(field, values)?)),
+        DataType::Date32(DateUnit::Day) => {
+            let days = values
+                .iter()
+                .map(|value| {
+                    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                        .map_err(|_| invalid_value(field, value))?;
+                    Ok((date - NaiveDate::from_ymd(1970, 1, 1)).num_days() as i32)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Arc::new(Date32Array::from(days))
+        }
+        DataType::Timestamp(unit, None) => {
+            let timestamps = values
+                .iter()
+                .map(|value| {
+                    parse_datetime(value)
+                        .and_then(|datetime| to_timestamp(&datetime, unit))
+                        .ok_or_else(|| invalid_value(field, value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            match unit {
+                TimeUnit::Second => {
+                    Arc::new(TimestampSecondArray::from_vec(timestamps, None))
+                }
+                TimeUnit::Millisecond => {
+                    Arc::new(TimestampMillisecondArray::from_vec(timestamps, None))
+                }
+                TimeUnit::Microsecond => {
+                    Arc::new(TimestampMicrosecondArray::from_vec(timestamps, None))
+                }
+                TimeUnit::Nanosecond => {
+                    Arc::new(TimestampNanosecondArray::from_vec(timestamps, None))
+                }
+            }
+        }
+        other => {
+            return Err(BuzzError::Plan(format!(
+                "Unsupported type {:?} for partition column {}",
+                other,
+                field.name()
+            )))
+        }
+    };
+    Ok(array)
+}
+
+fn parse_all<T: FromStr>(field: &Field, values: &[&str]) -> Result<Vec<T>> {
+    values
+        .iter()
+        .map(|value| value.parse::<T>().map_err(|_| invalid_value(field, value)))
+        .collect()
+}
+
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_hms(0, 0, 0))
+        })
+}
+
+/// The number of `unit` since the epoch, `None` if it does not fit in an i64
+/// (nanosecond timestamps are limited to the years 1677 to 2262)
+fn to_timestamp(datetime: &NaiveDateTime, unit: &TimeUnit) -> Option<i64> {
+    let seconds = datetime.timestamp();
+    let nanos = datetime.timestamp_subsec_nanos() as i64;
+    match unit {
+        TimeUnit::Second => Some(seconds),
+        TimeUnit::Millisecond => {
+            seconds.checked_mul(1_000)?.checked_add(nanos / 1_000_000)
+        }
+        TimeUnit::Microsecond => {
+            seconds.checked_mul(1_000_000)?.checked_add(nanos / 1_000)
+        }
+        TimeUnit::Nanosecond => seconds.checked_mul(1_000_000_000)?.checked_add(nanos),
+    }
+}
+
+fn invalid_value(field: &Field, value: &str) -> BuzzError {
+    BuzzError::Plan(format!(
+        "Invalid value {} for partition column {} of type {:?}",
+        value,
+        field.name(),
+        field.data_type()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_partitions() -> Result<()> {
+        let field = Field::new("day", DataType::Date32(DateUnit::Day), false);
+        let array = parse_partition_values(&field, &["1970-01-02", "2020-01-01"])?;
+        let array = array.as_any().downcast_ref::<Date32Array>().unwrap();
+        assert_eq!(array.value(0), 1);
+        assert_eq!(array.value(1), 18262);
+
+        let field =
+            Field::new("hour", DataType::Timestamp(TimeUnit::Second, None), false);
+        let array = parse_partition_values(
+            &field,
+            &["1970-01-01 01:00:00", "1970-01-01T00:00:10", "1970-01-02"],
+        )?;
+        let array = array
+            .as_any()
+            .downcast_ref::<TimestampSecondArray>()
+            .unwrap();
+        assert_eq!(array.value(0), 3600);
+        assert_eq!(array.value(1), 10);
+        assert_eq!(array.value(2), 86400);
+
+        // dates out of the range of nanosecond timestamps
+        let field = Field::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            false,
+        );
+        let array = parse_partition_values(&field, &["2300-01-01"])?;
+        let array = array
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert_eq!(array.value(0), 10_413_792_000_000);
+        let field =
+            Field::new("ts", DataType::Timestamp(TimeUnit::Nanosecond, None), false);
+        let err = parse_partition_values(&field, &["2300-01-01"])
+            .err()
+            .expect("Nanosecond timestamps after 2262 should be rejected");
+        assert!(matches!(err, BuzzError::Plan(_)));
+
+        let field = Field::new("year", DataType::Int32, false);
+        let array = parse_partition_values(&field, &["2020"])?;
+        let array = array.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(array.value(0), 2020);
+
+        let err = parse_partition_values(&field, &["twenty"])
+            .err()
+            .expect("Invalid integers should be rejected");
+        assert!(matches!(err, BuzzError::Plan(_)));
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 use std::sync::Arc;
 
+use super::parquet_footer::{self, FooterCache};
+use super::partition_values::parse_partition_values;
 use super::{CatalogTable, SplittableTable};
+use crate::clients::{s3, CachedFile};
 use crate::datasource::{HBeeTableDesc, S3ParquetTable};
-use crate::error::Result;
+use crate::error::{BuzzError, Result};
 use crate::models::SizedFile;
 use arrow::array::*;
 use arrow::datatypes::*;
 use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
 use datafusion::datasource::{MemTable, TableProvider};
 
 pub struct CatalogFile {
@@ -20,6 +24,9 @@ impl CatalogFile {
             sized_file: SizedFile {
                 key: key.to_owned(),
                 length,
+                row_groups: None,
+                row_groups_length: None,
+                partitions: vec![],
             },
             partitions,
         }
@@ -27,13 +34,14 @@ impl CatalogFile {
 }
 
 /// A catalog table that contains a static list of files.
-/// Only supports S3 parquet files for now and simply sends each file into a different hbee.
+/// Only supports S3 parquet files for now.
 pub struct StaticCatalogTable {
     schema: SchemaRef,
     region: String,
     bucket: String,
     files: Vec<CatalogFile>,
-    partition_cols: Vec<String>,
+    partition_cols: Vec<Field>,
+    footer_cache: FooterCache,
 }
 
 impl StaticCatalogTable {
@@ -41,7 +49,7 @@ impl StaticCatalogTable {
         schema: SchemaRef,
         region: String,
         bucket: String,
-        partition_cols: Vec<String>,
+        partition_cols: Vec<Field>,
         files: Vec<CatalogFile>,
     ) -> CatalogTable {
         CatalogTable::new(Box::new(Self {
@@ -50,74 +58,227 @@ impl StaticCatalogTable {
             bucket,
             files,
             partition_cols,
+            footer_cache: FooterCache::default(),
         }))
     }
+}
 
-    fn to_table(&self) -> Result<Box<dyn TableProvider + Send + Sync>> {
-        let mut key_builder = StringBuilder::new(self.files.len());
-        let mut length_builder = UInt64Builder::new(self.files.len());
-        let mut partition_builders = self
-            .partition_cols
-            .iter()
-            .map(|_| StringBuilder::new(self.files.len()))
-            .collect::<Vec<_>>();
-        for catalog_file in &self.files {
-            assert_eq!(catalog_file.partitions.len(), self.partition_cols.len(),"Each catalog entry should have as many partition values as partition cols.");
-            key_builder.append_value(&catalog_file.sized_file.key)?;
-            length_builder.append_value(catalog_file.sized_file.length)?;
-            for (i, part_val) in catalog_file.partitions.iter().enumerate() {
-                partition_builders[i].append_value(part_val)?;
-            }
+/// The file table of a catalog: the `key` and `length` of each file, followed by
+/// one column for each partition, with the values parsed to the type of the partition
+pub(crate) fn files_table(
+    files: &[CatalogFile],
+    partition_cols: &[Field],
+) -> Result<Box<dyn TableProvider + Send + Sync>> {
+    let mut key_builder = StringBuilder::new(files.len());
+    let mut length_builder = UInt64Builder::new(files.len());
+    let mut partition_values = partition_cols
+        .iter()
+        .map(|_| Vec::with_capacity(files.len()))
+        .collect::<Vec<_>>();
+    for catalog_file in files {
+        if catalog_file.partitions.len() != partition_cols.len() {
+            return Err(BuzzError::Plan(format!(
+                "Each catalog entry should have as many partition values as partition cols ({}), found {} for {}",
+                partition_cols.len(),
+                catalog_file.partitions.len(),
+                catalog_file.sized_file.key
+            )));
         }
-
-        // finish all builders
-        let mut col_arrays: Vec<ArrayRef> = vec![
-            ArrayBuilder::finish(&mut key_builder),
-            ArrayBuilder::finish(&mut length_builder),
-        ];
-        for mut partition_builder in partition_builders {
-            col_arrays.push(ArrayBuilder::finish(&mut partition_builder));
+        key_builder.append_value(&catalog_file.sized_file.key)?;
+        length_builder.append_value(catalog_file.sized_file.length)?;
+        for (i, part_val) in catalog_file.partitions.iter().enumerate() {
+            partition_values[i].push(part_val.as_str());
         }
+    }
 
-        // build schema
-        let mut fields = vec![
-            Field::new("key", DataType::Utf8, false),
-            Field::new("length", DataType::UInt64, false),
-        ];
-        for col in &self.partition_cols {
-            fields.push(Field::new(col, DataType::Utf8, false));
-        }
-        let schema = Arc::new(Schema::new(fields));
-
-        let record_batch = RecordBatch::try_new(Arc::clone(&schema), col_arrays)?;
-        Ok(Box::new(MemTable::try_new(
-            schema,
-            vec![vec![record_batch]],
-        )?))
+    // finish all builders
+    let mut col_arrays: Vec<ArrayRef> = vec![
+        ArrayBuilder::finish(&mut key_builder),
+        ArrayBuilder::finish(&mut length_builder),
+    ];
+    for (col, values) in partition_cols.iter().zip(&partition_values) {
+        col_arrays.push(parse_partition_values(col, values)?);
     }
+
+    // build schema
+    let mut fields = vec![
+        Field::new("key", DataType::Utf8, false),
+        Field::new("length", DataType::UInt64, false),
+    ];
+    fields.extend_from_slice(partition_cols);
+    let schema = Arc::new(Schema::new(fields));
+
+    let record_batch = RecordBatch::try_new(Arc::clone(&schema), col_arrays)?;
+    Ok(Box::new(MemTable::try_new(
+        schema,
+        vec![vec![record_batch]],
+    )?))
+}
+
+/// One S3 parquet table for each group of files
+pub(crate) fn split_s3_parquet(
+    region: &str,
+    bucket: &str,
+    schema: &SchemaRef,
+    file_groups: Vec<Vec<SizedFile>>,
+) -> Vec<HBeeTableDesc> {
+    file_groups
+        .into_iter()
+        .map(|files| {
+            S3ParquetTable::new(
+                region.to_owned(),
+                bucket.to_owned(),
+                files,
+                Arc::clone(schema),
+            )
+        })
+        .collect()
+}
+
+/// The S3 parquet files, read through the footer cache of the catalog
+async fn s3_cached_files(
+    region: &str,
+    bucket: &str,
+    footer_cache: &FooterCache,
+    files: &[SizedFile],
+) -> Vec<CachedFile> {
+    let cache = footer_cache.get().await;
+    files
+        .iter()
+        .map(|file| {
+            let (dler_id, dler_creator) = s3::downloader_creator(region);
+            CachedFile::new(
+                s3::file_id(bucket, &file.key),
+                file.length,
+                Arc::clone(&cache),
+                dler_id,
+                dler_creator,
+            )
+        })
+        .collect()
+}
+
+/// Read the footers of the S3 parquet files to get the size of their row groups
+pub(crate) async fn s3_row_group_sizes(
+    region: &str,
+    bucket: &str,
+    footer_cache: &FooterCache,
+    files: &[SizedFile],
+) -> Result<Vec<Vec<u64>>> {
+    let cached_files = s3_cached_files(region, bucket, footer_cache, files).await;
+    parquet_footer::all_row_group_sizes(cached_files).await
 }
 
+/// Read the footers of the S3 parquet files to get their Arrow schema
+pub(crate) async fn s3_file_schemas(
+    region: &str,
+    bucket: &str,
+    footer_cache: &FooterCache,
+    files: &[SizedFile],
+) -> Result<Vec<Schema>> {
+    let cached_files = s3_cached_files(region, bucket, footer_cache, files).await;
+    parquet_footer::all_arrow_schemas(cached_files).await
+}
+
+#[async_trait]
 impl SplittableTable for StaticCatalogTable {
-    fn split(&self, files: Vec<SizedFile>) -> Vec<HBeeTableDesc> {
-        files
-            .into_iter()
-            .map(|file| {
-                S3ParquetTable::new(
-                    self.region.clone(),
-                    self.bucket.clone(),
-                    vec![file],
-                    Arc::clone(&self.schema),
-                )
-            })
-            .collect()
+    fn split(&self, file_groups: Vec<Vec<SizedFile>>) -> Vec<HBeeTableDesc> {
+        split_s3_parquet(&self.region, &self.bucket, &self.schema, file_groups)
+    }
+    /// Read the footers of the files to get the size of their row groups
+    async fn row_group_sizes(&self, files: &[SizedFile]) -> Result<Vec<Vec<u64>>> {
+        s3_row_group_sizes(&self.region, &self.bucket, &self.footer_cache, files).await
     }
-    fn partition_columns(&self) -> &[String] {
+    async fn file_schemas(&self, files: &[SizedFile]) -> Result<Vec<Schema>> {
+        s3_file_schemas(&self.region, &self.bucket, &self.footer_cache, files).await
+    }
+    fn partition_columns(&self) -> &[Field] {
         &self.partition_cols
     }
     fn schema(&self) -> SchemaRef {
         self.schema.clone()
     }
-    fn file_table(&self) -> Box<dyn TableProvider + Send + Sync> {
-        self.to_table().unwrap()
+    fn set_schema(&mut self, schema: SchemaRef) {
+        self.schema = schema;
+    }
+    async fn file_table(&self) -> Result<Box<dyn TableProvider + Send + Sync>> {
+        files_table(&self.files, &self.partition_cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invalid_partitions() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let catalog = StaticCatalogTable {
+            schema,
+            region: "north-pole-1".to_owned(),
+            bucket: "santas-bucket".to_owned(),
+            files: vec![CatalogFile::new("file_1", 100, vec![])],
+            partition_cols: vec![Field::new("part", DataType::Utf8, false)],
+            footer_cache: FooterCache::default(),
+        };
+        let err = catalog
+            .file_table()
+            .await
+            .err()
+            .expect("Missing partition values should be rejected");
+        assert!(matches!(err, BuzzError::Plan(_)));
+    }
+
+    #[tokio::test]
+    async fn test_typed_partitions() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let catalog = StaticCatalogTable::new(
+            Arc::clone(&schema),
+            "north-pole-1".to_owned(),
+            "santas-bucket".to_owned(),
+            vec![Field::new("hour", DataType::Int32, false)],
+            vec![
+                CatalogFile::new("file_9", 100, vec!["9".to_owned()]),
+                CatalogFile::new("file_10", 100, vec!["10".to_owned()]),
+                CatalogFile::new("file_11", 100, vec!["11".to_owned()]),
+            ],
+        );
+        assert_eq!(
+            catalog
+                .schema()
+                .field_with_name("hour")
+                .unwrap()
+                .data_type(),
+            &DataType::Int32
+        );
+
+        // compared as integers, "9" would be greater than "10" as strings
+        let split = catalog
+            .split(&Some("hour >= 10".to_owned()), &[], None)
+            .await
+            .unwrap();
+        let mut keys = split
+            .tables
+            .iter()
+            .flat_map(|table| table.files())
+            .map(|file| file.key.as_str())
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["file_10", "file_11"]);
+        assert_eq!(split.pruned_partitions, vec!["hour=9".to_owned()]);
+
+        let catalog = StaticCatalogTable {
+            schema,
+            region: "north-pole-1".to_owned(),
+            bucket: "santas-bucket".to_owned(),
+            files: vec![CatalogFile::new("file_1", 100, vec!["nine".to_owned()])],
+            partition_cols: vec![Field::new("hour", DataType::Int32, false)],
+            footer_cache: FooterCache::default(),
+        };
+        catalog
+            .file_table()
+            .await
+            .err()
+            .expect("Values that cannot be parsed should be rejected");
     }
 }
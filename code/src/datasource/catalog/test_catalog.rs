@@ -18,7 +18,8 @@ macro_rules! pattern_vec {
 /// A SplittableTable that splits into `nb_split` S3Parquet tables
 pub struct MockSplittableTable {
     nb_split: usize,
-    partitions: Vec<String>,
+    partitions: Vec<Field>,
+    nb_row_groups: usize,
 }
 
 impl MockSplittableTable {
@@ -28,39 +29,59 @@ impl MockSplittableTable {
     pub fn new(nb_split: usize, partition_keys: usize) -> Self {
         Self {
             nb_split,
-            partitions: pattern_vec!("part_key_{}", partition_keys),
+            partitions: pattern_vec!("part_key_{}", partition_keys)
+                .into_iter()
+                .map(|name| Field::new(&name, DataType::Utf8, false))
+                .collect(),
+            nb_row_groups: 0,
         }
     }
+
+    /// Each file will have `nb_row_groups` row groups of equal size
+    pub fn with_row_groups(mut self, nb_row_groups: usize) -> Self {
+        self.nb_row_groups = nb_row_groups;
+        self
+    }
 }
 
+#[async_trait]
 impl SplittableTable for MockSplittableTable {
-    fn split(&self, files: Vec<SizedFile>) -> Vec<HBeeTableDesc> {
-        files
+    fn split(&self, file_groups: Vec<Vec<SizedFile>>) -> Vec<HBeeTableDesc> {
+        file_groups
             .into_iter()
-            .map(|file| {
+            .map(|files| {
                 S3ParquetTable::new(
                     "north-pole-1".to_owned(),
                     "santas-bucket".to_owned(),
-                    vec![file],
+                    files,
                     test_schema(),
                 )
             })
             .collect::<Vec<_>>()
     }
-    fn partition_columns(&self) -> &[String] {
+    async fn row_group_sizes(&self, files: &[SizedFile]) -> Result<Vec<Vec<u64>>> {
+        Ok(files
+            .iter()
+            .map(|file| {
+                (0..self.nb_row_groups)
+                    .map(|_| file.length / self.nb_row_groups as u64)
+                    .collect()
+            })
+            .collect())
+    }
+    fn partition_columns(&self) -> &[Field] {
         &self.partitions
     }
     fn schema(&self) -> SchemaRef {
         test_schema()
     }
-    fn file_table(&self) -> Box<dyn TableProvider + Send + Sync> {
+    fn set_schema(&mut self, _schema: SchemaRef) {}
+    async fn file_table(&self) -> Result<Box<dyn TableProvider + Send + Sync>> {
         let mut fields = vec![
             Field::new("key", DataType::Utf8, false),
             Field::new("length", DataType::UInt64, false),
         ];
-        for partition in &self.partitions {
-            fields.push(Field::new(partition, DataType::Utf8, false));
-        }
+        fields.extend_from_slice(&self.partitions);
 
         let file_table_schema = Arc::new(Schema::new(fields));
 
@@ -80,10 +101,10 @@ impl SplittableTable for MockSplittableTable {
         let batches = RecordBatch::try_new(Arc::clone(&file_table_schema), arrays)
             .expect("Invalid test data");
 
-        Box::new(
+        Ok(Box::new(
             MemTable::try_new(file_table_schema, vec![vec![batches]])
                 .expect("invalid test table"),
-        )
+        ))
     }
 }
 
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use super::{partition_values, HBeeTableDesc};
+use crate::clients::local_fs;
+use crate::clients::CachedFile;
+use crate::clients::RangeCache;
+use crate::execution_plan::{ParquetExec, ParquetPart};
+use crate::models::SizedFile;
+use arrow::datatypes::*;
+use datafusion::error::Result;
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+
+/// Table-based representation of `ParquetFile`s in a directory of the local file system.
+/// The hbee that reads it should have access to the same directory.
+#[derive(Debug)]
+pub struct LocalParquetTable {
+    dir: String,
+    files: Vec<SizedFile>,
+    schema: SchemaRef,
+    partition_cols: Vec<Field>,
+}
+
+impl LocalParquetTable {
+    /// Initialize a new `ParquetTable` from a list of files in `dir` and an expected schema.
+    pub fn new(dir: String, files: Vec<SizedFile>, schema: SchemaRef) -> HBeeTableDesc {
+        HBeeTableDesc::LocalParquet(Self {
+            schema,
+            dir,
+            files,
+            partition_cols: vec![],
+        })
+    }
+
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    pub fn files(&self) -> &[SizedFile] {
+        &self.files
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    pub fn partition_cols(&self) -> &[Field] {
+        &self.partition_cols
+    }
+
+    pub(super) fn set_partition_cols(&mut self, partition_cols: Vec<Field>) {
+        self.partition_cols = partition_cols;
+    }
+
+    pub fn scan(
+        &self,
+        cache: Arc<RangeCache>,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        _filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let parts = self
+            .files
+            .iter()
+            .map(|file| {
+                let (dler_id, dler_creator) = local_fs::downloader_creator();
+                Ok(ParquetPart {
+                    file: CachedFile::new(
+                        local_fs::file_id(&self.dir, &file.key),
+                        file.length,
+                        Arc::clone(&cache),
+                        dler_id,
+                        dler_creator,
+                    ),
+                    row_groups: file.row_groups.clone(),
+                    partitions: partition_values(&self.partition_cols, file)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Arc::new(ParquetExec::new(
+            parts,
+            projection.clone(),
+            batch_size,
+            Arc::clone(&self.schema),
+            self.partition_cols.clone(),
+        )))
+    }
+}
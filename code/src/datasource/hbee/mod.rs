@@ -1,29 +1,106 @@
+pub mod local_parquet;
 pub mod s3_parquet;
 
 use std::any::Any;
 use std::sync::Arc;
 
 use crate::clients::RangeCache;
+use crate::datasource::catalog::partition_values::parse_partition_values;
+use crate::models::SizedFile;
+use arrow::array::ArrayRef;
 use arrow::datatypes::*;
 use datafusion::datasource::datasource::Statistics;
 use datafusion::datasource::TableProvider;
-use datafusion::error::Result;
+use datafusion::error::{DataFusionError, Result};
 use datafusion::logical_plan::Expr;
 use datafusion::physical_plan::ExecutionPlan;
+use local_parquet::LocalParquetTable;
 use s3_parquet::S3ParquetTable;
 
 /// Implemented as an enum because serialization must be mapped for new implems
 #[derive(Debug)]
 pub enum HBeeTableDesc {
     S3Parquet(S3ParquetTable),
+    LocalParquet(LocalParquetTable),
 }
 
 impl HBeeTableDesc {
+    /// The schema of the table: the columns of the files followed by the partition columns
     pub fn schema(&self) -> SchemaRef {
+        let file_schema = self.file_schema();
+        let partition_cols = self.partition_cols();
+        if partition_cols.is_empty() {
+            return file_schema;
+        }
+        let mut fields = file_schema.fields().clone();
+        fields.extend_from_slice(partition_cols);
+        Arc::new(Schema::new_with_metadata(
+            fields,
+            file_schema.metadata().clone(),
+        ))
+    }
+
+    /// The schema expected in the files
+    pub fn file_schema(&self) -> SchemaRef {
         match self {
             HBeeTableDesc::S3Parquet(table) => table.schema(),
+            HBeeTableDesc::LocalParquet(table) => table.schema(),
+        }
+    }
+
+    pub fn partition_cols(&self) -> &[Field] {
+        match self {
+            HBeeTableDesc::S3Parquet(table) => table.partition_cols(),
+            HBeeTableDesc::LocalParquet(table) => table.partition_cols(),
         }
     }
+
+    /// Append the given partition columns to the table. Their values are taken from the
+    /// `partitions` of each file and added as constant columns to the scanned batches.
+    pub fn with_partition_cols(mut self, partition_cols: Vec<Field>) -> Self {
+        match &mut self {
+            HBeeTableDesc::S3Parquet(table) => table.set_partition_cols(partition_cols),
+            HBeeTableDesc::LocalParquet(table) => {
+                table.set_partition_cols(partition_cols)
+            }
+        }
+        self
+    }
+
+    pub fn files(&self) -> &[SizedFile] {
+        match self {
+            HBeeTableDesc::S3Parquet(table) => table.files(),
+            HBeeTableDesc::LocalParquet(table) => table.files(),
+        }
+    }
+
+    /// The URL of the location of the files, e.g `s3://bucket`
+    pub fn location(&self) -> String {
+        match self {
+            HBeeTableDesc::S3Parquet(table) => format!("s3://{}", table.bucket()),
+            HBeeTableDesc::LocalParquet(table) => format!("file://{}", table.dir()),
+        }
+    }
+}
+
+/// Parse the partition values of the file into arrays of one element
+fn partition_values(partition_cols: &[Field], file: &SizedFile) -> Result<Vec<ArrayRef>> {
+    if file.partitions.len() != partition_cols.len() {
+        return Err(DataFusionError::Plan(format!(
+            "Expected {} partition values for {}, found {}",
+            partition_cols.len(),
+            file.key,
+            file.partitions.len()
+        )));
+    }
+    partition_cols
+        .iter()
+        .zip(&file.partitions)
+        .map(|(col, value)| {
+            parse_partition_values(col, &[value.as_str()])
+                .map_err(|e| DataFusionError::Plan(e.reason()))
+        })
+        .collect()
 }
 
 /// A table that can be distributed to hbees
@@ -61,6 +138,9 @@ impl TableProvider for HBeeTable {
             HBeeTableDesc::S3Parquet(table) => {
                 table.scan(Arc::clone(&self.cache), projection, batch_size, filters)
             }
+            HBeeTableDesc::LocalParquet(table) => {
+                table.scan(Arc::clone(&self.cache), projection, batch_size, filters)
+            }
         }
     }
 
@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
-use super::HBeeTableDesc;
+use super::{partition_values, HBeeTableDesc};
 use crate::clients::s3;
 use crate::clients::CachedFile;
 use crate::clients::RangeCache;
-use crate::execution_plan::ParquetExec;
+use crate::execution_plan::{ParquetExec, ParquetPart};
 use crate::models::SizedFile;
 use arrow::datatypes::*;
 use datafusion::error::Result;
@@ -18,6 +18,7 @@ pub struct S3ParquetTable {
     bucket: String,
     files: Vec<SizedFile>,
     schema: SchemaRef,
+    partition_cols: Vec<Field>,
 }
 
 impl S3ParquetTable {
@@ -33,6 +34,7 @@ impl S3ParquetTable {
             region,
             bucket,
             files,
+            partition_cols: vec![],
         })
     }
 
@@ -52,6 +54,14 @@ impl S3ParquetTable {
         self.schema.clone()
     }
 
+    pub fn partition_cols(&self) -> &[Field] {
+        &self.partition_cols
+    }
+
+    pub(super) fn set_partition_cols(&mut self, partition_cols: Vec<Field>) {
+        self.partition_cols = partition_cols;
+    }
+
     pub fn scan(
         &self,
         cache: Arc<RangeCache>,
@@ -59,26 +69,31 @@ impl S3ParquetTable {
         batch_size: usize,
         _filters: &[Expr],
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        let s3_files = self
+        let s3_parts = self
             .files
             .iter()
             .map(|file| {
                 let (dler_id, dler_creator) = s3::downloader_creator(&self.region);
                 let file_id = s3::file_id(&self.bucket, &file.key);
-                CachedFile::new(
-                    file_id,
-                    file.length,
-                    Arc::clone(&cache),
-                    dler_id,
-                    dler_creator,
-                )
+                Ok(ParquetPart {
+                    file: CachedFile::new(
+                        file_id,
+                        file.length,
+                        Arc::clone(&cache),
+                        dler_id,
+                        dler_creator,
+                    ),
+                    row_groups: file.row_groups.clone(),
+                    partitions: partition_values(&self.partition_cols, file)?,
+                })
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
         Ok(Arc::new(ParquetExec::new(
-            s3_files,
+            s3_parts,
             projection.clone(),
             batch_size,
             Arc::clone(&self.schema),
+            self.partition_cols.clone(),
         )))
     }
 }
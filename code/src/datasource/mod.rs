@@ -1,11 +1,19 @@
 //! Datasources are implementations of DataFusion's TableProvider trait
 
+mod broadcast;
 mod catalog;
 mod hbee;
 mod hcomb;
 
+pub use broadcast::BroadcastTable;
+pub use catalog::catalog_config::CatalogConfig;
+pub use catalog::hive_catalog::HiveCatalogTable;
+pub use catalog::local_catalog::LocalCatalogTable;
 pub use catalog::static_catalog::{CatalogFile, StaticCatalogTable};
 pub use catalog::test_catalog::MockSplittableTable;
-pub use catalog::{CatalogTable, SplittableTable};
-pub use hbee::{s3_parquet::S3ParquetTable, HBeeTable, HBeeTableDesc};
+pub use catalog::{CatalogSplit, CatalogTable, SplittableTable};
+pub use hbee::{
+    local_parquet::LocalParquetTable, s3_parquet::S3ParquetTable, HBeeTable,
+    HBeeTableDesc,
+};
 pub use hcomb::{HCombTable, HCombTableDesc};
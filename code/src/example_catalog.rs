@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
-use crate::datasource::{CatalogFile, CatalogTable, StaticCatalogTable};
+use crate::datasource::{
+    BroadcastTable, CatalogFile, CatalogTable, LocalCatalogTable, StaticCatalogTable,
+};
+use arrow::array::StringArray;
 use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
 
 /// shortened nyc taxi, hosted by cloudfuse
 pub fn nyc_taxi_cloudfuse_sample() -> CatalogTable {
@@ -9,7 +13,7 @@ pub fn nyc_taxi_cloudfuse_sample() -> CatalogTable {
         nyc_taxi_v1_schema(TimeUnit::Microsecond),
         "us-east-2".to_owned(),
         "cloudfuse-taxi-data".to_owned(),
-        vec!["month".to_owned()],
+        vec![month_partition()],
         vec![CatalogFile::new(
             "raw_small/2009/01/data.parquet",
             27301328,
@@ -18,13 +22,23 @@ pub fn nyc_taxi_cloudfuse_sample() -> CatalogTable {
     )
 }
 
+/// nyc taxi files copied to a local directory, e.g `month=2009%2F01/data.parquet` for
+/// the shortened file hosted by cloudfuse (the `/` of the month is escaped)
+pub fn nyc_taxi_local(dir: &str) -> CatalogTable {
+    LocalCatalogTable::new(
+        nyc_taxi_v1_schema(TimeUnit::Microsecond),
+        dir.to_owned(),
+        vec![month_partition()],
+    )
+}
+
 /// complete nyc taxi files with 5M rows per rowgroups, hosted by cloudfuse
 pub fn nyc_taxi_cloudfuse() -> CatalogTable {
     StaticCatalogTable::new(
         nyc_taxi_v1_schema(TimeUnit::Microsecond),
         "us-east-2".to_owned(),
         "cloudfuse-taxi-data".to_owned(),
-        vec!["month".to_owned()],
+        vec![month_partition()],
         vec![
             CatalogFile::new(
                 "raw_5M/2009/01/data.parquet",
@@ -62,7 +76,7 @@ pub fn nyc_taxi_ursa() -> CatalogTable {
         nyc_taxi_v1_schema(TimeUnit::Nanosecond),
         "us-east-2".to_owned(),
         "ursa-labs-taxi-data".to_owned(),
-        vec!["month".to_owned()],
+        vec![month_partition()],
         vec![
             CatalogFile::new(
                 "2009/01/data.parquet",
@@ -128,6 +142,42 @@ pub fn nyc_taxi_ursa() -> CatalogTable {
     )
 }
 
+/// lookup table to normalize the payment types found in the 2009 nyc taxi files
+pub fn nyc_taxi_payment_types() -> BroadcastTable {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("payment_type_code", DataType::Utf8, false),
+        Field::new("payment_method", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        Arc::clone(&schema),
+        vec![
+            Arc::new(StringArray::from(vec![
+                "CASH",
+                "Cash",
+                "CREDIT",
+                "Credit",
+                "No Charge",
+                "Dispute",
+            ])),
+            Arc::new(StringArray::from(vec![
+                "cash",
+                "cash",
+                "credit card",
+                "credit card",
+                "no charge",
+                "dispute",
+            ])),
+        ],
+    )
+    .expect("invalid payment types table");
+    BroadcastTable::new("nyc_taxi_payment_types".to_owned(), schema, vec![batch])
+}
+
+/// the month of the nyc taxi files, formatted as `YYYY/MM`
+fn month_partition() -> Field {
+    Field::new("month", DataType::Utf8, false)
+}
+
 /// schema found in earlier nyc taxi files (e.g 2009)
 fn nyc_taxi_v1_schema(time_unit: TimeUnit) -> Arc<Schema> {
     Arc::new(Schema::new(vec![
@@ -3,5 +3,6 @@
 mod parquet;
 mod stream;
 
-pub use parquet::ParquetExec;
+pub(crate) use parquet::can_read_as;
+pub use parquet::{ParquetExec, ParquetPart};
 pub use stream::StreamExec;
@@ -6,7 +6,10 @@ use std::task::{Context, Poll};
 use std::{fmt, thread};
 
 use crate::clients::CachedFile;
-use arrow::datatypes::{Schema, SchemaRef};
+use arrow::array::*;
+use arrow::compute::kernels::cast::cast;
+use arrow::compute::kernels::take::take;
+use arrow::datatypes::{DataType, DateUnit, Field, Schema, SchemaRef, TimeUnit};
 use arrow::error::{ArrowError, Result as ArrowResult};
 use arrow::record_batch::RecordBatch;
 use arrow_parquet::arrow::{ArrowReader, ParquetFileArrowReader};
@@ -18,40 +21,57 @@ use datafusion::physical_plan::Partitioning;
 use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
 use futures::stream::Stream;
 
+/// A Parquet file or a subset of its row groups
+#[derive(Debug, Clone)]
+pub struct ParquetPart {
+    pub file: CachedFile,
+    /// The indexes of the row groups to read, all of them if `None`
+    pub row_groups: Option<Vec<usize>>,
+    /// The value of each partition column for this part, as arrays of one element
+    pub partitions: Vec<ArrayRef>,
+}
+
 /// Execution plan for scanning a Parquet file
 #[derive(Debug, Clone)]
 pub struct ParquetExec {
-    files: Vec<CachedFile>,
-    /// Schema before projection is applied
+    /// One part per partition
+    parts: Vec<ParquetPart>,
+    /// Schema of the files, each file can differ from it (see `reconcile`)
     file_schema: SchemaRef,
-    /// Schema after projection is applied
+    /// Schema after projection is applied, partition columns included
     projected_schema: SchemaRef,
-    /// Projection for which columns to load
+    /// Projection for which columns to load, the indexes after the file columns
+    /// refer to the partition columns
     projection: Vec<usize>,
     /// Batch size
     batch_size: usize,
 }
 
 impl ParquetExec {
-    /// Create a new Parquet reader execution plan
+    /// Create a new Parquet reader execution plan.
+    /// The `partition_cols` are appended to the `schema` of the files, with the constant
+    /// values given by the `partitions` of each part.
     pub fn new(
-        files: Vec<CachedFile>,
+        parts: Vec<ParquetPart>,
         projection: Option<Vec<usize>>,
         batch_size: usize,
         schema: SchemaRef,
+        partition_cols: Vec<Field>,
     ) -> Self {
+        let mut table_fields = schema.fields().clone();
+        table_fields.extend(partition_cols);
         let projection = match projection {
             Some(p) => p,
-            None => (0..schema.fields().len()).collect(),
+            None => (0..table_fields.len()).collect(),
         };
         let projected_schema = Schema::new(
             projection
                 .iter()
-                .map(|col| schema.field(*col).clone())
+                .map(|col| table_fields[*col].clone())
                 .collect(),
         );
         Self {
-            files,
+            parts,
             file_schema: schema,
             projected_schema: Arc::new(projected_schema),
             projection,
@@ -59,51 +79,129 @@ impl ParquetExec {
         }
     }
 
-    /// Read the footer and schedule the downloads of all the required chunks
-    async fn init_file(
+    /// Match the projected columns of the table with the columns of a file, by name.
+    /// Returns the columns to read from the file and how to assemble them into the
+    /// projected batches: the columns that the file does not have are filled with nulls,
+    /// the columns of the file that are not in the table are ignored and the columns
+    /// with a compatible type are cast to the type of the table (see `can_read_as`).
+    /// If no column of the file is needed, the first one is read anyway to know the
+    /// number of rows.
+    fn reconcile(
         &self,
         partition: usize,
-    ) -> DataFusionResult<Arc<SerializedFileReader<CachedFile>>> {
-        let end_dl_chunk_start = Self::download_footer(self.files[partition].clone());
-        let file_schema = self.file_schema.clone();
-        let file = self.files[partition].clone();
-        let projection = self.projection.clone();
+        parsed_schema: &Schema,
+    ) -> DataFusionResult<(Vec<usize>, BatchAssembler)> {
+        let nb_table_cols = self.file_schema.fields().len();
+        let mut file_cols = vec![];
+        for col in self.projection.iter().filter(|col| **col < nb_table_cols) {
+            let field = self.file_schema.field(*col);
+            match parsed_schema.index_of(field.name()) {
+                Ok(file_col) => {
+                    let parsed_type = parsed_schema.field(file_col).data_type();
+                    if !can_read_as(parsed_type, field.data_type()) {
+                        return Err(DataFusionError::Plan(format!(
+                            "The column {} of type {:?} in the file cannot be read as {:?}",
+                            field.name(),
+                            parsed_type,
+                            field.data_type()
+                        )));
+                    }
+                    file_cols.push(Some(file_col));
+                }
+                Err(_) => {
+                    // check early that the missing column can be filled with nulls
+                    null_array(field.data_type(), 0)?;
+                    file_cols.push(None);
+                }
+            }
+        }
 
-        // Reading the footer is blocking so it should be started on a specific thread
-        tokio::task::spawn_blocking(move || {
-            let file_reader = Arc::new(
-                SerializedFileReader::new(file.clone())
-                    .map_err(|e| DataFusionError::ParquetError(e))?,
-            );
-            let mut arrow_reader = ParquetFileArrowReader::new(file_reader.clone());
+        // the batches of the reader have the columns in the order of the file
+        let mut file_projection = file_cols.iter().flatten().cloned().collect::<Vec<_>>();
+        file_projection.sort();
+        file_projection.dedup();
+        if file_projection.is_empty()
+            && !self.projection.is_empty()
+            && !parsed_schema.fields().is_empty()
+        {
+            file_projection.push(0);
+        }
 
-            if file_schema.fields() != arrow_reader.get_schema()?.fields() {
-                return Err(DataFusionError::Plan(format!(
-                    "Expected and parsed schema fields are not equal: {:?} != {:?}",
-                    file_schema.fields(),
-                    arrow_reader.get_schema()?.fields()
-                )));
-            }
-            // prefetch usefull byte ranges
-            let metadata = file_reader.metadata();
-            for i in 0..metadata.num_row_groups() {
-                for proj in &projection {
-                    let rg_metadata = metadata.row_group(i);
-                    let col_metadata = rg_metadata.column(*proj);
-                    let (start, length) = col_metadata.byte_range();
-                    if start < end_dl_chunk_start {
-                        file.prefetch(start, length as usize);
-                    }
+        let mut file_cols = file_cols.into_iter();
+        let sources = self
+            .projection
+            .iter()
+            .map(|col| {
+                if *col >= nb_table_cols {
+                    let value = &self.parts[partition].partitions[*col - nb_table_cols];
+                    return ColumnSource::Partition(Arc::clone(value));
                 }
+                match file_cols.next().flatten() {
+                    Some(file_col) => ColumnSource::File(
+                        file_projection.binary_search(&file_col).unwrap(),
+                    ),
+                    None => ColumnSource::Missing,
+                }
+            })
+            .collect();
+        let assembler = BatchAssembler {
+            schema: self.projected_schema.clone(),
+            sources,
+        };
+        Ok((file_projection, assembler))
+    }
+
+    /// Read the footer, reconcile the schema of the file with the one of the table
+    /// and schedule the downloads of all the required chunks
+    async fn init_file(
+        &self,
+        partition: usize,
+    ) -> DataFusionResult<(
+        Arc<SerializedFileReader<CachedFile>>,
+        Vec<usize>,
+        BatchAssembler,
+    )> {
+        let end_dl_chunk_start =
+            Self::download_footer(self.parts[partition].file.clone());
+        let file = self.parts[partition].file.clone();
+        let row_groups = self.parts[partition].row_groups.clone();
+
+        // Reading the footer is blocking so it should be started on a specific thread
+        let (file_reader, parsed_schema) = tokio::task::spawn_blocking(move || {
+            let mut file_reader = SerializedFileReader::new(file)
+                .map_err(|e| DataFusionError::ParquetError(e))?;
+            if let Some(row_groups) = row_groups {
+                // only the selected row groups will be prefetched and read
+                file_reader.filter_row_groups(&|_, i| row_groups.contains(&i));
             }
-            Ok(file_reader)
+            let file_reader = Arc::new(file_reader);
+            let mut arrow_reader = ParquetFileArrowReader::new(file_reader.clone());
+            let parsed_schema = arrow_reader.get_schema()?;
+            Ok::<_, DataFusionError>((file_reader, parsed_schema))
         })
         .await
-        .unwrap()
+        .unwrap()?;
+
+        let (projection, assembler) = self.reconcile(partition, &parsed_schema)?;
+
+        // prefetch usefull byte ranges
+        let file = &self.parts[partition].file;
+        let metadata = file_reader.metadata();
+        for i in 0..metadata.num_row_groups() {
+            for proj in &projection {
+                let rg_metadata = metadata.row_group(i);
+                let col_metadata = rg_metadata.column(*proj);
+                let (start, length) = col_metadata.byte_range();
+                if start < end_dl_chunk_start {
+                    file.prefetch(start, length as usize);
+                }
+            }
+        }
+        Ok((file_reader, projection, assembler))
     }
 
     // returns the start of the downloaded chunk
-    fn download_footer(file: CachedFile) -> u64 {
+    pub(crate) fn download_footer(file: CachedFile) -> u64 {
         let end_length = 1024 * 1024;
         let (end_start, end_length) = match file.len().checked_sub(end_length) {
             Some(val) => (val, end_length),
@@ -132,7 +230,7 @@ impl ExecutionPlan for ParquetExec {
 
     /// Get the output partitioning of this plan
     fn output_partitioning(&self) -> Partitioning {
-        Partitioning::UnknownPartitioning(self.files.len())
+        Partitioning::UnknownPartitioning(self.parts.len())
     }
 
     fn with_new_children(
@@ -153,7 +251,8 @@ impl ExecutionPlan for ParquetExec {
         &self,
         partition: usize,
     ) -> DataFusionResult<SendableRecordBatchStream> {
-        let parquet_reader = self.init_file(partition).await?;
+        let (parquet_reader, file_projection, assembler) =
+            self.init_file(partition).await?;
         // because the parquet implementation is not thread-safe, it is necessary to execute
         // on a thread and communicate with channels
         let (response_tx, response_rx): (
@@ -161,12 +260,16 @@ impl ExecutionPlan for ParquetExec {
             Receiver<Option<ArrowResult<RecordBatch>>>,
         ) = sync_channel(2);
 
-        let projection = self.projection.clone();
         let batch_size = self.batch_size;
 
         thread::spawn(move || {
-            if let Err(e) = read_file(parquet_reader, projection, batch_size, response_tx)
-            {
+            if let Err(e) = read_file(
+                parquet_reader,
+                file_projection,
+                batch_size,
+                assembler,
+                response_tx,
+            ) {
                 println!("Parquet reader thread terminated due to error: {:?}", e);
             }
         });
@@ -188,10 +291,131 @@ fn send_result(
     Ok(())
 }
 
+/// Where a projected column of the table comes from, for a given file
+#[derive(Debug)]
+enum ColumnSource {
+    /// The column at the given index in the batches read from the file
+    File(usize),
+    /// A column that the file does not have
+    Missing,
+    /// A partition column, with its value as an array of one element
+    Partition(ArrayRef),
+}
+
+/// Builds the projected batches from the columns read in the file and the partition values
+struct BatchAssembler {
+    schema: SchemaRef,
+    sources: Vec<ColumnSource>,
+}
+
+impl BatchAssembler {
+    fn assemble(&self, file_batch: RecordBatch) -> ArrowResult<RecordBatch> {
+        let nb_rows = file_batch.num_rows();
+        let columns = self
+            .sources
+            .iter()
+            .zip(self.schema.fields())
+            .map(|(source, field)| match source {
+                ColumnSource::File(i) => {
+                    let column = file_batch.column(*i);
+                    if column.data_type() == field.data_type() {
+                        Ok(Arc::clone(column))
+                    } else {
+                        cast(column, field.data_type())
+                    }
+                }
+                ColumnSource::Missing => null_array(field.data_type(), nb_rows),
+                ColumnSource::Partition(value) => {
+                    take(value, &UInt32Array::from(vec![0; nb_rows]), None)
+                }
+            })
+            .collect::<ArrowResult<Vec<_>>>()?;
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+}
+
+/// Whether the values of a file column of type `from` can be read as values of type `to`:
+/// integers and floats can be widened, dates extended and timestamps converted to finer units.
+/// Converting timestamps to a coarser unit would silently drop their precision.
+pub(crate) fn can_read_as(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    match (from, to) {
+        _ if from == to => true,
+        (Int8, Int16) | (Int8, Int32) | (Int8, Int64) => true,
+        (Int16, Int32) | (Int16, Int64) | (Int32, Int64) => true,
+        (UInt8, UInt16) | (UInt8, UInt32) | (UInt8, UInt64) => true,
+        (UInt16, UInt32) | (UInt16, UInt64) | (UInt32, UInt64) => true,
+        (UInt8, Int16) | (UInt8, Int32) | (UInt8, Int64) => true,
+        (UInt16, Int32) | (UInt16, Int64) | (UInt32, Int64) => true,
+        (Float32, Float64) => true,
+        (Date32(_), Date64(_)) => true,
+        (Timestamp(from_unit, from_tz), Timestamp(to_unit, to_tz)) => {
+            from_tz == to_tz && unit_rank(from_unit) <= unit_rank(to_unit)
+        }
+        _ => false,
+    }
+}
+
+/// The position of the unit from the coarsest to the finest
+fn unit_rank(unit: &TimeUnit) -> u8 {
+    match unit {
+        TimeUnit::Second => 0,
+        TimeUnit::Millisecond => 1,
+        TimeUnit::Microsecond => 2,
+        TimeUnit::Nanosecond => 3,
+    }
+}
+
+/// An array of `len` nulls, for the columns that a file does not have
+fn null_array(data_type: &DataType, len: usize) -> ArrowResult<ArrayRef> {
+    macro_rules! nulls {
+        ($builder:ty) => {{
+            let mut builder = <$builder>::new(len);
+            for _ in 0..len {
+                builder.append_null()?;
+            }
+            Ok(Arc::new(builder.finish()) as ArrayRef)
+        }};
+    }
+    match data_type {
+        DataType::Null => Ok(Arc::new(NullArray::new(len))),
+        DataType::Boolean => nulls!(BooleanBuilder),
+        DataType::Int8 => nulls!(Int8Builder),
+        DataType::Int16 => nulls!(Int16Builder),
+        DataType::Int32 => nulls!(Int32Builder),
+        DataType::Int64 => nulls!(Int64Builder),
+        DataType::UInt8 => nulls!(UInt8Builder),
+        DataType::UInt16 => nulls!(UInt16Builder),
+        DataType::UInt32 => nulls!(UInt32Builder),
+        DataType::UInt64 => nulls!(UInt64Builder),
+        DataType::Float32 => nulls!(Float32Builder),
+        DataType::Float64 => nulls!(Float64Builder),
+        DataType::Utf8 => nulls!(StringBuilder),
+        DataType::Binary => nulls!(BinaryBuilder),
+        DataType::Date32(DateUnit::Day) => nulls!(Date32Builder),
+        DataType::Date64(DateUnit::Millisecond) => nulls!(Date64Builder),
+        DataType::Timestamp(TimeUnit::Second, None) => nulls!(TimestampSecondBuilder),
+        DataType::Timestamp(TimeUnit::Millisecond, None) => {
+            nulls!(TimestampMillisecondBuilder)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            nulls!(TimestampMicrosecondBuilder)
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+            nulls!(TimestampNanosecondBuilder)
+        }
+        other => Err(ArrowError::ComputeError(format!(
+            "A missing column of type {:?} cannot be filled with nulls",
+            other
+        ))),
+    }
+}
+
 fn read_file(
     file_reader: Arc<SerializedFileReader<CachedFile>>,
     projection: Vec<usize>,
     batch_size: usize,
+    assembler: BatchAssembler,
     response_tx: SyncSender<Option<ArrowResult<RecordBatch>>>,
 ) -> DataFusionResult<()> {
     let mut arrow_reader = ParquetFileArrowReader::new(file_reader.clone());
@@ -199,7 +423,9 @@ fn read_file(
         arrow_reader.get_record_reader_by_columns(projection.clone(), batch_size)?;
     loop {
         match batch_reader.next() {
-            Some(Ok(batch)) => send_result(&response_tx, Some(Ok(batch)))?,
+            Some(Ok(batch)) => {
+                send_result(&response_tx, Some(assembler.assemble(batch)))?
+            }
             None => {
                 // finished reading file
                 send_result(&response_tx, None)?;
@@ -258,7 +484,7 @@ mod tests {
     use crate::clients::RangeCache;
     use crate::error::Result as BuzzResult;
     use arrow::array::*;
-    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
     use arrow_parquet::arrow::ArrowWriter;
     use async_trait::async_trait;
     use tokio::fs::File as TokioFile;
@@ -300,29 +526,239 @@ mod tests {
         );
     }
 
+    #[tokio::test(threaded_scheduler)]
+    async fn test_row_group_selection() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        // each batch is written as a separate row group
+        let rec_batches = (0..4)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from(vec![i; 100]))],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let filename = "test_row_group_selection.parquet";
+        let file = write_file(&rec_batches, filename).await;
+        let part = ParquetPart {
+            file,
+            row_groups: Some(vec![1, 3]),
+            partitions: vec![],
+        };
+        let exec_plan = ParquetExec::new(vec![part], None, 2048, schema, vec![]);
+        let results = datafusion::physical_plan::collect(Arc::new(exec_plan))
+            .await
+            .unwrap();
+
+        let values = results
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values.len(), 200);
+        assert!(values.iter().all(|val| *val == 1 || *val == 3));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_partition_columns() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let rec_batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+        )
+        .unwrap();
+        let file = write_file(&[rec_batch], "test_partition_columns.parquet").await;
+        let exec = |projection| {
+            let part = ParquetPart {
+                file: file.clone(),
+                row_groups: None,
+                partitions: vec![
+                    Arc::new(StringArray::from(vec!["Rovaniemi"])) as ArrayRef,
+                    Arc::new(Int32Array::from(vec![2020])) as ArrayRef,
+                ],
+            };
+            ParquetExec::new(
+                vec![part],
+                projection,
+                2048,
+                schema.clone(),
+                vec![
+                    Field::new("city", DataType::Utf8, false),
+                    Field::new("year", DataType::Int32, false),
+                ],
+            )
+        };
+
+        let results = datafusion::physical_plan::collect(Arc::new(exec(None)))
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_columns(), 3);
+        assert_eq!(results[0].schema().field(2).name(), "year");
+        let years = results[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(years.len(), 3);
+        assert!((0..3).all(|i| years.value(i) == 2020));
+
+        // the rows are counted even if no column of the file is projected
+        let results = datafusion::physical_plan::collect(Arc::new(exec(Some(vec![1]))))
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_columns(), 1);
+        let cities = results[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(cities.len(), 3);
+        assert_eq!(cities.value(2), "Rovaniemi");
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_schema_evolution() {
+        let file_schema = Arc::new(Schema::new(vec![
+            Field::new("added", DataType::Utf8, false),
+            Field::new("b", DataType::Int32, false),
+            Field::new("a", DataType::Timestamp(TimeUnit::Millisecond, None), false),
+        ]));
+        let rec_batch = RecordBatch::try_new(
+            file_schema,
+            vec![
+                Arc::new(StringArray::from(vec!["x", "y"])),
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(TimestampMillisecondArray::from_vec(vec![1000, 2000], None)),
+            ],
+        )
+        .unwrap();
+        let file = write_file(&[rec_batch], "test_schema_evolution.parquet").await;
+        let exec = |table_schema| {
+            let part = ParquetPart {
+                file: file.clone(),
+                row_groups: None,
+                partitions: vec![],
+            };
+            ParquetExec::new(vec![part], None, 2048, table_schema, vec![])
+        };
+
+        // columns are matched by name, widened or filled with nulls
+        let table_schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+            Field::new("b", DataType::Int64, true),
+            Field::new("missing", DataType::Float64, true),
+        ]));
+        let results = datafusion::physical_plan::collect(Arc::new(exec(table_schema)))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        let a = results[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(a.value(1), 2_000_000);
+        let b = results[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(b.value(0), 1);
+        assert_eq!(results[0].column(2).data_type(), &DataType::Float64);
+        assert_eq!(results[0].column(2).null_count(), 2);
+
+        // narrowing a column is rejected
+        let table_schema =
+            Arc::new(Schema::new(vec![Field::new("b", DataType::Int8, true)]));
+        datafusion::physical_plan::collect(Arc::new(exec(table_schema)))
+            .await
+            .err()
+            .expect("Int32 values cannot be read as Int8");
+
+        // so is converting a timestamp to a coarser unit
+        let table_schema = Arc::new(Schema::new(vec![Field::new(
+            "a",
+            DataType::Timestamp(TimeUnit::Second, None),
+            true,
+        )]));
+        datafusion::physical_plan::collect(Arc::new(exec(table_schema)))
+            .await
+            .err()
+            .expect("Millisecond timestamps cannot be read as seconds");
+    }
+
+    #[test]
+    fn test_can_read_timestamps() {
+        let ts = |unit| DataType::Timestamp(unit, None);
+        assert!(can_read_as(
+            &ts(TimeUnit::Millisecond),
+            &ts(TimeUnit::Microsecond)
+        ));
+        assert!(can_read_as(
+            &ts(TimeUnit::Second),
+            &ts(TimeUnit::Nanosecond)
+        ));
+        assert!(!can_read_as(
+            &ts(TimeUnit::Nanosecond),
+            &ts(TimeUnit::Millisecond)
+        ));
+        assert!(!can_read_as(
+            &ts(TimeUnit::Microsecond),
+            &ts(TimeUnit::Second)
+        ));
+        assert!(!can_read_as(
+            &ts(TimeUnit::Millisecond),
+            &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".to_owned()))
+        ));
+    }
+
     /// Write the given `rec_batch` as a parquet file then make it into an exec plan
     async fn write_and_exec(rec_batch: &RecordBatch, filename: &str) -> Vec<RecordBatch> {
+        let file = write_file(&[rec_batch.clone()], filename).await;
+        let part = ParquetPart {
+            file,
+            row_groups: None,
+            partitions: vec![],
+        };
+
+        let exec_plan =
+            ParquetExec::new(vec![part], None, 2048, rec_batch.schema(), vec![]);
+
+        datafusion::physical_plan::collect(Arc::new(exec_plan))
+            .await
+            .unwrap()
+    }
+
+    /// Write the given `rec_batches` as a parquet file that can be read through the cache
+    async fn write_file(rec_batches: &[RecordBatch], filename: &str) -> CachedFile {
         let (tmp_file, path) = get_temp_file(filename);
 
-        let mut writer =
-            ArrowWriter::try_new(tmp_file.try_clone().unwrap(), rec_batch.schema(), None)
-                .unwrap();
-        writer.write(&rec_batch).unwrap();
+        let mut writer = ArrowWriter::try_new(
+            tmp_file.try_clone().unwrap(),
+            rec_batches[0].schema(),
+            None,
+        )
+        .unwrap();
+        for rec_batch in rec_batches {
+            writer.write(&rec_batch).unwrap();
+        }
         writer.close().unwrap();
 
-        let file = CachedFile::new(
+        CachedFile::new(
             path.into_os_string().into_string().unwrap(),
             tmp_file.metadata().unwrap().len(),
             Arc::new(RangeCache::new().await),
             "file_downloader".to_owned(),
             || Arc::new(FileDownloader {}),
-        );
-
-        let exec_plan = ParquetExec::new(vec![file], None, 2048, rec_batch.schema());
-
-        datafusion::physical_plan::collect(Arc::new(exec_plan))
-            .await
-            .unwrap()
+        )
     }
 
     /// A downloader that simply reads from file system (file_id is the file path)
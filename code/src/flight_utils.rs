@@ -23,15 +23,21 @@ pub async fn flight_to_batches(
     flights: tonic::Streaming<FlightData>,
 ) -> Result<(String, impl Stream<Item = ArrowResult<RecordBatch>>), Box<dyn Error>> {
     let mut flights = Box::pin(flights);
-    let flight_data = flights.next().await.unwrap()?;
+    let flight_data = flights
+        .next()
+        .await
+        .ok_or_else(|| internal_err!("Flight stream should not be empty"))??;
     let schema = Arc::new(Schema::try_from(&flight_data)?);
     let cmd = descriptor_to_cmd(flight_data.flight_descriptor)?;
 
     // all the remaining stream messages should be dictionary and record batches
     let record_batch_stream = flights.map(move |flight_data_res| match flight_data_res {
-        Ok(flight_data) => {
-            flight_data_to_arrow_batch(&flight_data, Arc::clone(&schema)).unwrap()
-        }
+        Ok(flight_data) => flight_data_to_arrow_batch(&flight_data, Arc::clone(&schema))
+            .unwrap_or_else(|| {
+                Err(ArrowError::ExternalError(Box::new(internal_err!(
+                    "Flight should contain a record batch"
+                ))))
+            }),
         Err(e) => Err(ArrowError::ExternalError(Box::new(e))),
     });
     Ok((cmd, record_batch_stream))
@@ -123,6 +129,6 @@ fn descriptor_to_cmd(
     if descriptor.r#type != flight_descriptor::DescriptorType::Cmd as i32 {
         Err(Box::new(internal_err!("Descriptor type should be cmd")))
     } else {
-        Ok(String::from_utf8(descriptor.cmd).unwrap())
+        Ok(String::from_utf8(descriptor.cmd)?)
     }
 }
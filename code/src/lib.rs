@@ -8,6 +8,7 @@ pub mod models;
 pub mod plan_utils;
 pub mod serde;
 pub mod services;
+pub mod sketch;
 
 // include the generated protobuf source as a submodule
 #[allow(clippy::all)]
@@ -1,6 +1,6 @@
 use std::io::Cursor;
 
-use crate::datasource::HBeeTableDesc;
+use crate::datasource::{BroadcastTable, HBeeTableDesc};
 use crate::error::Result;
 use crate::internal_err;
 use crate::models::HCombAddress;
@@ -22,8 +22,9 @@ pub struct HBeePlanBytes {
 pub struct HBeeEvent {
     #[serde(rename = "id")]
     pub query_id: String,
+    /// The hcombs that receive the results, one per partition if shuffled
     #[serde(rename = "a")]
-    pub hcomb_address: HCombAddress,
+    pub hcomb_addresses: Vec<HCombAddress>,
     #[serde(rename = "p")]
     pub plan: HBeePlanBytes,
 }
@@ -34,8 +35,16 @@ impl HBeePlanBytes {
         table_desc: &HBeeTableDesc,
         sql: String,
         source: String,
+        broadcasts: &[BroadcastTable],
+        shuffle_keys: &[String],
     ) -> Result<Self> {
-        let proto_plan = proto_serde::serialize_hbee(table_desc, sql, source);
+        let proto_plan = proto_serde::serialize_hbee(
+            table_desc,
+            sql,
+            source,
+            broadcasts,
+            shuffle_keys,
+        )?;
 
         let mut buf = vec![];
         proto_plan
@@ -47,7 +56,15 @@ impl HBeePlanBytes {
         })
     }
 
-    pub fn parse(&self) -> Result<(HBeeTableDesc, String, String)> {
+    pub fn parse(
+        &self,
+    ) -> Result<(
+        HBeeTableDesc,
+        String,
+        String,
+        Vec<BroadcastTable>,
+        Vec<String>,
+    )> {
         let buf = base64::decode(&self.bytes)
             .map_err(|_| internal_err!("Could convert parse Base64"))?;
         let proto_plan = protobuf::HBeeScanNode::decode(&mut Cursor::new(buf))
@@ -12,5 +12,20 @@ pub type HCombAddress = String;
 #[derive(Clone, Debug)]
 pub struct SizedFile {
     pub key: String,
+    /// The length of the complete file, even if only some row groups are selected
     pub length: u64,
+    /// The row groups to read from the file, all of them if `None`
+    pub row_groups: Option<Vec<usize>>,
+    /// The length of the selected row groups, if they are known
+    pub row_groups_length: Option<u64>,
+    /// The values of the partition columns of the catalog for this file, in order
+    pub partitions: Vec<String>,
+}
+
+impl SizedFile {
+    /// The approximate number of bytes to read from the file:
+    /// the length of the selected row groups if known, of the complete file otherwise
+    pub fn read_length(&self) -> u64 {
+        self.row_groups_length.unwrap_or(self.length)
+    }
 }
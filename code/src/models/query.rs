@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::Deserialize;
 
 #[derive(PartialEq, Deserialize)]
@@ -11,6 +13,9 @@ pub struct BuzzStep {
     pub sql: String,
     pub name: String,
     pub partition_filter: Option<String>,
+    /// Overrides the amount of data each hbee should read for this step
+    #[serde(default)]
+    pub bytes_per_hbee: Option<u64>,
     pub step_type: BuzzStepType,
 }
 
@@ -20,8 +25,30 @@ pub struct HCombCapacity {
     pub zones: i16,
 }
 
+/// A typed value that replaces the `$name` or `:name` placeholders of the query
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum QueryParameter {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
 #[derive(Deserialize)]
 pub struct BuzzQuery {
     pub steps: Vec<BuzzStep>,
     pub capacity: HCombCapacity,
+    /// The values bound as literals into the SQL and the partition filters of the steps
+    #[serde(default)]
+    pub parameters: HashMap<String, QueryParameter>,
+    /// If true, the query is only planned and the plan is printed
+    #[serde(default)]
+    pub explain: bool,
+    /// If true, the query is only planned and the estimation of its cost is printed
+    #[serde(default)]
+    pub dry_run: bool,
+    /// If true, the cached results of the query are ignored and replaced
+    #[serde(default)]
+    pub refresh_cache: bool,
 }
@@ -1,4 +1,4 @@
-use datafusion::logical_plan::{self, Expr, Operator};
+use datafusion::logical_plan::{self, Expr, LogicalPlan, Operator};
 
 /// converts "A AND (B AND (C OR D))" => [A, B, C OR D]
 /// Copied from DataFusion filter pushdown
@@ -16,15 +16,32 @@ pub fn split_expr<'a>(predicate: &'a Expr, predicates: &mut Vec<&'a Expr>) {
     }
 }
 
-/// converts [A, B, C] => "(A AND B) AND C"
-pub fn merge_expr<'a>(predicates: &[Expr]) -> Expr {
+/// converts [A, B, C] => "(A AND B) AND C", None if there is no predicate
+pub fn merge_expr(predicates: &[Expr]) -> Option<Expr> {
     let mut predicates_iter = predicates.iter();
-    let mut merged_pred = predicates_iter
-        .next()
-        .expect("Merging requires at least one expr")
-        .clone();
-    while let Some(expr) = predicates_iter.next() {
+    let mut merged_pred = predicates_iter.next()?.clone();
+    for expr in predicates_iter {
         merged_pred = logical_plan::and(merged_pred, expr.clone());
     }
-    merged_pred
+    Some(merged_pred)
+}
+
+/// If the plan only keeps the first rows of its input, without filtering, ordering or
+/// aggregating them, returns the number of rows kept. The limit can then be applied
+/// to each part of the input independently and the plan can stop as soon as
+/// it has received enough rows.
+pub fn limit_only(plan: &LogicalPlan) -> Option<usize> {
+    match plan {
+        LogicalPlan::Limit { n, input } if is_projected_scan(input) => Some(*n),
+        LogicalPlan::Projection { input, .. } => limit_only(input),
+        _ => None,
+    }
+}
+
+fn is_projected_scan(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::TableScan { .. } => true,
+        LogicalPlan::Projection { input, .. } => is_projected_scan(input),
+        _ => false,
+    }
 }
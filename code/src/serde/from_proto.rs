@@ -1,15 +1,39 @@
 use std::sync::Arc;
 
-use crate::datasource::{HBeeTableDesc, HCombTableDesc, S3ParquetTable};
+use crate::datasource::{
+    BroadcastTable, HBeeTableDesc, HCombTableDesc, LocalParquetTable, S3ParquetTable,
+};
 use crate::error::Result;
 use crate::internal_err;
 use crate::models::SizedFile;
 use crate::protobuf;
 use arrow::ipc::convert;
 
+fn deserialize_files(files: &[protobuf::SizedFile]) -> Vec<SizedFile> {
+    files
+        .iter()
+        .map(|sized_file| SizedFile {
+            key: sized_file.key.to_owned(),
+            length: sized_file.length,
+            row_groups: sized_file.row_groups.as_ref().map(|row_groups| {
+                row_groups.indexes.iter().map(|i| *i as usize).collect()
+            }),
+            // only used by the fuse to estimate the plan
+            row_groups_length: None,
+            partitions: sized_file.partitions.clone(),
+        })
+        .collect()
+}
+
 pub fn deserialize_hbee(
     message: protobuf::HBeeScanNode,
-) -> Result<(HBeeTableDesc, String, String)> {
+) -> Result<(
+    HBeeTableDesc,
+    String,
+    String,
+    Vec<BroadcastTable>,
+    Vec<String>,
+)> {
     let schema = convert::schema_from_bytes(&message.schema)?;
     let scan = message
         .scan
@@ -18,29 +42,54 @@ pub fn deserialize_hbee(
         protobuf::h_bee_scan_node::Scan::S3Parquet(scan_node) => S3ParquetTable::new(
             scan_node.region.to_owned(),
             scan_node.bucket.to_owned(),
-            scan_node
-                .files
-                .iter()
-                .map(|sized_file| SizedFile {
-                    key: sized_file.key.to_owned(),
-                    length: sized_file.length,
-                })
-                .collect(),
+            deserialize_files(&scan_node.files),
             Arc::new(schema),
         ),
+        protobuf::h_bee_scan_node::Scan::LocalParquet(scan_node) => {
+            LocalParquetTable::new(
+                scan_node.dir.to_owned(),
+                deserialize_files(&scan_node.files),
+                Arc::new(schema),
+            )
+        }
+    };
+    let provider = if message.partition_schema.is_empty() {
+        provider
+    } else {
+        let partition_schema = convert::schema_from_bytes(&message.partition_schema)?;
+        provider.with_partition_cols(partition_schema.fields().clone())
     };
 
-    Ok((provider, message.sql, message.source))
+    let broadcasts = message
+        .broadcasts
+        .into_iter()
+        .map(|broadcast| BroadcastTable::try_from_ipc(broadcast.name, &broadcast.data))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((
+        provider,
+        message.sql,
+        message.source,
+        broadcasts,
+        message.shuffle_keys,
+    ))
 }
 
 pub fn deserialize_hcomb(
     message: protobuf::HCombScanNode,
-) -> Result<(HCombTableDesc, String, String)> {
-    let schema = convert::schema_from_bytes(&message.schema)?;
-    let provider = HCombTableDesc::new(
-        message.query_id.to_owned(),
-        message.nb_hbee as usize,
-        Arc::new(schema),
-    );
-    Ok((provider, message.sql, message.source))
+) -> Result<(Vec<(HCombTableDesc, String)>, String)> {
+    let inputs = message
+        .inputs
+        .into_iter()
+        .map(|input| {
+            let schema = convert::schema_from_bytes(&input.schema)?;
+            let provider = HCombTableDesc::new(
+                input.query_id,
+                input.nb_hbee as usize,
+                Arc::new(schema),
+            );
+            Ok((provider, input.source))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((inputs, message.sql))
 }
@@ -11,29 +11,56 @@ mod tests {
     use std::sync::Arc;
 
     use super::*;
-    use crate::datasource::{HCombTableDesc, S3ParquetTable};
+    use crate::datasource::{
+        BroadcastTable, HCombTableDesc, LocalParquetTable, S3ParquetTable,
+    };
     use crate::models::SizedFile;
+    use arrow::array::{Int32Array, StringArray};
     use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arrow::record_batch::RecordBatch;
 
     #[test]
     fn roundtrip_parquet() {
         let parquet_table = S3ParquetTable::new(
             "south-pole-1".to_owned(),
             "santa".to_owned(),
-            vec![SizedFile {
-                key: "gift1".to_owned(),
-                length: 1,
-            }],
+            vec![
+                SizedFile {
+                    key: "gift1".to_owned(),
+                    length: 1,
+                    row_groups: None,
+                    row_groups_length: None,
+                    partitions: vec![],
+                },
+                SizedFile {
+                    key: "gift2".to_owned(),
+                    length: 2,
+                    row_groups: Some(vec![0, 2]),
+                    row_groups_length: None,
+                    partitions: vec![],
+                },
+            ],
             Arc::new(test_schema()),
         );
         let sql = "SELECT * FROM swag";
         let source = "swag";
 
-        let proto =
-            to_proto::serialize_hbee(&parquet_table, sql.to_owned(), source.to_owned());
+        let proto = to_proto::serialize_hbee(
+            &parquet_table,
+            sql.to_owned(),
+            source.to_owned(),
+            &[],
+            &["name".to_owned()],
+        )
+        .unwrap();
 
-        let (transfered_table, transfered_sql, transfered_source) =
-            from_proto::deserialize_hbee(proto).unwrap();
+        let (
+            transfered_table,
+            transfered_sql,
+            transfered_source,
+            transfered_broadcasts,
+            transfered_shuffle_keys,
+        ) = from_proto::deserialize_hbee(proto).unwrap();
 
         assert_eq!(sql, transfered_sql);
         assert_eq!(source, transfered_source);
@@ -41,27 +68,112 @@ mod tests {
             format!("{:?}", parquet_table),
             format!("{:?}", transfered_table)
         );
+        assert_eq!(transfered_broadcasts.len(), 0);
+        assert_eq!(transfered_shuffle_keys, vec!["name".to_owned()]);
+    }
+
+    #[test]
+    fn roundtrip_local_parquet() {
+        let parquet_table = LocalParquetTable::new(
+            "/data/santa".to_owned(),
+            vec![SizedFile {
+                key: "year=2020/gift1".to_owned(),
+                length: 1,
+                row_groups: Some(vec![1]),
+                row_groups_length: None,
+                partitions: vec!["2020".to_owned()],
+            }],
+            Arc::new(test_schema()),
+        )
+        .with_partition_cols(vec![Field::new("year", DataType::Int32, false)]);
+
+        let proto = to_proto::serialize_hbee(
+            &parquet_table,
+            "SELECT * FROM swag".to_owned(),
+            "swag".to_owned(),
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        let (transfered_table, _, _, _, _) = from_proto::deserialize_hbee(proto).unwrap();
+        assert_eq!(
+            format!("{:?}", parquet_table),
+            format!("{:?}", transfered_table)
+        );
+    }
+
+    #[test]
+    fn roundtrip_broadcast() {
+        let parquet_table = S3ParquetTable::new(
+            "south-pole-1".to_owned(),
+            "santa".to_owned(),
+            vec![],
+            Arc::new(test_schema()),
+        );
+        let lookup_schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("label", DataType::Utf8, false),
+        ]));
+        let lookup_batch = RecordBatch::try_new(
+            lookup_schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["reindeer", "elf"])),
+            ],
+        )
+        .unwrap();
+        let broadcast =
+            BroadcastTable::new("lookup".to_owned(), lookup_schema, vec![lookup_batch]);
+
+        let proto = to_proto::serialize_hbee(
+            &parquet_table,
+            "SELECT * FROM swag JOIN lookup ON id = gift_id".to_owned(),
+            "swag".to_owned(),
+            &[broadcast.clone()],
+            &[],
+        )
+        .unwrap();
+
+        let (_, _, _, transfered_broadcasts, _) =
+            from_proto::deserialize_hbee(proto).unwrap();
+
+        assert_eq!(transfered_broadcasts.len(), 1);
+        assert_eq!(
+            format!("{:?}", broadcast),
+            format!("{:?}", transfered_broadcasts[0])
+        );
     }
 
     #[test]
     fn roundtrip_hcomb() {
-        let hcomb_table =
-            HCombTableDesc::new("test_query_id".to_owned(), 16, Arc::new(test_schema()));
-        let sql = "SELECT * FROM swag";
-        let source = "swag";
+        let inputs = vec![
+            (
+                HCombTableDesc::new(
+                    "test_query_id".to_owned(),
+                    16,
+                    Arc::new(test_schema()),
+                ),
+                "swag".to_owned(),
+            ),
+            (
+                HCombTableDesc::new(
+                    "other_query_id".to_owned(),
+                    4,
+                    Arc::new(test_schema()),
+                ),
+                "other_swag".to_owned(),
+            ),
+        ];
+        let sql = "SELECT * FROM swag JOIN other_swag ON swag.id = other_swag.id";
 
-        let proto =
-            to_proto::serialize_hcomb(&hcomb_table, sql.to_owned(), source.to_owned());
+        let proto = to_proto::serialize_hcomb(&inputs, sql.to_owned());
 
-        let (transfered_table, transfered_sql, transfered_source) =
+        let (transfered_inputs, transfered_sql) =
             from_proto::deserialize_hcomb(proto).unwrap();
 
         assert_eq!(sql, transfered_sql);
-        assert_eq!(source, transfered_source);
-        assert_eq!(
-            format!("{:?}", hcomb_table),
-            format!("{:?}", transfered_table)
-        );
+        assert_eq!(format!("{:?}", inputs), format!("{:?}", transfered_inputs));
     }
 
     fn test_schema() -> Schema {
@@ -1,8 +1,26 @@
-use crate::datasource::{HBeeTableDesc, HCombTableDesc};
+use crate::datasource::{BroadcastTable, HBeeTableDesc, HCombTableDesc};
+use crate::error::Result;
+use crate::models::SizedFile;
 use crate::protobuf;
 use arrow::datatypes::Schema;
 use arrow::ipc::{writer, writer::EncodedData, writer::IpcWriteOptions};
 
+fn serialize_files(files: &[SizedFile]) -> Vec<protobuf::SizedFile> {
+    files
+        .iter()
+        .map(|sized_file| protobuf::SizedFile {
+            key: sized_file.key.to_owned(),
+            length: sized_file.length,
+            row_groups: sized_file.row_groups.as_ref().map(|row_groups| {
+                protobuf::RowGroupSelection {
+                    indexes: row_groups.iter().map(|i| *i as u32).collect(),
+                }
+            }),
+            partitions: sized_file.partitions.clone(),
+        })
+        .collect()
+}
+
 fn serialize_schema(schema: &Schema) -> EncodedData {
     let options = IpcWriteOptions::default();
     let data_gen = writer::IpcDataGenerator::default();
@@ -13,43 +31,67 @@ pub fn serialize_hbee(
     hbee_table: &HBeeTableDesc,
     sql: String,
     source: String,
-) -> protobuf::HBeeScanNode {
-    let schema = serialize_schema(&hbee_table.schema());
+    broadcasts: &[BroadcastTable],
+    shuffle_keys: &[String],
+) -> Result<protobuf::HBeeScanNode> {
+    let schema = serialize_schema(&hbee_table.file_schema());
+    let partition_cols = hbee_table.partition_cols();
+    let partition_schema = if partition_cols.is_empty() {
+        vec![]
+    } else {
+        serialize_schema(&Schema::new(partition_cols.to_vec())).ipc_message
+    };
     let scan = match hbee_table {
         HBeeTableDesc::S3Parquet(table) => Some(
             protobuf::h_bee_scan_node::Scan::S3Parquet(protobuf::S3ParquetScanNode {
                 region: table.region().to_owned(),
                 bucket: table.bucket().to_owned(),
-                files: table
-                    .files()
-                    .iter()
-                    .map(|sized_file| protobuf::SizedFile {
-                        key: sized_file.key.to_owned(),
-                        length: sized_file.length,
-                    })
-                    .collect(),
+                files: serialize_files(table.files()),
             }),
         ),
+        HBeeTableDesc::LocalParquet(table) => {
+            Some(protobuf::h_bee_scan_node::Scan::LocalParquet(
+                protobuf::LocalParquetScanNode {
+                    dir: table.dir().to_owned(),
+                    files: serialize_files(table.files()),
+                },
+            ))
+        }
     };
-    protobuf::HBeeScanNode {
+    let broadcasts = broadcasts
+        .iter()
+        .map(|broadcast| {
+            Ok(protobuf::BroadcastTable {
+                name: broadcast.name().to_owned(),
+                data: broadcast.to_ipc()?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(protobuf::HBeeScanNode {
         scan,
         sql,
         schema: schema.ipc_message,
         source,
-    }
+        broadcasts,
+        shuffle_keys: shuffle_keys.to_vec(),
+        partition_schema,
+    })
 }
 
 pub fn serialize_hcomb(
-    hcomb_table: &HCombTableDesc,
+    inputs: &[(HCombTableDesc, String)],
     sql: String,
-    source: String,
 ) -> protobuf::HCombScanNode {
-    let schema = serialize_schema(&hcomb_table.schema());
     protobuf::HCombScanNode {
-        query_id: hcomb_table.query_id().to_owned(),
-        nb_hbee: hcomb_table.nb_hbee() as u32,
-        schema: schema.ipc_message,
+        inputs: inputs
+            .iter()
+            .map(|(hcomb_table, source)| protobuf::HCombInput {
+                query_id: hcomb_table.query_id().to_owned(),
+                nb_hbee: hcomb_table.nb_hbee() as u32,
+                schema: serialize_schema(&hcomb_table.schema()).ipc_message,
+                source: source.clone(),
+            })
+            .collect(),
         sql,
-        source,
     }
 }
@@ -0,0 +1,232 @@
+//! Estimation of the resources a distributed plan will use, without running it
+
+use std::collections::HashSet;
+
+use super::query_planner::{DistributedPlan, StagePlan};
+use serde::Serialize;
+
+/// The figures used to translate the size of a plan into a cost, in USD.
+/// The defaults correspond to the AWS Lambda and S3 public prices (us-east-1)
+/// and to the hbee configuration of the provided infrastructure.
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    /// The memory allocated to each hbee
+    pub hbee_memory_gb: f64,
+    /// The speed at which an hbee reads and processes data, in bytes per second
+    pub hbee_throughput: f64,
+    /// The minimum billed duration of an hbee, in seconds
+    pub hbee_min_duration: f64,
+    pub lambda_price_per_gb_second: f64,
+    pub lambda_price_per_request: f64,
+    pub s3_price_per_get: f64,
+    /// The average amount of data fetched by each S3 GET request
+    pub s3_bytes_per_get: u64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            hbee_memory_gb: 2.,
+            hbee_throughput: 100. * 1024. * 1024.,
+            hbee_min_duration: 0.1,
+            lambda_price_per_gb_second: 0.0000166667,
+            lambda_price_per_request: 0.0000002,
+            s3_price_per_get: 0.0000004,
+            s3_bytes_per_get: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// The estimated resources of a stage
+#[derive(Debug, Serialize)]
+pub struct StageEstimate {
+    pub name: String,
+    pub nb_hbee: usize,
+    pub nb_hcomb: usize,
+    pub nb_files: usize,
+    pub scanned_bytes: u64,
+    pub nb_pruned_partitions: usize,
+}
+
+/// The estimated resources and cost of a query
+#[derive(Debug, Serialize)]
+pub struct QueryEstimate {
+    pub nb_hbee: usize,
+    /// The number of hcombs required, they are shared by the stages
+    pub nb_hcomb: usize,
+    pub nb_files: usize,
+    pub scanned_bytes: u64,
+    pub nb_s3_get: u64,
+    /// The cost of the hbee invocations and the S3 requests, in USD.
+    /// The hcombs are long running containers so they are not included.
+    pub cost_usd: f64,
+    pub stages: Vec<StageEstimate>,
+}
+
+impl DistributedPlan {
+    /// Estimate the resources the plan would use if it was run
+    pub fn estimate(&self, cost_model: &CostModel) -> QueryEstimate {
+        let mut stages = vec![];
+        let mut nb_s3_get = 0;
+        let mut hbee_seconds = 0.;
+        for stage in &self.stages {
+            let hbee_bytes = hbee_scan_sizes(stage);
+            for (nb_files, bytes) in &hbee_bytes {
+                // at least one request is needed for the footer of each file
+                nb_s3_get += *nb_files as u64
+                    + (bytes + cost_model.s3_bytes_per_get - 1)
+                        / cost_model.s3_bytes_per_get;
+                hbee_seconds += f64::max(
+                    *bytes as f64 / cost_model.hbee_throughput,
+                    cost_model.hbee_min_duration,
+                );
+            }
+            stages.push(StageEstimate {
+                name: stage.name.clone(),
+                nb_hbee: hbee_bytes.len(),
+                nb_hcomb: stage.zones.len(),
+                nb_files: count_files(stage),
+                scanned_bytes: hbee_bytes.iter().map(|(_, bytes)| bytes).sum(),
+                nb_pruned_partitions: stage
+                    .hbee_steps
+                    .iter()
+                    .map(|step| step.pruned_partitions.len())
+                    .sum(),
+            });
+        }
+        let cost_usd = self.nb_hbee as f64 * cost_model.lambda_price_per_request
+            + hbee_seconds
+                * cost_model.hbee_memory_gb
+                * cost_model.lambda_price_per_gb_second
+            + nb_s3_get as f64 * cost_model.s3_price_per_get;
+        QueryEstimate {
+            nb_hbee: self.nb_hbee,
+            nb_hcomb: stages.iter().map(|stage| stage.nb_hcomb).max().unwrap_or(0),
+            nb_files: stages.iter().map(|stage| stage.nb_files).sum(),
+            scanned_bytes: stages.iter().map(|stage| stage.scanned_bytes).sum(),
+            nb_s3_get,
+            cost_usd,
+            stages,
+        }
+    }
+}
+
+/// The distinct files read by the hbees of the stage
+fn count_files(stage: &StagePlan) -> usize {
+    stage
+        .zones
+        .iter()
+        .flat_map(|zone| &zone.hbee)
+        .flat_map(|hbee| {
+            let location = hbee.table.location();
+            hbee.table
+                .files()
+                .iter()
+                .map(move |file| (location.clone(), file.key.as_str()))
+        })
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// The number of files and the approximate number of bytes read by each hbee of the stage.
+/// The files that are split by row groups only count the row groups read by the hbee.
+fn hbee_scan_sizes(stage: &StagePlan) -> Vec<(usize, u64)> {
+    stage
+        .zones
+        .iter()
+        .flat_map(|zone| &zone.hbee)
+        .map(|hbee| {
+            let files = hbee.table.files();
+            let bytes = files.iter().map(|file| file.read_length()).sum();
+            (files.len(), bytes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::{CatalogTable, MockSplittableTable};
+    use crate::models::query::{BuzzStep, BuzzStepType};
+    use crate::services::fuse::QueryPlanner;
+
+    async fn plan(catalog: CatalogTable) -> DistributedPlan {
+        plan_sql(catalog, "SELECT * FROM test").await
+    }
+
+    async fn plan_sql(catalog: CatalogTable, sql: &str) -> DistributedPlan {
+        let mut planner = QueryPlanner::new();
+        planner.add_catalog("test", catalog);
+        let steps = vec![
+            BuzzStep {
+                sql: sql.to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT * FROM mapper".to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+        planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect("The planner failed on a simple query")
+    }
+
+    #[tokio::test]
+    async fn test_estimate_simple_query() {
+        let catalog = CatalogTable::new(Box::new(MockSplittableTable::new(5, 0)));
+        let estimate = plan(catalog).await.estimate(&CostModel::default());
+
+        assert_eq!(estimate.nb_hbee, 5);
+        assert_eq!(estimate.nb_hcomb, 1);
+        assert_eq!(estimate.nb_files, 5);
+        // the mock files are 999999999 bytes long
+        assert_eq!(estimate.scanned_bytes, 5 * 999999999);
+        // 1 footer + 120 chunks of 8MiB per file
+        assert_eq!(estimate.nb_s3_get, 5 * 121);
+        assert!(estimate.cost_usd > 0.);
+        assert_eq!(estimate.stages.len(), 1);
+        assert_eq!(estimate.stages[0].nb_hbee, 5);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_split_files() {
+        // the mock files are 999999999 bytes long with 4 row groups
+        let catalog = CatalogTable::new(Box::new(
+            MockSplittableTable::new(2, 0).with_row_groups(4),
+        ))
+        .with_bytes_per_hbee(500_000_000);
+        let plan = plan(catalog).await;
+        let estimate = plan.estimate(&CostModel::default());
+
+        assert_eq!(estimate.nb_hbee, 4);
+        assert_eq!(estimate.nb_files, 2);
+        // each file is read by 2 hbees of 2 row groups of 249999999 bytes
+        assert_eq!(estimate.scanned_bytes, 4 * 499999998);
+
+        // the explained plan reports the size of the row groups read by each hbee
+        let explained = plan.to_string();
+        assert!(explained.contains("1 file(s), 499999998 bytes"));
+        assert!(explained.contains("(499999998 of 999999999 bytes) row groups [0, 1]"));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_file_read_twice() {
+        let catalog = CatalogTable::new(Box::new(MockSplittableTable::new(1, 0)));
+        let plan =
+            plan_sql(catalog, "SELECT * FROM test UNION ALL SELECT * FROM test").await;
+        let estimate = plan.estimate(&CostModel::default());
+
+        // each branch reads the complete file
+        assert_eq!(estimate.nb_hbee, 2);
+        assert_eq!(estimate.nb_files, 1);
+        assert_eq!(estimate.scanned_bytes, 2 * 999999999);
+    }
+}
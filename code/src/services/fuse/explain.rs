@@ -0,0 +1,117 @@
+//! Human readable description of a distributed plan, without running it
+
+use std::fmt;
+
+use super::query_planner::{DistributedPlan, HBeePlan, StagePlan};
+
+impl fmt::Display for DistributedPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Distributed plan: {} stage(s), {} hbee(s)",
+            self.stages.len(),
+            self.nb_hbee
+        )?;
+        for stage in &self.stages {
+            write!(f, "{}", stage)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StagePlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stage {}", self.name)?;
+        if let Some(upstream) = &self.upstream {
+            write!(f, " (fed by stage {})", upstream)?;
+        }
+        if self.is_output {
+            write!(f, " [output]")?;
+        }
+        writeln!(f)?;
+
+        for hbee_step in &self.hbee_steps {
+            writeln!(f, "  HBee step {}: {}", hbee_step.name, hbee_step.sql)?;
+            writeln!(f, "    plan: {}", indent(&hbee_step.plan, 6))?;
+            if hbee_step.pruned_partitions.is_empty() {
+                writeln!(f, "    pruned partitions: none")?;
+            } else {
+                writeln!(
+                    f,
+                    "    pruned partitions: {}",
+                    hbee_step.pruned_partitions.join(", ")
+                )?;
+            }
+        }
+        writeln!(f, "  HComb step {}", self.name)?;
+        writeln!(f, "    plan: {}", indent(&self.hcomb_plan, 6))?;
+        if let Some(limit) = self.limit {
+            writeln!(
+                f,
+                "    limit: {} rows, hbees are scheduled until it is reached",
+                limit
+            )?;
+        }
+
+        writeln!(f, "  {} zone(s)", self.zones.len())?;
+        for (i, zone) in self.zones.iter().enumerate() {
+            let nb_results = zone
+                .hcomb
+                .inputs
+                .iter()
+                .map(|input| input.table.nb_hbee())
+                .sum::<usize>();
+            writeln!(
+                f,
+                "  - zone {}: hcomb waits for {} hbee result(s), {} hbee(s) scheduled",
+                i,
+                nb_results,
+                zone.hbee.len()
+            )?;
+            for (j, hbee) in zone.hbee.iter().enumerate() {
+                write_hbee(f, j, hbee)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The files that are split by row groups are reported with the size of the row groups read
+fn write_hbee(f: &mut fmt::Formatter, idx: usize, hbee: &HBeePlan) -> fmt::Result {
+    let files = hbee.table.files();
+    let location = hbee.table.location();
+    let total_bytes = files.iter().map(|file| file.read_length()).sum::<u64>();
+    write!(
+        f,
+        "    - hbee {}: {} file(s), {} bytes",
+        idx,
+        files.len(),
+        total_bytes
+    )?;
+    if !hbee.shuffle_keys.is_empty() {
+        write!(f, ", shuffled by {}", hbee.shuffle_keys.join(", "))?;
+    }
+    writeln!(f)?;
+    for file in files {
+        write!(f, "      {}/{}", location, file.key)?;
+        match &file.row_groups {
+            Some(row_groups) => write!(
+                f,
+                " ({} of {} bytes) row groups {:?}",
+                file.read_length(),
+                file.length,
+                row_groups
+            )?,
+            None => write!(f, " ({} bytes)", file.length)?,
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+/// Indent the continuation lines of a multi-line plan
+fn indent(text: &str, nb_spaces: usize) -> String {
+    text.lines()
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}", " ".repeat(nb_spaces)))
+}
@@ -1,23 +1,58 @@
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
 
+use super::estimate::{CostModel, QueryEstimate};
 use super::hbee_scheduler::HBeeScheduler;
 use super::hcomb_manager::HCombManager;
 use super::hcomb_scheduler::HCombScheduler;
-use super::query_planner::QueryPlanner;
-use crate::datasource::CatalogTable;
+use super::query_parameters;
+use super::query_planner::{DistributedPlan, QueryPlanner, StagePlan};
+use super::result_cache::{ResultCache, StageResults};
+use crate::datasource::{BroadcastTable, CatalogConfig, CatalogTable};
 use crate::error::Result;
+use crate::internal_err;
 use crate::models::query::BuzzQuery;
+use crate::models::HCombAddress;
 use arrow::record_batch::RecordBatch;
 use arrow::util::pretty;
 use chrono::Utc;
-use futures::{StreamExt, TryStreamExt};
+use futures::future::{select, Either};
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::Serialize;
 use tokio::join;
 
+/// The type of the result streams returned by the hcombs
+type HCombStream = Pin<Box<dyn Stream<Item = arrow::error::Result<RecordBatch>>>>;
+
+/// The number of hbees scheduled in the first wave of a stage with a limit,
+/// each following wave is twice as large
+const LIMIT_FIRST_WAVE: usize = 2;
+/// The time given to each wave of hbees to reach the limit before scheduling the next one
+const LIMIT_WAVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Statistics about a query run
+#[derive(Debug, Default, Serialize)]
+pub struct QueryStats {
+    /// True if the results were served by the result cache
+    pub cache_hit: bool,
+    pub nb_hbee: usize,
+    pub duration_ms: u64,
+    /// The description of the distributed plan, if the query was only explained
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explain: Option<String>,
+    /// The estimate of the data scanned by the query and of its cost, for a dry run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<QueryEstimate>,
+}
+
 pub struct FuseService {
     hbee_scheduler: Box<dyn HBeeScheduler>,
     hcomb_manager: Box<dyn HCombManager>,
     hcomb_scheduler: Box<dyn HCombScheduler>,
     query_planner: QueryPlanner,
+    cost_model: CostModel,
+    result_cache: Option<ResultCache>,
 }
 
 impl FuseService {
@@ -32,6 +67,40 @@ impl FuseService {
             hcomb_manager,
             hcomb_scheduler,
             query_planner,
+            cost_model: CostModel::default(),
+            result_cache: None,
+        }
+    }
+
+    /// Customize the prices used to estimate the cost of the queries
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = cost_model;
+        self
+    }
+
+    /// Serve the results of queries that were already run on the same files from the cache
+    pub fn with_result_cache(mut self, result_cache: ResultCache) -> Self {
+        self.result_cache = Some(result_cache);
+        self
+    }
+
+    /// Remove the cached results of the given query, if any
+    pub async fn invalidate_cached_results(&mut self, query: BuzzQuery) -> Result<()> {
+        if self.result_cache.is_none() {
+            return Ok(());
+        }
+        let plan = self.plan_only(query).await?;
+        match &self.result_cache {
+            Some(cache) => cache.invalidate(&plan),
+            None => Ok(()),
+        }
+    }
+
+    /// Remove all the cached results
+    pub fn clear_result_cache(&self) -> Result<()> {
+        match &self.result_cache {
+            Some(cache) => cache.invalidate_all(),
+            None => Ok(()),
         }
     }
 
@@ -39,37 +108,187 @@ impl FuseService {
         self.query_planner.add_catalog(name, table);
     }
 
-    pub async fn run(&mut self, query: BuzzQuery) -> Result<()> {
+    /// Register the catalogs defined in a JSON or YAML file (see `CatalogConfig`)
+    pub async fn add_catalogs_from_file(&mut self, path: &str) -> Result<()> {
+        for (name, table) in CatalogConfig::from_file(path)?.into_tables().await? {
+            self.add_catalog(&name, table);
+        }
+        Ok(())
+    }
+
+    pub fn add_broadcast_table(&mut self, table: BroadcastTable) -> Result<()> {
+        self.query_planner.add_broadcast_table(table)
+    }
+
+    /// Plan the query and describe how it would be distributed,
+    /// without starting any hcomb or hbee.
+    pub async fn explain(&mut self, query: BuzzQuery) -> Result<String> {
+        Ok(self.plan_only(query).await?.to_string())
+    }
+
+    /// Plan the query and estimate the amount of data it would scan and its cost,
+    /// without starting any hcomb or hbee.
+    pub async fn estimate(&mut self, query: BuzzQuery) -> Result<QueryEstimate> {
+        Ok(self.plan_only(query).await?.estimate(&self.cost_model))
+    }
+
+    async fn plan_only(&mut self, query: BuzzQuery) -> Result<DistributedPlan> {
+        let query_id = format!("query-{}", Utc::now().to_rfc3339());
+        let steps = query_parameters::bind_steps(query.steps, &query.parameters)?;
+        self.query_planner
+            .plan(query_id, steps, query.capacity.zones)
+            .await
+    }
+
+    /// Run the query, or only plan it if it should be explained or estimated.
+    /// The returned stats then contain the explained plan or the estimate.
+    pub async fn run(&mut self, query: BuzzQuery) -> Result<QueryStats> {
+        if query.explain {
+            return Ok(QueryStats {
+                explain: Some(self.explain(query).await?),
+                ..QueryStats::default()
+            });
+        }
+        if query.dry_run {
+            return Ok(QueryStats {
+                estimate: Some(self.estimate(query).await?),
+                ..QueryStats::default()
+            });
+        }
         let start_run = Instant::now();
-        let addresses_future = self.hcomb_manager.find_or_start(&query.capacity);
+        let steps = query_parameters::bind_steps(query.steps, &query.parameters)?;
         let query_id = format!("query-{}", Utc::now().to_rfc3339());
-        let plan_future =
-            self.query_planner
-                .plan(query_id.clone(), query.steps, query.capacity.zones);
-        let (addresses, plan) = join!(addresses_future, plan_future);
-        let addresses = addresses?;
-        let plan = plan?;
-
-        assert!(
-            addresses.len() >= plan.zones.len(),
-            "Not enough hcombs (found {}) were started for plan (expected {})",
-            addresses.len(),
-            plan.zones.len()
-        );
+        let (addresses, plan) = if let Some(cache) = &self.result_cache {
+            // the plan is needed to look up the cache, before starting the hcombs
+            let plan = self
+                .query_planner
+                .plan(query_id, steps, query.capacity.zones)
+                .await?;
+            if query.refresh_cache {
+                cache.invalidate(&plan)?;
+            } else if let Some(results) = Self::cached_results(cache, &plan) {
+                println!("[fuse] serving results from the cache");
+                for (_, batches) in &results {
+                    pretty::print_batches(batches)?;
+                }
+                return Ok(QueryStats {
+                    cache_hit: true,
+                    nb_hbee: 0,
+                    duration_ms: start_run.elapsed().as_millis() as u64,
+                    ..QueryStats::default()
+                });
+            }
+            let addresses = self.hcomb_manager.find_or_start(&query.capacity).await?;
+            (addresses, plan)
+        } else {
+            let addresses_future = self.hcomb_manager.find_or_start(&query.capacity);
+            let plan_future =
+                self.query_planner
+                    .plan(query_id, steps, query.capacity.zones);
+            let (addresses, plan) = join!(addresses_future, plan_future);
+            (addresses?, plan?)
+        };
 
-        if plan.zones.len() == 0 {
-            println!("no work scheduled, empty result");
-            return Ok(());
+        let nb_zones = plan
+            .stages
+            .iter()
+            .map(|stage| stage.zones.len())
+            .max()
+            .unwrap_or(0);
+        if addresses.len() < nb_zones {
+            return Err(internal_err!(
+                "Not enough hcombs (found {}) were started for plan (expected {})",
+                addresses.len(),
+                nb_zones
+            ));
+        }
+        let distinct_addresses = addresses.iter().collect::<HashSet<_>>();
+        if distinct_addresses.len() < addresses.len() {
+            return Err(internal_err!(
+                "The same hcomb was assigned to several zones: {:?}",
+                addresses
+            ));
+        }
+
+        // results of the stages that are consumed by other stages, one vec per zone
+        let mut intermediate_results = HashMap::new();
+        let mut output_results: StageResults = vec![];
+        for stage in &plan.stages {
+            println!("[fuse] run stage {}", stage.name);
+            let results = self
+                .run_stage(&addresses, stage, &mut intermediate_results)
+                .await?;
+            if stage.is_output {
+                // the hcombs each own a part of the results
+                let results = results.concat();
+                pretty::print_batches(&results)?;
+                output_results.push((stage.name.clone(), results));
+            } else {
+                intermediate_results.insert(stage.name.clone(), results);
+            }
+        }
+
+        if let Some(cache) = &self.result_cache {
+            // the results were computed anyway, a failing cache should not fail the query
+            if let Err(e) = cache.put(&plan, &output_results) {
+                println!("[fuse] results could not be cached: {}", e);
+            }
+        }
+
+        let duration_ms = start_run.elapsed().as_millis() as u64;
+        println!("[fuse] total run duration: {}", duration_ms);
+        Ok(QueryStats {
+            cache_hit: false,
+            nb_hbee: plan.nb_hbee,
+            duration_ms,
+            ..QueryStats::default()
+        })
+    }
+
+    /// The cached results of the plan, if the cache could be read
+    fn cached_results(
+        cache: &ResultCache,
+        plan: &DistributedPlan,
+    ) -> Option<StageResults> {
+        match cache.get(plan) {
+            Ok(results) => results,
+            Err(e) => {
+                println!("[fuse] result cache could not be read: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Runs the given stage and collects its results, one vector of batches per zone
+    async fn run_stage(
+        &self,
+        addresses: &[HCombAddress],
+        stage: &StagePlan,
+        intermediate_results: &mut HashMap<String, Vec<Vec<RecordBatch>>>,
+    ) -> Result<Vec<Vec<RecordBatch>>> {
+        if stage.zones.len() == 0 {
+            println!("[fuse] no work scheduled, empty result");
+            return Ok(vec![]);
         }
 
         // connect to the hcombs to init the query and get result handle
         println!("[fuse] schedule hcombs");
-        let future_hcombs = (0..plan.zones.len()).map(|i| {
+        let hcomb_inputs = stage
+            .zones
+            .iter()
+            .map(|zone| {
+                zone.hcomb
+                    .inputs
+                    .iter()
+                    .map(|input| (input.table.clone(), input.source.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let future_hcombs = stage.zones.iter().enumerate().map(|(i, zone)| {
             self.hcomb_scheduler.schedule(
                 &addresses[i],
-                &plan.zones[i].hcomb.table,
-                plan.zones[i].hcomb.sql.clone(),
-                plan.zones[i].hcomb.source.clone(),
+                &hcomb_inputs[i],
+                zone.hcomb.sql.clone(),
             )
         });
         let hcomb_streams = futures::stream::iter(future_hcombs)
@@ -77,24 +296,129 @@ impl FuseService {
             .try_collect::<Vec<_>>()
             .await?;
 
-        // when hcombs are ready, start hbees!
+        // when hcombs are ready, send them their input
+        match &stage.upstream {
+            None if stage.limit.is_some() => {
+                return self.run_limit_stage(addresses, stage, hcomb_streams).await;
+            }
+            None => self.schedule_hbees(addresses, stage).await?,
+            Some(upstream) => {
+                let upstream_results =
+                    intermediate_results.remove(upstream).ok_or_else(|| {
+                        internal_err!("Results of stage {} not found", upstream)
+                    })?;
+                println!(
+                    "[fuse] forward {} results from {}",
+                    upstream_results.len(),
+                    upstream
+                );
+                let hcomb_table = &stage.zones[0].hcomb.inputs[0].table;
+                for results in upstream_results {
+                    self.hcomb_scheduler
+                        .forward(
+                            &addresses[0],
+                            hcomb_table.query_id().to_owned(),
+                            results,
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        // wait for hcombs to collect all the results
+        println!("[fuse] collect hcombs");
+        let mut results = vec![];
+        for hcomb_stream in hcomb_streams {
+            results.push(hcomb_stream.try_collect::<Vec<_>>().await?);
+        }
+        Ok(results)
+    }
+
+    /// Schedules the hbees of a stage with a limit by waves of growing size, until the
+    /// hcomb has collected enough rows and closed its result stream.
+    async fn run_limit_stage(
+        &self,
+        addresses: &[HCombAddress],
+        stage: &StagePlan,
+        hcomb_streams: Vec<HCombStream>,
+    ) -> Result<Vec<Vec<RecordBatch>>> {
+        let hbees = Self::hbee_order(stage);
+        let collect = futures::future::try_join_all(
+            hcomb_streams
+                .into_iter()
+                .map(|hcomb_stream| hcomb_stream.try_collect::<Vec<_>>()),
+        );
+        futures::pin_mut!(collect);
+        let mut scheduled = 0;
+        let mut wave_size = LIMIT_FIRST_WAVE;
+        while scheduled < hbees.len() {
+            let wave_end = std::cmp::min(scheduled + wave_size, hbees.len());
+            self.schedule_hbee_list(addresses, stage, &hbees[scheduled..wave_end])
+                .await?;
+            scheduled = wave_end;
+            wave_size *= 2;
+            if scheduled == hbees.len() {
+                break;
+            }
+            // give the hbees of this wave some time to reach the limit
+            let wave_delay = Box::pin(tokio::time::delay_for(LIMIT_WAVE_INTERVAL));
+            if let Either::Left((results, _)) = select(collect.as_mut(), wave_delay).await
+            {
+                println!(
+                    "[fuse] limit reached after scheduling {}/{} hbees",
+                    scheduled,
+                    hbees.len()
+                );
+                return Ok(results?);
+            }
+        }
+        Ok(collect.await?)
+    }
+
+    async fn schedule_hbees(
+        &self,
+        addresses: &[HCombAddress],
+        stage: &StagePlan,
+    ) -> Result<()> {
         // TODO start hbees for hcombs that are ready before the others?
-        println!("[fuse] schedule {} hbees", plan.nb_hbee);
-        let start_schedule = Instant::now();
-        let mut hcomb_hbee_idx_tuple = (0..plan.zones.len())
-            .flat_map(|i| (0..plan.zones[i].hbee.len()).map(move |j| (i, j)))
+        let hbees = Self::hbee_order(stage);
+        self.schedule_hbee_list(addresses, stage, &hbees).await
+    }
+
+    /// The (zone, hbee) indexes of the hbees of the stage, in scheduling order
+    fn hbee_order(stage: &StagePlan) -> Vec<(usize, usize)> {
+        let mut hcomb_hbee_idx_tuple = (0..stage.zones.len())
+            .flat_map(|i| (0..stage.zones[i].hbee.len()).map(move |j| (i, j)))
             .collect::<Vec<_>>();
 
         // sort by hbee index in order to alternate between hcombs
         hcomb_hbee_idx_tuple.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        hcomb_hbee_idx_tuple
+    }
 
-        let future_hbees = hcomb_hbee_idx_tuple.into_iter().map(|(i, j)| {
+    async fn schedule_hbee_list(
+        &self,
+        addresses: &[HCombAddress],
+        stage: &StagePlan,
+        hbees: &[(usize, usize)],
+    ) -> Result<()> {
+        println!("[fuse] schedule {} hbees", hbees.len());
+        let start_schedule = Instant::now();
+        let future_hbees = hbees.iter().map(|&(i, j)| {
+            // shuffled results are sent to all the hcombs of the stage
+            let hbee_addresses = if stage.zones[i].hbee[j].shuffle_keys.is_empty() {
+                &addresses[i..=i]
+            } else {
+                &addresses[..stage.zones.len()]
+            };
             self.hbee_scheduler.schedule(
-                query_id.clone(),
-                &addresses[i],
-                &plan.zones[i].hbee[j].table,
-                plan.zones[i].hbee[j].sql.clone(),
-                plan.zones[i].hbee[j].source.clone(),
+                stage.zones[i].hbee[j].query_id.clone(),
+                hbee_addresses,
+                &stage.zones[i].hbee[j].table,
+                stage.zones[i].hbee[j].sql.clone(),
+                stage.zones[i].hbee[j].source.clone(),
+                &stage.zones[i].hbee[j].broadcasts,
+                &stage.zones[i].hbee[j].shuffle_keys,
             )
         });
         futures::stream::iter(future_hbees)
@@ -106,22 +430,6 @@ impl FuseService {
             "[fuse] hbee scheduling duration: {}",
             start_schedule.elapsed().as_millis()
         );
-
-        // wait for hcombs to collect all the results and desplay them comb by comb
-        println!("[fuse] collect hcombs");
-        for hcomb_stream in hcomb_streams {
-            let result: Vec<RecordBatch> = hcomb_stream.try_collect::<Vec<_>>().await?;
-            pretty::print_batches(&result).unwrap();
-        }
-
-        println!(
-            "[fuse] hbee total duration: {}",
-            start_schedule.elapsed().as_millis()
-        );
-        println!(
-            "[fuse] total run duration: {}",
-            start_run.elapsed().as_millis()
-        );
         Ok(())
     }
 }
@@ -1,5 +1,5 @@
 use crate::clients::lambda::LambdaInvokeClient;
-use crate::datasource::HBeeTableDesc;
+use crate::datasource::{BroadcastTable, HBeeTableDesc};
 use crate::error::Result;
 use crate::internal_err;
 use crate::models::{HBeeEvent, HBeePlanBytes, HCombAddress};
@@ -11,10 +11,12 @@ pub trait HBeeScheduler {
     async fn schedule(
         &self,
         query_id: String,
-        address: &HCombAddress,
+        addresses: &[HCombAddress],
         table: &HBeeTableDesc,
         sql: String,
         source: String,
+        broadcasts: &[BroadcastTable],
+        shuffle_keys: &[String],
     ) -> Result<()>;
 }
 
@@ -27,17 +29,19 @@ impl HBeeScheduler for TestHBeeScheduler {
     async fn schedule(
         &self,
         query_id: String,
-        address: &HCombAddress,
+        addresses: &[HCombAddress],
         table: &HBeeTableDesc,
         sql: String,
         source: String,
+        broadcasts: &[BroadcastTable],
+        shuffle_keys: &[String],
     ) -> Result<()> {
         let client = Client::new();
 
         let req_body = serde_json::to_string(&HBeeEvent {
             query_id,
-            hcomb_address: address.clone(),
-            plan: HBeePlanBytes::try_new(&table, sql, source)?,
+            hcomb_addresses: addresses.to_vec(),
+            plan: HBeePlanBytes::try_new(&table, sql, source, broadcasts, shuffle_keys)?,
         })
         .map_err(|_| internal_err!("failed to serialize to json"))?;
 
@@ -73,15 +77,17 @@ impl HBeeScheduler for LambdaHBeeScheduler {
     async fn schedule(
         &self,
         query_id: String,
-        address: &HCombAddress,
+        addresses: &[HCombAddress],
         table: &HBeeTableDesc,
         sql: String,
         source: String,
+        broadcasts: &[BroadcastTable],
+        shuffle_keys: &[String],
     ) -> Result<()> {
         let req_body = serde_json::to_vec(&HBeeEvent {
             query_id,
-            hcomb_address: address.clone(),
-            plan: HBeePlanBytes::try_new(&table, sql, source)?,
+            hcomb_addresses: addresses.to_vec(),
+            plan: HBeePlanBytes::try_new(&table, sql, source, broadcasts, shuffle_keys)?,
         })
         .map_err(|_| internal_err!("failed to serialize to json"))?;
 
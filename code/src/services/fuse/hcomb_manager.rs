@@ -2,7 +2,7 @@ use std::time::{Duration, Instant};
 
 use crate::clients::fargate::FargateCreationClient;
 use crate::clients::flight_client;
-use crate::error::Result;
+use crate::error::{BuzzError, Result};
 use crate::internal_err;
 use crate::models::{query::HCombCapacity, HCombAddress};
 use async_trait::async_trait;
@@ -25,7 +25,12 @@ impl HCombManager for TestHCombManager {
         &self,
         capactity: &HCombCapacity,
     ) -> Result<Vec<HCombAddress>> {
-        assert_eq!(capactity.zones, 1, "Only single zone supported for now");
+        if capactity.zones != 1 {
+            return Err(BuzzError::BadRequest(format!(
+                "Only a single zone is supported by the test hcomb manager, found {}",
+                capactity.zones
+            )));
+        }
         Ok(vec![format!("http://{}:3333", self.domain)])
     }
 }
@@ -48,10 +53,16 @@ impl HCombManager for FargateHCombManager {
         &self,
         capactity: &HCombCapacity,
     ) -> Result<Vec<HCombAddress>> {
-        assert_eq!(capactity.zones, 1, "Only single zone supported for now");
-
-        let private_ip = self.client.create_new().await?;
+        // one distinct hcomb is needed per zone, the results are shuffled among them
+        let private_ips = self.client.create_n(capactity.zones).await?;
+        let connections = private_ips.into_iter().map(|ip| self.connect_hcomb(ip));
+        futures::future::try_join_all(connections).await
+    }
+}
 
+impl FargateHCombManager {
+    /// Wait for the hcomb task at the given IP to accept connections
+    async fn connect_hcomb(&self, private_ip: String) -> Result<HCombAddress> {
         let address = format!("http://{}:3333", private_ip);
 
         let start = Instant::now();
@@ -84,6 +95,6 @@ impl HCombManager for FargateHCombManager {
             "[fuse] took {}ms to connect to hcomb",
             start.elapsed().as_millis()
         );
-        Ok(vec![address])
+        Ok(address)
     }
 }
@@ -13,13 +13,21 @@ use tokio::stream::Stream;
 #[async_trait]
 pub trait HCombScheduler {
     /// Notifies the hcomb that a query is starting and opens a stream of results.
+    /// The `inputs` are the tables that the query reads from, with their name.
     async fn schedule(
         &self,
         address: &HCombAddress,
-        hcomb_table: &HCombTableDesc,
+        inputs: &[(HCombTableDesc, String)],
         sql: String,
-        source: String,
     ) -> Result<Pin<Box<dyn Stream<Item = ArrowResult<RecordBatch>>>>>;
+
+    /// Sends the results of an upstream stage to the hcomb.
+    async fn forward(
+        &self,
+        address: &HCombAddress,
+        query_id: String,
+        results: Vec<RecordBatch>,
+    ) -> Result<()>;
 }
 
 pub struct HttpHCombScheduler;
@@ -29,12 +37,22 @@ impl HCombScheduler for HttpHCombScheduler {
     async fn schedule(
         &self,
         address: &HCombAddress,
-        hcomb_table: &HCombTableDesc,
+        inputs: &[(HCombTableDesc, String)],
         sql: String,
-        source: String,
     ) -> Result<Pin<Box<dyn Stream<Item = ArrowResult<RecordBatch>>>>> {
-        flight_client::call_do_get(address, hcomb_table, sql, source)
+        flight_client::call_do_get(address, inputs, sql)
             .await
             .map_err(|e| internal_err!("Could not get result from HComb: {}", e))
     }
+
+    async fn forward(
+        &self,
+        address: &HCombAddress,
+        query_id: String,
+        results: Vec<RecordBatch>,
+    ) -> Result<()> {
+        flight_client::call_do_put(query_id, address, results)
+            .await
+            .map_err(|e| internal_err!("Could not forward results to HComb: {}", e))
+    }
 }
@@ -1,11 +1,19 @@
+mod estimate;
+mod explain;
 mod fuse_service;
 mod hbee_scheduler;
 mod hcomb_manager;
 mod hcomb_scheduler;
+mod query_parameters;
 mod query_planner;
+mod query_splitter;
+mod result_cache;
+mod top_k;
 
-pub use fuse_service::FuseService;
+pub use estimate::{CostModel, QueryEstimate, StageEstimate};
+pub use fuse_service::{FuseService, QueryStats};
 pub use hbee_scheduler::{HBeeScheduler, LambdaHBeeScheduler, TestHBeeScheduler};
 pub use hcomb_manager::{FargateHCombManager, HCombManager, TestHCombManager};
 pub use hcomb_scheduler::{HCombScheduler, HttpHCombScheduler};
 pub use query_planner::{HBeePlan, QueryPlanner};
+pub use result_cache::{InMemoryStore, LocalDiskStore, ResultCache, ResultStore};
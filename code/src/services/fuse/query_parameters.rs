@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::error::{BuzzError, Result};
+use crate::models::query::{BuzzStep, QueryParameter};
+
+/// Replaces the `$name` and `:name` placeholders of the SQL and the partition filters
+/// of the steps with the literal value of the associated parameter.
+pub fn bind_steps(
+    steps: Vec<BuzzStep>,
+    parameters: &HashMap<String, QueryParameter>,
+) -> Result<Vec<BuzzStep>> {
+    steps
+        .into_iter()
+        .map(|step| {
+            Ok(BuzzStep {
+                sql: bind(&step.sql, parameters)?,
+                partition_filter: step
+                    .partition_filter
+                    .map(|filter| bind(&filter, parameters))
+                    .transpose()?,
+                ..step
+            })
+        })
+        .collect()
+}
+
+/// Replaces the placeholders of the given SQL. String literals, quoted identifiers,
+/// `--` and `/* */` comments and `::` casts are left untouched.
+pub fn bind(sql: &str, parameters: &HashMap<String, QueryParameter>) -> Result<String> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut bound = String::with_capacity(sql.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' | '"' => {
+                // copy up to the closing quote, a doubled quote is an escaped one
+                let end = closing_quote(&chars, i);
+                bound.extend(&chars[i..end]);
+                i = end;
+            }
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                let end = (i..chars.len())
+                    .find(|j| chars[*j] == '\n')
+                    .unwrap_or(chars.len());
+                bound.extend(&chars[i..end]);
+                i = end;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                // block comments end at the first `*/`, or with the SQL
+                let end = (i + 2..chars.len())
+                    .find(|j| chars[*j] == '*' && chars.get(j + 1) == Some(&'/'))
+                    .map_or(chars.len(), |j| j + 2);
+                bound.extend(&chars[i..end]);
+                i = end;
+            }
+            ':' if chars.get(i + 1) == Some(&':') => {
+                bound.push_str("::");
+                i += 2;
+            }
+            '$' | ':' if chars.get(i + 1).map_or(false, |n| is_ident_start(*n)) => {
+                let end = (i + 1..chars.len())
+                    .find(|j| !is_ident_part(chars[*j]))
+                    .unwrap_or(chars.len());
+                let name = chars[i + 1..end].iter().collect::<String>();
+                let value = parameters.get(&name).ok_or_else(|| {
+                    BuzzError::BadRequest(format!(
+                        "No value was provided for the query parameter {}{}",
+                        c, name
+                    ))
+                })?;
+                bound.push_str(&to_literal(&name, value)?);
+                i = end;
+            }
+            _ => {
+                bound.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(bound)
+}
+
+/// The index after the quote that closes the one at `start`, or the end of the SQL
+fn closing_quote(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_part(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The SQL literal of the parameter value. Negative numbers are parenthesized so that
+/// a preceding `-` does not turn them into a comment (`a-$n` would give `a--5`).
+fn to_literal(name: &str, value: &QueryParameter) -> Result<String> {
+    let literal = match value {
+        QueryParameter::Bool(true) => "TRUE".to_owned(),
+        QueryParameter::Bool(false) => "FALSE".to_owned(),
+        QueryParameter::Int(int) if *int < 0 => format!("({})", int),
+        QueryParameter::Int(int) => int.to_string(),
+        QueryParameter::Float(float) if float.is_finite() && float.is_sign_negative() => {
+            format!("({:?})", float)
+        }
+        QueryParameter::Float(float) if float.is_finite() => format!("{:?}", float),
+        QueryParameter::Float(float) => {
+            return Err(BuzzError::BadRequest(format!(
+                "Query parameter {} should be a finite number, found {}",
+                name, float
+            )))
+        }
+        QueryParameter::Str(string) => format!("'{}'", string.replace('\'', "''")),
+    };
+    Ok(literal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters() -> HashMap<String, QueryParameter> {
+        let mut parameters = HashMap::new();
+        parameters.insert(
+            "start_month".to_owned(),
+            QueryParameter::Str("2009/01".to_owned()),
+        );
+        parameters.insert("limit".to_owned(), QueryParameter::Int(10));
+        parameters.insert("min_fare".to_owned(), QueryParameter::Float(2.));
+        parameters.insert(
+            "vendor".to_owned(),
+            QueryParameter::Str("O'Neil".to_owned()),
+        );
+        parameters.insert("paid".to_owned(), QueryParameter::Bool(true));
+        parameters
+    }
+
+    #[test]
+    fn test_bind() {
+        let bound = bind(
+            "SELECT * FROM t WHERE month >= $start_month AND fare > :min_fare AND vendor = $vendor AND paid = :paid LIMIT :limit",
+            &parameters(),
+        )
+        .unwrap();
+        assert_eq!(
+            bound,
+            "SELECT * FROM t WHERE month >= '2009/01' AND fare > 2.0 AND vendor = 'O''Neil' AND paid = TRUE LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn test_bind_ignores_literals() {
+        let sql =
+            "SELECT ':limit', \"$vendor\", 'it''s $limit', fare::int FROM t -- :limit\n";
+        assert_eq!(bind(sql, &parameters()).unwrap(), sql);
+    }
+
+    #[test]
+    fn test_bind_negative_numbers() {
+        let mut parameters = parameters();
+        parameters.insert("n".to_owned(), QueryParameter::Int(-5));
+        parameters.insert("x".to_owned(), QueryParameter::Float(-1.5));
+        let bound = bind("SELECT a-$n, -:x FROM t WHERE b > 0", &parameters).unwrap();
+        assert_eq!(bound, "SELECT a-(-5), -(-1.5) FROM t WHERE b > 0");
+    }
+
+    #[test]
+    fn test_bind_ignores_block_comments() {
+        let sql = "SELECT /* $unknown\n:other */ fare FROM t /* $unterminated";
+        assert_eq!(bind(sql, &parameters()).unwrap(), sql);
+    }
+
+    #[test]
+    fn test_bind_errors() {
+        let err = bind("SELECT * FROM t LIMIT $unknown", &parameters()).unwrap_err();
+        assert!(matches!(err, BuzzError::BadRequest(_)));
+
+        let mut parameters = parameters();
+        parameters.insert("nan".to_owned(), QueryParameter::Float(f64::NAN));
+        bind("SELECT $nan", &parameters).expect_err("NaN is not a valid literal");
+    }
+
+    #[test]
+    fn test_deserialize_parameters() {
+        let parameters: HashMap<String, QueryParameter> = serde_json::from_str(
+            r#"{"month": "2009/01", "limit": 10, "fare": 2.5, "paid": false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parameters["month"],
+            QueryParameter::Str("2009/01".to_owned())
+        );
+        assert_eq!(parameters["limit"], QueryParameter::Int(10));
+        assert_eq!(parameters["fare"], QueryParameter::Float(2.5));
+        assert_eq!(parameters["paid"], QueryParameter::Bool(false));
+    }
+}
@@ -1,16 +1,32 @@
-use crate::datasource::{CatalogTable, HBeeTableDesc, HCombTable, HCombTableDesc};
+use std::collections::{HashMap, HashSet};
+
+use super::query_splitter;
+use super::top_k;
+use crate::datasource::{
+    BroadcastTable, CatalogSplit, CatalogTable, HBeeTableDesc, HCombTable, HCombTableDesc,
+};
 use crate::error::{BuzzError, Result};
 use crate::models::query::{BuzzStep, BuzzStepType};
-use crate::not_impl_err;
 use crate::plan_utils;
 use crate::services::utils;
+use crate::sketch::register_sketch_udafs;
+use crate::{internal_err, not_impl_err};
+use arrow::datatypes::SchemaRef;
+use datafusion::datasource::MemTable;
 use datafusion::execution::context::ExecutionContext;
-use datafusion::logical_plan::LogicalPlan;
+use datafusion::logical_plan::{Expr, LogicalPlan};
 use futures::future::{BoxFuture, FutureExt};
 
+/// The maximum size of the broadcast tables of an hbee, once serialized. They are inlined
+/// in its Lambda invocation payload, which is limited to 256KB for asynchronous calls.
+/// Base64 encoded, this leaves room for the files and the SQL of the hbee.
+const MAX_BROADCAST_BYTES: usize = 128 * 1024;
+
 pub struct QueryPlanner {
     /// This execution context is not meant to run queries but only to plan them.
     execution_context: ExecutionContext,
+    /// The small tables that can be joined by hbees, by name.
+    broadcast_tables: HashMap<String, BroadcastTable>,
 }
 
 #[derive(Debug)]
@@ -18,11 +34,24 @@ pub struct HBeePlan {
     pub sql: String,
     pub source: String,
     pub table: HBeeTableDesc,
+    /// The id under which the hcombs collect the results of this hbee
+    pub query_id: String,
+    pub broadcasts: Vec<BroadcastTable>,
+    /// If not empty, the results are hash partitioned by these columns
+    /// and sent to all the hcombs of the stage
+    pub shuffle_keys: Vec<String>,
 }
 
 #[derive(Debug)]
 pub struct HCombPlan {
     pub sql: String,
+    /// The results of the steps that the sql reads from, one per step
+    pub inputs: Vec<HCombInput>,
+}
+
+/// The results of a step, registered on the hcomb under the name of the step
+#[derive(Debug, Clone)]
+pub struct HCombInput {
     pub source: String,
     pub table: HCombTableDesc,
 }
@@ -33,19 +62,85 @@ pub struct ZonePlan {
     pub hcomb: HCombPlan,
 }
 
+/// A set of hcombs that run the same HComb step of the query.
+#[derive(Debug)]
+pub struct StagePlan {
+    /// The name of the HComb step that this stage runs
+    pub name: String,
+    /// The HComb step whose results feed this stage, `None` if it is fed by hbees
+    pub upstream: Option<String>,
+    /// One hcomb/hbee combination of plan for each zone.
+    pub zones: Vec<ZonePlan>,
+    /// True if the results of this stage are not consumed by any other stage
+    pub is_output: bool,
+    /// The optimized logical plan of the HComb step, for display
+    pub hcomb_plan: String,
+    /// The HBee steps that feed this stage, if any
+    pub hbee_steps: Vec<HBeeStepExplain>,
+    /// If set, the stage only needs this number of rows from its hbees,
+    /// so they can be scheduled incrementally until the hcomb has enough results
+    pub limit: Option<usize>,
+}
+
+/// The description of an HBee step, as planned by the fuse
+#[derive(Debug)]
+pub struct HBeeStepExplain {
+    pub name: String,
+    pub sql: String,
+    /// The optimized logical plan of the HBee step, for display
+    pub plan: String,
+    /// The catalog partitions that were excluded by the filters of the step
+    pub pruned_partitions: Vec<String>,
+}
+
 /// The plans to be distributed among hbees and hcombs
 /// To transfer them over the wire, these logical plans should be serializable
 #[derive(Debug)]
 pub struct DistributedPlan {
-    /// One hcomb/hbee combination of plan for each zone.
-    pub zones: Vec<ZonePlan>,
+    /// The stages in execution order, a stage always comes after its upstream.
+    pub stages: Vec<StagePlan>,
     pub nb_hbee: usize,
 }
 
+/// The hbee tasks of an HBee step, waiting for the HComb step that consumes them.
+struct HBeeStepPlan {
+    /// The SELECT statements of the step, several if they are combined with UNION ALL
+    branches: Vec<HBeeBranch>,
+    explain: HBeeStepExplain,
+}
+
+/// A SELECT statement of an HBee step, that reads from a single catalog
+struct HBeeBranch {
+    sql: String,
+    source: String,
+    tables: Vec<HBeeTableDesc>,
+    broadcasts: Vec<BroadcastTable>,
+    /// The optimized logical plan of the branch
+    logical_plan: LogicalPlan,
+}
+
+impl HBeeStepPlan {
+    fn nb_tables(&self) -> usize {
+        self.branches.iter().map(|branch| branch.tables.len()).sum()
+    }
+
+    /// The SQL run by the hbees, for display
+    fn sql(&self) -> String {
+        self.branches
+            .iter()
+            .map(|branch| branch.sql.as_str())
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ")
+    }
+}
+
 impl QueryPlanner {
     pub fn new() -> Self {
+        let mut execution_context = ExecutionContext::new();
+        register_sketch_udafs(&mut execution_context);
         Self {
-            execution_context: ExecutionContext::new(),
+            execution_context,
+            broadcast_tables: HashMap::new(),
         }
     }
 
@@ -53,111 +148,603 @@ impl QueryPlanner {
         self.execution_context.register_table(name, Box::new(table));
     }
 
+    /// Register a small table that will be sent to each hbee that joins it.
+    /// Tables that would not fit in the hbee invocation payload are rejected.
+    pub fn add_broadcast_table(&mut self, table: BroadcastTable) -> Result<()> {
+        let size = table.to_ipc()?.len();
+        if size > MAX_BROADCAST_BYTES {
+            return Err(BuzzError::BadRequest(format!(
+                "Broadcast table {} is {} bytes once serialized, the limit is {} bytes",
+                table.name(),
+                size,
+                MAX_BROADCAST_BYTES
+            )));
+        }
+        self.execution_context
+            .register_table(table.name(), Box::new(table.to_mem_table()?));
+        self.broadcast_tables.insert(table.name().to_owned(), table);
+        Ok(())
+    }
+
     pub async fn plan(
         &mut self,
         query_id: String,
         query_steps: Vec<BuzzStep>,
         nb_hcomb: i16,
     ) -> Result<DistributedPlan> {
-        // TODO lift the limitation inforced by the following assert:
-        assert!(
-            query_steps.len() == 2
-                && query_steps[0].step_type == BuzzStepType::HBee
-                && query_steps[1].step_type == BuzzStepType::HComb,
-            "You must have one exactly one HBee step followed by one HComb step for now"
-        );
+        if nb_hcomb < 1 {
+            return Err(BuzzError::BadRequest(format!(
+                "The capacity should be at least one zone, found {}",
+                nb_hcomb
+            )));
+        }
+        let query_steps = Self::auto_split(query_steps)?;
 
-        let hbee_step = &query_steps[0];
-        let hcomb_step = &query_steps[1];
+        let mut step_names = HashSet::new();
+        // the outputs of the steps that were not consumed yet
+        let mut intermediates = HashMap::new();
+        let mut hbee_steps = HashMap::new();
+        let mut stages: Vec<StagePlan> = vec![];
+        let mut nb_hbee = 0;
 
-        let bee_df = self.execution_context.sql(&hbee_step.sql)?;
-        let src_bee_plan = self.execution_context.optimize(&bee_df.to_logical_plan())?;
-        let hbee_actual_src = utils::find_table_name::<CatalogTable>(&src_bee_plan)?;
-        let bee_output_schema = src_bee_plan.schema().as_ref().clone();
-        let bee_plans = self
-            .split(&src_bee_plan, &hbee_step.partition_filter)
-            .await?;
-        let nb_hbee = bee_plans.len();
-
-        if nb_hbee == 0 {
-            return Ok(DistributedPlan {
-                zones: vec![],
-                nb_hbee,
-            });
+        for step in query_steps {
+            if !step_names.insert(step.name.clone()) {
+                return Err(BuzzError::BadRequest(format!(
+                    "Step names should be unique, found {} twice",
+                    step.name
+                )));
+            }
+            match step.step_type {
+                BuzzStepType::HBee => {
+                    let (hbee_step, schema) = self.plan_hbee_step(&step).await?;
+                    let desc = self.register_intermediate(
+                        &query_id,
+                        &step.name,
+                        hbee_step.nb_tables(),
+                        schema,
+                    );
+                    intermediates.insert(step.name.clone(), desc);
+                    hbee_steps.insert(step.name, hbee_step);
+                }
+                BuzzStepType::HComb => {
+                    // plan the hcomb part of the query, to check if it is valid
+                    let hcomb_df = self.execution_context.sql(&step.sql)?;
+                    let hcomb_plan = hcomb_df.to_logical_plan();
+                    if !utils::find_table_names::<MemTable>(&hcomb_plan).is_empty() {
+                        return Err(not_impl_err!(
+                            "Broadcast tables can only be joined in HBee steps"
+                        ));
+                    }
+                    let sources = utils::find_table_names::<HCombTable>(&hcomb_plan)
+                        .into_iter()
+                        .map(|name| name.to_owned())
+                        .collect::<Vec<_>>();
+                    let explained_plan =
+                        format!("{:?}", self.execution_context.optimize(&hcomb_plan)?);
+
+                    let stage = if sources.len() > 1 {
+                        let mut inputs = vec![];
+                        for source in sources {
+                            if inputs.iter().any(|(name, _, _)| name == &source) {
+                                return Err(not_impl_err!(
+                                    "An HComb step can read the results of the {} step only once",
+                                    source
+                                ));
+                            }
+                            let input =
+                                Self::consume(&mut intermediates, &source, &step.name)?;
+                            let hbee_step = hbee_steps.remove(&source).ok_or_else(|| {
+                                not_impl_err!(
+                                    "HComb steps that read several steps can only combine HBee steps, {} is not one",
+                                    source
+                                )
+                            })?;
+                            nb_hbee += hbee_step.nb_tables();
+                            inputs.push((source, input, hbee_step));
+                        }
+                        Self::multi_hbee_fed_stage(&step, inputs, explained_plan)
+                    } else {
+                        let source =
+                            utils::find_table_name::<HCombTable>(&hcomb_plan)?.to_owned();
+                        let input =
+                            Self::consume(&mut intermediates, &source, &step.name)?;
+                        if let Some(hbee_step) = hbee_steps.remove(&source) {
+                            nb_hbee += hbee_step.nb_tables();
+                            Self::hbee_fed_stage(
+                                &step,
+                                &source,
+                                &input,
+                                &hcomb_plan,
+                                explained_plan,
+                                hbee_step,
+                                nb_hcomb,
+                            )?
+                        } else {
+                            let upstream = stages
+                                .iter_mut()
+                                .find(|stage| stage.name == source)
+                                .ok_or_else(|| {
+                                    internal_err!(
+                                        "Stage {} should have been planned",
+                                        source
+                                    )
+                                })?;
+                            upstream.is_output = false;
+                            let upstream_zones = upstream.zones.len();
+                            Self::hcomb_fed_stage(
+                                &step,
+                                &source,
+                                &input,
+                                explained_plan,
+                                upstream_zones,
+                            )
+                        }
+                    };
+
+                    let desc = self.register_intermediate(
+                        &query_id,
+                        &step.name,
+                        stage.zones.len(),
+                        hcomb_plan.schema().as_ref().clone().into(),
+                    );
+                    intermediates.insert(step.name, desc);
+                    stages.push(stage);
+                }
+            }
+        }
+
+        if let Some(unconsumed) = hbee_steps.keys().next() {
+            return Err(BuzzError::BadRequest(format!(
+                "The HBee step {} is not consumed by any HComb step",
+                unconsumed
+            )));
+        }
+        if stages.is_empty() {
+            return Err(BuzzError::BadRequest(
+                "A query should contain at least one HComb step".to_owned(),
+            ));
         }
 
-        // register a handle to the intermediate table on the context
-        let hcomb_table_desc =
-            HCombTableDesc::new(query_id, nb_hbee, bee_output_schema.into());
-        let hcomb_expected_src = &hbee_step.name;
+        Ok(DistributedPlan { stages, nb_hbee })
+    }
 
-        // plan the hcomb part of the query, to check if it is valid
-        let hcomb_table = HCombTable::new_empty(hcomb_table_desc.clone());
-        self.execution_context
-            .register_table(hcomb_expected_src, Box::new(hcomb_table));
-        let hcomb_df = self.execution_context.sql(&hcomb_step.sql)?;
-        let hcomb_plan = hcomb_df.to_logical_plan();
-        let hcomb_actual_src = utils::find_table_name::<HCombTable>(&hcomb_plan)?;
-        if hcomb_actual_src != hcomb_expected_src {
+    /// Split the HBee step into hbee tasks and compute its output schema.
+    /// If the step is a UNION ALL, each branch is split over its own catalog
+    /// and the tasks of all the branches feed the same HComb step.
+    async fn plan_hbee_step(
+        &mut self,
+        step: &BuzzStep,
+    ) -> Result<(HBeeStepPlan, SchemaRef)> {
+        let branch_sqls = query_splitter::union_branches(&step.sql)?;
+        let is_union = branch_sqls.len() > 1;
+        let mut branches = vec![];
+        let mut plans = vec![];
+        let mut all_pruned_partitions = vec![];
+        let mut schema: Option<SchemaRef> = None;
+        for sql in branch_sqls {
+            let (branch, branch_schema, pruned_partitions) =
+                self.plan_hbee_branch(sql, step).await?;
+            match &schema {
+                Some(schema) if !Self::same_columns(schema, &branch_schema) => {
+                    return Err(BuzzError::BadRequest(format!(
+                        "The branches of the UNION ALL of step {} should have the same columns, found {:?} and {:?}",
+                        step.name,
+                        schema.fields(),
+                        branch_schema.fields()
+                    )));
+                }
+                Some(_) => {}
+                None => schema = Some(branch_schema),
+            }
+            if is_union {
+                // prefix the partitions with their catalog to tell them apart
+                all_pruned_partitions.extend(
+                    pruned_partitions
+                        .into_iter()
+                        .map(|partition| format!("{}/{}", branch.source, partition)),
+                );
+            } else {
+                all_pruned_partitions.extend(pruned_partitions);
+            }
+            plans.push(format!("{:?}", branch.logical_plan));
+            branches.push(branch);
+        }
+        let schema = schema
+            .ok_or_else(|| internal_err!("An HBee step has at least one branch"))?;
+        let hbee_step = HBeeStepPlan {
+            branches,
+            explain: HBeeStepExplain {
+                name: step.name.clone(),
+                sql: step.sql.clone(),
+                plan: plans.join("\n"),
+                pruned_partitions: all_pruned_partitions,
+            },
+        };
+        Ok((hbee_step, schema))
+    }
+
+    /// Split a SELECT statement of an HBee step over the catalog it reads from,
+    /// returns the branch, its output schema and the pruned partitions.
+    async fn plan_hbee_branch(
+        &mut self,
+        sql: String,
+        step: &BuzzStep,
+    ) -> Result<(HBeeBranch, SchemaRef, Vec<String>)> {
+        let bee_df = self.execution_context.sql(&sql)?;
+        let src_bee_plan = self.execution_context.optimize(&bee_df.to_logical_plan())?;
+        let source = utils::find_table_name::<CatalogTable>(&src_bee_plan)?.to_owned();
+        let schema = src_bee_plan.schema().as_ref().clone().into();
+        let broadcasts = utils::find_table_names::<MemTable>(&src_bee_plan)
+            .into_iter()
+            .map(|name| {
+                self.broadcast_tables.get(name).cloned().ok_or_else(|| {
+                    BuzzError::BadRequest(format!(
+                        "Table {} is not a broadcast table",
+                        name
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // each table fits on its own, but they are all sent to every hbee of the branch
+        let mut broadcast_bytes = 0;
+        for broadcast in &broadcasts {
+            broadcast_bytes += broadcast.to_ipc()?.len();
+        }
+        if broadcast_bytes > MAX_BROADCAST_BYTES {
             return Err(BuzzError::BadRequest(format!(
-                "The source table for the {} step is not an HBee",
-                hcomb_step.name,
+                "The broadcast tables joined by step {} are {} bytes once serialized, the limit is {} bytes",
+                step.name, broadcast_bytes, MAX_BROADCAST_BYTES
             )));
         }
+        let CatalogSplit {
+            tables,
+            pruned_partitions,
+        } = self.split(&src_bee_plan, step, vec![]).await?;
+        let branch = HBeeBranch {
+            sql,
+            source,
+            tables,
+            broadcasts,
+            logical_plan: src_bee_plan,
+        };
+        Ok((branch, schema, pruned_partitions))
+    }
+
+    /// The branches of a UNION ALL should produce the same columns, in the same order
+    fn same_columns(left: &SchemaRef, right: &SchemaRef) -> bool {
+        left.fields().len() == right.fields().len()
+            && left
+                .fields()
+                .iter()
+                .zip(right.fields())
+                .all(|(l, r)| l.name() == r.name() && l.data_type() == r.data_type())
+    }
+
+    /// Take the output of the `source` step, that is read by the `step_name` step.
+    /// The output of a step can only be consumed once.
+    fn consume(
+        intermediates: &mut HashMap<String, HCombTableDesc>,
+        source: &str,
+        step_name: &str,
+    ) -> Result<HCombTableDesc> {
+        intermediates.remove(source).ok_or_else(|| {
+            BuzzError::BadRequest(format!(
+                "The source table {} for the {} step is not a step or was already consumed",
+                source, step_name,
+            ))
+        })
+    }
 
+    /// Register a handle to the output of a step on the context so that
+    /// the HComb steps that consume it can be planned.
+    fn register_intermediate(
+        &mut self,
+        query_id: &str,
+        name: &str,
+        nb_tasks: usize,
+        schema: SchemaRef,
+    ) -> HCombTableDesc {
+        let desc =
+            HCombTableDesc::new(format!("{}-{}", query_id, name), nb_tasks, schema);
+        let table = HCombTable::new_empty(desc.clone());
+        self.execution_context.register_table(name, Box::new(table));
+        desc
+    }
+
+    /// Distribute the hbee tasks of an HBee step among the hcombs of the stage.
+    fn hbee_fed_stage(
+        step: &BuzzStep,
+        source: &str,
+        input: &HCombTableDesc,
+        hcomb_plan: &LogicalPlan,
+        explained_plan: String,
+        mut hbee_step: HBeeStepPlan,
+        nb_hcomb: i16,
+    ) -> Result<StagePlan> {
+        // if the HComb step only keeps the first rows, each hbee can apply the limit
+        let limit = plan_utils::limit_only(hcomb_plan);
+        if let Some(limit) = limit {
+            for branch in &mut hbee_step.branches {
+                branch.sql = query_splitter::push_limit(&branch.sql, limit)?;
+            }
+            hbee_step.explain.sql = hbee_step.sql();
+        } else {
+            let top_ks = hbee_step
+                .branches
+                .iter()
+                .map(|branch| top_k::top_k(hcomb_plan, &branch.logical_plan))
+                .collect::<Option<Vec<_>>>();
+            if let Some(top_ks) = top_ks {
+                // each hbee only sends its own top-k, the hcomb merges them
+                for (branch, top_k) in hbee_step.branches.iter_mut().zip(top_ks) {
+                    branch.sql = query_splitter::push_top_k(&branch.sql, &top_k)?;
+                }
+                hbee_step.explain.sql = hbee_step.sql();
+            }
+        }
+        let shuffle_keys = Self::shuffle_keys(hcomb_plan);
+        let nb_tables = hbee_step.nb_tables();
         // If they are less hbees than hcombs, don't use all hcombs
-        let used_hcomb = std::cmp::min(nb_hcomb as usize, nb_hbee);
+        let used_hcomb = match &shuffle_keys {
+            Some(_) => std::cmp::min(nb_hcomb as usize, nb_tables),
+            None => std::cmp::min(1, nb_tables),
+        };
+        // shuffling is only required if the hcombs need to split the groups
+        let shuffle_keys = match shuffle_keys {
+            Some(keys) if used_hcomb > 1 => keys,
+            _ => vec![],
+        };
 
-        // init plans for each zone
-        let mut zones = (0..used_hcomb)
-            .map(|_i| ZonePlan {
-                hbee: vec![],
+        let mut hbees = (0..used_hcomb).map(|_i| vec![]).collect::<Vec<_>>();
+        // distribute hbee plans between zones
+        let HBeeStepPlan { branches, explain } = hbee_step;
+        Self::hbee_plans(branches, input.query_id(), &shuffle_keys)
+            .enumerate()
+            .for_each(|(i, hbee)| hbees[i % used_hcomb].push(hbee));
+
+        // init plans for each zone, if the results are not shuffled
+        // each hcomb only waits for its own hbees
+        let zones = hbees
+            .into_iter()
+            .map(|hbee| ZonePlan {
                 hcomb: HCombPlan {
-                    table: hcomb_table_desc.clone(),
-                    sql: query_steps[1].sql.clone(),
-                    source: hcomb_actual_src.to_owned(),
+                    inputs: vec![HCombInput {
+                        table: HCombTableDesc::new(
+                            input.query_id().to_owned(),
+                            if shuffle_keys.is_empty() {
+                                hbee.len()
+                            } else {
+                                nb_tables
+                            },
+                            input.schema(),
+                        ),
+                        source: source.to_owned(),
+                    }],
+                    sql: step.sql.clone(),
                 },
+                hbee,
             })
-            .collect::<Vec<_>>();
-        // distribute hbee plans between zones
-        bee_plans.into_iter().enumerate().for_each(|(i, bee_plan)| {
-            zones[i % used_hcomb].hbee.push(HBeePlan {
-                table: bee_plan,
-                sql: hbee_step.sql.clone(),
-                source: hbee_actual_src.to_owned(),
-            })
-        });
+            .collect();
+
+        Ok(StagePlan {
+            name: step.name.clone(),
+            upstream: None,
+            zones,
+            is_output: true,
+            hcomb_plan: explained_plan,
+            hbee_steps: vec![explain],
+            limit,
+        })
+    }
+
+    /// A stage that combines several HBee steps (e.g. a join) runs on a single hcomb
+    /// that receives the results of the hbees of all the steps, each step being
+    /// collected separately under its own name.
+    fn multi_hbee_fed_stage(
+        step: &BuzzStep,
+        inputs: Vec<(String, HCombTableDesc, HBeeStepPlan)>,
+        hcomb_plan: String,
+    ) -> StagePlan {
+        let mut hbee = vec![];
+        let mut hcomb_inputs = vec![];
+        let mut hbee_steps = vec![];
+        for (source, input, hbee_step) in inputs {
+            let HBeeStepPlan { branches, explain } = hbee_step;
+            hbee.extend(Self::hbee_plans(branches, input.query_id(), &[]));
+            hcomb_inputs.push(HCombInput {
+                source,
+                table: input,
+            });
+            hbee_steps.push(explain);
+        }
+        let zones = if hbee.is_empty() {
+            vec![]
+        } else {
+            vec![ZonePlan {
+                hbee,
+                hcomb: HCombPlan {
+                    sql: step.sql.clone(),
+                    inputs: hcomb_inputs,
+                },
+            }]
+        };
+        StagePlan {
+            name: step.name.clone(),
+            upstream: None,
+            zones,
+            is_output: true,
+            hcomb_plan,
+            hbee_steps,
+            limit: None,
+        }
+    }
 
-        Ok(DistributedPlan {
-            zones: zones,
-            nb_hbee,
+    /// One hbee plan for each table of the branches of an HBee step
+    fn hbee_plans<'a>(
+        branches: Vec<HBeeBranch>,
+        query_id: &'a str,
+        shuffle_keys: &'a [String],
+    ) -> impl Iterator<Item = HBeePlan> + 'a {
+        branches.into_iter().flat_map(move |branch| {
+            let HBeeBranch {
+                sql,
+                source,
+                tables,
+                broadcasts,
+                ..
+            } = branch;
+            tables.into_iter().map(move |table| HBeePlan {
+                table,
+                query_id: query_id.to_owned(),
+                sql: sql.clone(),
+                source: source.clone(),
+                broadcasts: broadcasts.clone(),
+                shuffle_keys: shuffle_keys.to_vec(),
+            })
         })
     }
 
+    /// A stage fed by another HComb step runs on a single hcomb that
+    /// receives the results of all the hcombs of the upstream stage.
+    fn hcomb_fed_stage(
+        step: &BuzzStep,
+        source: &str,
+        input: &HCombTableDesc,
+        hcomb_plan: String,
+        upstream_zones: usize,
+    ) -> StagePlan {
+        let zones = if upstream_zones == 0 {
+            vec![]
+        } else {
+            vec![ZonePlan {
+                hbee: vec![],
+                hcomb: HCombPlan {
+                    inputs: vec![HCombInput {
+                        table: HCombTableDesc::new(
+                            input.query_id().to_owned(),
+                            upstream_zones,
+                            input.schema(),
+                        ),
+                        source: source.to_owned(),
+                    }],
+                    sql: step.sql.clone(),
+                },
+            }]
+        };
+        StagePlan {
+            name: step.name.clone(),
+            upstream: Some(source.to_owned()),
+            zones,
+            is_output: true,
+            hcomb_plan,
+            hbee_steps: vec![],
+            limit: None,
+        }
+    }
+
+    /// How the hbee results should be distributed among several hcombs
+    /// for the union of the hcomb results to be the result of the HComb step:
+    /// - `Some(vec![])`: each hbee can send its results to any hcomb
+    /// - `Some(keys)`: the results should be hash partitioned by the `keys` columns
+    /// - `None`: the HComb step needs all the results on a single hcomb
+    fn shuffle_keys(plan: &LogicalPlan) -> Option<Vec<String>> {
+        match plan {
+            LogicalPlan::Aggregate {
+                group_expr, input, ..
+            } => {
+                if !Self::is_filtered_scan(input) {
+                    return None;
+                }
+                let keys = group_expr
+                    .iter()
+                    .map(|expr| match expr {
+                        Expr::Column(name) => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                if keys.is_empty() {
+                    None
+                } else {
+                    Some(keys)
+                }
+            }
+            LogicalPlan::Limit { .. } | LogicalPlan::Sort { .. } => None,
+            _ => {
+                let inputs = datafusion::optimizer::utils::inputs(plan);
+                match inputs.len() {
+                    0 => Some(vec![]),
+                    1 => Self::shuffle_keys(inputs[0]),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn is_filtered_scan(plan: &LogicalPlan) -> bool {
+        match plan {
+            LogicalPlan::TableScan { .. } => true,
+            LogicalPlan::Filter { input, .. } => Self::is_filtered_scan(input),
+            _ => false,
+        }
+    }
+
+    /// If the query is made of a single HBee step, derive the HBee and HComb steps from it.
+    /// `COUNT(DISTINCT)` queries need two HComb steps, see `query_splitter::split_step()`.
+    fn auto_split(query_steps: Vec<BuzzStep>) -> Result<Vec<BuzzStep>> {
+        if query_steps.len() == 1 && query_steps[0].step_type == BuzzStepType::HBee {
+            let step = query_steps.into_iter().next().unwrap();
+            query_splitter::split_step(step)
+        } else {
+            Ok(query_steps)
+        }
+    }
+
     /// Takes a plan and if the source is a catalog, distibutes the files accordingly
     /// Each resulting HBee table is a good workload for a given hbee
-    /// Only works with linear plans (only one datasource)
+    /// Joins are only supported if exactly one side reads from a catalog,
+    /// the other sides are broadcast tables that are sent to all hbees.
+    /// The predicates of the filters met on the way to the catalog are accumulated
+    /// in `query_filters` so they can be used for partition pruning.
     fn split<'a>(
         &'a mut self,
         plan: &'a LogicalPlan,
-        partition_filters: &'a Option<String>,
-    ) -> BoxFuture<'a, Result<Vec<HBeeTableDesc>>> {
+        step: &'a BuzzStep,
+        mut query_filters: Vec<Expr>,
+    ) -> BoxFuture<'a, Result<CatalogSplit>> {
         async move {
             let new_inputs = datafusion::optimizer::utils::inputs(&plan);
             if new_inputs.len() > 1 {
-                Err(not_impl_err!(
-                    "Operations with more than one inputs are not supported",
-                ))
+                let catalog_inputs = new_inputs
+                    .into_iter()
+                    .filter(|input| {
+                        !utils::find_table_names::<CatalogTable>(input).is_empty()
+                    })
+                    .collect::<Vec<_>>();
+                if catalog_inputs.len() != 1 {
+                    return Err(not_impl_err!(
+                        "Operations with more than one inputs are only supported if exactly one of them reads from a catalog",
+                    ));
+                }
+                // the filters above the join might refer to the broadcast tables
+                self.split(catalog_inputs[0], step, vec![]).await
             } else if new_inputs.len() == 1 {
-                let mut filter_exprs = vec![];
-                if let LogicalPlan::Filter { predicate, .. } = &plan {
-                    plan_utils::split_expr(predicate, &mut filter_exprs);
+                match &plan {
+                    LogicalPlan::Filter { predicate, .. } => {
+                        let mut filter_exprs = vec![];
+                        plan_utils::split_expr(predicate, &mut filter_exprs);
+                        query_filters.extend(filter_exprs.into_iter().cloned());
+                    }
+                    // filters above these nodes might refer to aliases or aggregates
+                    LogicalPlan::Projection { .. } | LogicalPlan::Aggregate { .. } => {
+                        query_filters.clear();
+                    }
+                    _ => {}
                 }
-                let table_descs = self.split(new_inputs[0], partition_filters).await?;
-                Ok(table_descs)
+                self.split(new_inputs[0], step, query_filters).await
             } else if let Some(catalog_table) = Self::as_catalog(&plan) {
-                let table_descs = catalog_table.split(partition_filters).await?;
-                Ok(table_descs)
+                catalog_table
+                    .split(&step.partition_filter, &query_filters, step.bytes_per_hbee)
+                    .await
             } else {
                 Err(not_impl_err!("Split only works with catalog tables",))
             }
@@ -176,9 +763,14 @@ impl QueryPlanner {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
     use crate::datasource::CatalogTable;
     use crate::datasource::MockSplittableTable;
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
 
     #[tokio::test]
     async fn test_simple_query() {
@@ -195,19 +787,21 @@ mod tests {
                 name: "mapper".to_owned(),
                 step_type: BuzzStepType::HBee,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
             BuzzStep {
                 sql: "SELECT * FROM mapper".to_owned(),
                 name: "reducer".to_owned(),
                 step_type: BuzzStepType::HComb,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
         ];
 
         let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
         let plan = plan_res.expect("The planner failed on a simple query");
-        assert_eq!(plan.zones.len(), 1);
-        assert_eq!(plan.zones[0].hbee.len(), nb_split);
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), nb_split);
     }
 
     #[tokio::test]
@@ -219,12 +813,14 @@ mod tests {
                 name: "mapper".to_owned(),
                 step_type: BuzzStepType::HBee,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
             BuzzStep {
                 sql: "SELECT * FROM mapper".to_owned(),
                 name: "reducer".to_owned(),
                 step_type: BuzzStepType::HComb,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
         ];
 
@@ -253,19 +849,54 @@ mod tests {
                     "part_key_2>='part_value_001' AND part_key_2<='part_value_003'"
                         .to_owned(),
                 ),
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT * FROM mapper".to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+
+        let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
+        let plan = plan_res.expect("The planner failed on a query with condition");
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_query_with_partition_condition() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 2))),
+        );
+
+        let steps = vec![
+            BuzzStep {
+                sql: "SELECT * FROM test WHERE data_col=0 AND part_key_1>='part_value_002' AND part_key_2<='part_value_003'"
+                    .to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
             },
             BuzzStep {
                 sql: "SELECT * FROM mapper".to_owned(),
                 name: "reducer".to_owned(),
                 step_type: BuzzStepType::HComb,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
         ];
 
         let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
         let plan = plan_res.expect("The planner failed on a query with condition");
-        assert_eq!(plan.zones.len(), 1);
-        assert_eq!(plan.zones[0].hbee.len(), 3);
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), 2);
     }
 
     #[tokio::test]
@@ -283,19 +914,21 @@ mod tests {
                 name: "mapper".to_owned(),
                 step_type: BuzzStepType::HBee,
                 partition_filter: Some("part_key_1='not_in_partition_value'".to_owned()),
+                bytes_per_hbee: None,
             },
             BuzzStep {
                 sql: "SELECT * FROM mapper".to_owned(),
                 name: "reducer".to_owned(),
                 step_type: BuzzStepType::HComb,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
         ];
 
         let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
         let plan =
             plan_res.expect("The planner failed on a query with no data in catalog");
-        assert_eq!(plan.zones.len(), 0);
+        assert_eq!(plan.stages[0].zones.len(), 0);
     }
 
     #[tokio::test]
@@ -315,6 +948,7 @@ mod tests {
                 name: "mapper".to_owned(),
                 step_type: BuzzStepType::HBee,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
             BuzzStep {
                 sql: "SELECT data_col, count(cnt) FROM mapper GROUP BY data_col"
@@ -322,13 +956,569 @@ mod tests {
                 name: "reducer".to_owned(),
                 step_type: BuzzStepType::HComb,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
         ];
 
         let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
         let plan = plan_res.expect("The planner failed on a query with condition");
-        assert_eq!(plan.zones.len(), 1);
-        assert_eq!(plan.zones[0].hbee.len(), nb_split);
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), nb_split);
+    }
+
+    #[tokio::test]
+    async fn test_query_with_bytes_per_hbee() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+
+        let steps = vec![
+            BuzzStep {
+                sql: "SELECT * FROM test".to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                // the mock files are 999999999 bytes long
+                bytes_per_hbee: Some(2_000_000_000),
+            },
+            BuzzStep {
+                sql: "SELECT * FROM mapper".to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+
+        let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
+        let plan = plan_res.expect("The planner failed on a query with bytes_per_hbee");
+        assert_eq!(plan.nb_hbee, 3);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_auto_split_query() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+
+        let steps = vec![BuzzStep {
+            sql: "SELECT data_col, COUNT(data_col) AS cnt, AVG(data_col) FROM test GROUP BY data_col"
+                .to_owned(),
+            name: "mapper".to_owned(),
+            step_type: BuzzStepType::HBee,
+            partition_filter: None,
+            bytes_per_hbee: None,
+        }];
+
+        let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
+        let plan = plan_res.expect("The planner failed to split a single step query");
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), nb_split);
+        assert_eq!(
+            plan.stages[0].zones[0].hcomb.inputs[0]
+                .table
+                .schema()
+                .fields()
+                .len(),
+            3,
+            "The intermediate table should contain the group key, the count and the sum"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_split_count_distinct() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+
+        let steps = vec![BuzzStep {
+            sql: "SELECT COUNT(DISTINCT data_col) AS cnt FROM test".to_owned(),
+            name: "mapper".to_owned(),
+            step_type: BuzzStepType::HBee,
+            partition_filter: None,
+            bytes_per_hbee: None,
+        }];
+
+        let plan = planner
+            .plan("mock_query_id".to_owned(), steps, 3)
+            .await
+            .expect("The planner failed to split a count distinct query");
+        assert_eq!(plan.stages.len(), 2);
+        // the distinct values are hash partitioned among the hcombs that deduplicate them
+        assert_eq!(plan.stages[0].name, "mapper_distinct");
+        assert!(!plan.stages[0].is_output);
+        assert_eq!(plan.stages[0].zones.len(), 3);
+        for zone in &plan.stages[0].zones {
+            for hbee in &zone.hbee {
+                assert!(hbee.sql.ends_with("GROUP BY data_col"));
+                assert_eq!(hbee.shuffle_keys, vec!["__buzz_dst".to_owned()]);
+            }
+        }
+        // a single hcomb counts the deduplicated values of all the hcombs
+        assert_eq!(plan.stages[1].name, "mapper_final");
+        assert!(plan.stages[1].is_output);
+        assert_eq!(plan.stages[1].zones.len(), 1);
+        assert_eq!(plan.stages[1].zones[0].hcomb.inputs[0].table.nb_hbee(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_multi_stage_query() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+        planner.add_catalog(
+            "other_test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+
+        let steps = vec![
+            BuzzStep {
+                sql: "SELECT data_col FROM test".to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT data_col, count(data_col) AS cnt FROM mapper GROUP BY data_col"
+                    .to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT max(cnt) FROM reducer".to_owned(),
+                name: "final_reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT data_col FROM other_test".to_owned(),
+                name: "other_mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT * FROM other_mapper".to_owned(),
+                name: "other_reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+
+        let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
+        let plan = plan_res.expect("The planner failed on a multi stage query");
+        assert_eq!(plan.nb_hbee, 2 * nb_split);
+        assert_eq!(plan.stages.len(), 3);
+
+        assert_eq!(plan.stages[0].name, "reducer");
+        assert_eq!(plan.stages[0].upstream, None);
+        assert!(!plan.stages[0].is_output);
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), nb_split);
+
+        assert_eq!(plan.stages[1].name, "final_reducer");
+        assert_eq!(plan.stages[1].upstream, Some("reducer".to_owned()));
+        assert!(plan.stages[1].is_output);
+        assert_eq!(plan.stages[1].zones.len(), 1);
+        assert_eq!(plan.stages[1].zones[0].hbee.len(), 0);
+        assert_eq!(plan.stages[1].zones[0].hcomb.inputs[0].table.nb_hbee(), 1);
+        assert_eq!(
+            plan.stages[1].zones[0].hcomb.inputs[0].table.query_id(),
+            "mock_query_id-reducer"
+        );
+
+        assert_eq!(plan.stages[2].name, "other_reducer");
+        assert!(plan.stages[2].is_output);
+        assert_eq!(plan.stages[2].zones[0].hbee.len(), nb_split);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_step_graphs() {
+        let mut planner = QueryPlanner::new();
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(5, 0))),
+        );
+
+        let mapper = || BuzzStep {
+            sql: "SELECT * FROM test".to_owned(),
+            name: "mapper".to_owned(),
+            step_type: BuzzStepType::HBee,
+            partition_filter: None,
+            bytes_per_hbee: None,
+        };
+        let reducer = |name: &str| BuzzStep {
+            sql: "SELECT * FROM mapper".to_owned(),
+            name: name.to_owned(),
+            step_type: BuzzStepType::HComb,
+            partition_filter: None,
+            bytes_per_hbee: None,
+        };
+
+        let steps = vec![mapper(), reducer("reducer"), reducer("other_reducer")];
+        planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect_err("An HBee step cannot be consumed twice");
+
+        let steps = vec![mapper(), mapper(), reducer("reducer")];
+        planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect_err("Step names should be unique");
+
+        let mut unconsumed = mapper();
+        unconsumed.name = "unconsumed".to_owned();
+        let steps = vec![mapper(), unconsumed, reducer("reducer")];
+        planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect_err("All HBee steps should be consumed");
+
+        let steps = vec![mapper(), reducer("reducer")];
+        let err = planner
+            .plan("mock_query_id".to_owned(), steps, 0)
+            .await
+            .expect_err("At least one hcomb is required");
+        assert!(matches!(err, BuzzError::BadRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_union_all_query() {
+        let mut planner = QueryPlanner::new();
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(5, 0))),
+        );
+        planner.add_catalog(
+            "other",
+            CatalogTable::new(Box::new(MockSplittableTable::new(3, 0))),
+        );
+
+        let steps = |hbee_sql: &str| {
+            vec![
+                BuzzStep {
+                    sql: hbee_sql.to_owned(),
+                    name: "mapper".to_owned(),
+                    step_type: BuzzStepType::HBee,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+                BuzzStep {
+                    sql: "SELECT data_col FROM mapper LIMIT 10".to_owned(),
+                    name: "reducer".to_owned(),
+                    step_type: BuzzStepType::HComb,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+            ]
+        };
+
+        let plan = planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT data_col FROM test UNION ALL SELECT data_col FROM other WHERE data_col > 0"),
+                1,
+            )
+            .await
+            .expect("The planner failed on a union of catalogs");
+        assert_eq!(plan.nb_hbee, 8);
+        let zone = &plan.stages[0].zones[0];
+        assert_eq!(zone.hcomb.inputs[0].table.nb_hbee(), 8);
+        let sources = zone
+            .hbee
+            .iter()
+            .map(|hbee| (hbee.source.as_str(), hbee.sql.as_str()))
+            .collect::<Vec<_>>();
+        // the limit of the hcomb is pushed into each branch
+        assert_eq!(
+            sources
+                .iter()
+                .filter(|(source, sql)| *source == "test"
+                    && *sql == "SELECT data_col FROM test LIMIT 10")
+                .count(),
+            5
+        );
+        assert_eq!(
+            sources
+                .iter()
+                .filter(|(source, sql)| *source == "other"
+                    && *sql == "SELECT data_col FROM other WHERE data_col > 0 LIMIT 10")
+                .count(),
+            3
+        );
+
+        planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT data_col FROM test UNION ALL SELECT data_col, data_col AS other_col FROM other"),
+                1,
+            )
+            .await
+            .expect_err("The branches of the union should have the same columns");
+    }
+
+    #[tokio::test]
+    async fn test_join_hbee_steps() {
+        let mut planner = QueryPlanner::new();
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(5, 0))),
+        );
+        planner.add_catalog(
+            "other",
+            CatalogTable::new(Box::new(MockSplittableTable::new(3, 0))),
+        );
+
+        let steps = |hcomb_sql: &str| {
+            vec![
+                BuzzStep {
+                    sql: "SELECT data_col FROM test".to_owned(),
+                    name: "left_mapper".to_owned(),
+                    step_type: BuzzStepType::HBee,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+                BuzzStep {
+                    sql: "SELECT data_col AS other_col FROM other".to_owned(),
+                    name: "right_mapper".to_owned(),
+                    step_type: BuzzStepType::HBee,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+                BuzzStep {
+                    sql: hcomb_sql.to_owned(),
+                    name: "reducer".to_owned(),
+                    step_type: BuzzStepType::HComb,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+            ]
+        };
+
+        let plan = planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT data_col, count(other_col) FROM left_mapper JOIN right_mapper ON data_col = other_col GROUP BY data_col"),
+                2,
+            )
+            .await
+            .expect("The planner failed on a join of HBee steps");
+        assert_eq!(plan.nb_hbee, 8);
+        assert_eq!(plan.stages.len(), 1);
+        assert_eq!(plan.stages[0].hbee_steps.len(), 2);
+        // a single hcomb collects the results of each step separately
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        let zone = &plan.stages[0].zones[0];
+        let inputs = zone
+            .hcomb
+            .inputs
+            .iter()
+            .map(|input| {
+                (
+                    input.source.as_str(),
+                    input.table.query_id(),
+                    input.table.nb_hbee(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            vec![
+                ("left_mapper", "mock_query_id-left_mapper", 5),
+                ("right_mapper", "mock_query_id-right_mapper", 3)
+            ]
+        );
+        assert_eq!(zone.hbee.len(), 8);
+        for hbee in &zone.hbee {
+            let step = if hbee.source == "test" {
+                "left_mapper"
+            } else {
+                "right_mapper"
+            };
+            assert_eq!(hbee.query_id, format!("mock_query_id-{}", step));
+            assert!(hbee.shuffle_keys.is_empty());
+        }
+
+        planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT data_col FROM left_mapper JOIN left_mapper ON data_col = data_col"),
+                1,
+            )
+            .await
+            .expect_err("A step can only be read once");
+    }
+
+    #[tokio::test]
+    async fn test_query_with_broadcast_join() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("label_id", DataType::Int64, false),
+            Field::new("label", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![0, 1])),
+                Arc::new(StringArray::from(vec!["zero", "one"])),
+            ],
+        )
+        .unwrap();
+        planner
+            .add_broadcast_table(BroadcastTable::new(
+                "labels".to_owned(),
+                schema,
+                vec![batch],
+            ))
+            .unwrap();
+
+        let steps = vec![
+            BuzzStep {
+                sql: "SELECT label, count(data_col) AS cnt FROM test JOIN labels ON data_col = label_id GROUP BY label"
+                    .to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT label, sum(cnt) FROM mapper GROUP BY label".to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+
+        let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
+        let plan = plan_res.expect("The planner failed on a broadcast join");
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), nb_split);
+        let hbee_plan = &plan.stages[0].zones[0].hbee[0];
+        assert_eq!(hbee_plan.source, "test");
+        assert_eq!(hbee_plan.broadcasts.len(), 1);
+        assert_eq!(hbee_plan.broadcasts[0].name(), "labels");
+    }
+
+    #[test]
+    fn test_broadcast_table_too_large() {
+        let mut planner = QueryPlanner::new();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "label",
+            DataType::Utf8,
+            false,
+        )]));
+        let large_label = "x".repeat(MAX_BROADCAST_BYTES);
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(vec![large_label.as_str()]))],
+        )
+        .unwrap();
+        let err = planner
+            .add_broadcast_table(BroadcastTable::new(
+                "labels".to_owned(),
+                schema,
+                vec![batch],
+            ))
+            .unwrap_err();
+        assert!(matches!(err, BuzzError::BadRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_query_with_multiple_hcombs() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+
+        let steps = |hcomb_sql: &str| {
+            vec![
+                BuzzStep {
+                    sql: "SELECT data_col FROM test".to_owned(),
+                    name: "mapper".to_owned(),
+                    step_type: BuzzStepType::HBee,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+                BuzzStep {
+                    sql: hcomb_sql.to_owned(),
+                    name: "reducer".to_owned(),
+                    step_type: BuzzStepType::HComb,
+                    partition_filter: None,
+                    bytes_per_hbee: None,
+                },
+            ]
+        };
+
+        // groups are hash partitioned among the hcombs
+        let plan = planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT data_col, count(data_col) FROM mapper GROUP BY data_col"),
+                3,
+            )
+            .await
+            .expect("The planner failed on a grouped query");
+        assert_eq!(plan.stages[0].zones.len(), 3);
+        for zone in &plan.stages[0].zones {
+            assert_eq!(zone.hcomb.inputs[0].table.nb_hbee(), nb_split);
+            for hbee in &zone.hbee {
+                assert_eq!(hbee.shuffle_keys, vec!["data_col".to_owned()]);
+            }
+        }
+
+        // without grouping, rows can be sent to any hcomb
+        let plan = planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT * FROM mapper WHERE data_col > 0"),
+                3,
+            )
+            .await
+            .expect("The planner failed on a query without aggregation");
+        assert_eq!(plan.stages[0].zones.len(), 3);
+        assert_eq!(plan.stages[0].zones[0].hcomb.inputs[0].table.nb_hbee(), 2);
+        assert!(plan.stages[0].zones[0].hbee[0].shuffle_keys.is_empty());
+
+        // a global aggregation can only run on a single hcomb
+        let plan = planner
+            .plan(
+                "mock_query_id".to_owned(),
+                steps("SELECT max(data_col) FROM mapper"),
+                3,
+            )
+            .await
+            .expect("The planner failed on a global aggregation");
+        assert_eq!(plan.stages[0].zones.len(), 1);
+        assert_eq!(
+            plan.stages[0].zones[0].hcomb.inputs[0].table.nb_hbee(),
+            nb_split
+        );
+        assert!(plan.stages[0].zones[0].hbee[0].shuffle_keys.is_empty());
     }
 
     #[tokio::test]
@@ -346,16 +1536,172 @@ mod tests {
                 name: "mapper".to_owned(),
                 step_type: BuzzStepType::HBee,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
             BuzzStep {
                 sql: "SELECT * FROM test".to_owned(),
                 name: "reducer".to_owned(),
                 step_type: BuzzStepType::HComb,
                 partition_filter: None,
+                bytes_per_hbee: None,
             },
         ];
 
         let plan_res = planner.plan("mock_query_id".to_owned(), steps, 1).await;
         plan_res.expect_err("The source table for the reducer step is not an HBee");
     }
+
+    #[tokio::test]
+    async fn test_explain_query() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 1))),
+        );
+
+        let steps = vec![
+            BuzzStep {
+                sql: "SELECT * FROM test WHERE part_key_1<='part_value_003'".to_owned(),
+                name: "mapper".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+            BuzzStep {
+                sql: "SELECT * FROM mapper".to_owned(),
+                name: "reducer".to_owned(),
+                step_type: BuzzStepType::HComb,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            },
+        ];
+
+        let plan = planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect("The planner failed on a query with condition");
+        let hbee_step = &plan.stages[0].hbee_steps[0];
+        assert_eq!(
+            hbee_step.pruned_partitions,
+            vec![
+                "part_key_1=part_value_004".to_owned(),
+                "part_key_1=part_value_005".to_owned()
+            ]
+        );
+
+        let explained = plan.to_string();
+        assert!(explained.contains("1 stage(s), 3 hbee(s)"));
+        assert!(explained.contains(
+            "pruned partitions: part_key_1=part_value_004, part_key_1=part_value_005"
+        ));
+        assert!(explained.contains("s3://santas-bucket/file_1 (999999999 bytes)"));
+    }
+
+    #[tokio::test]
+    async fn test_limit_query() {
+        let mut planner = QueryPlanner::new();
+        let nb_split = 5;
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(nb_split, 0))),
+        );
+
+        let steps = vec![BuzzStep {
+            sql: "SELECT * FROM test WHERE data_col=0 LIMIT 10".to_owned(),
+            name: "query".to_owned(),
+            step_type: BuzzStepType::HBee,
+            partition_filter: None,
+            bytes_per_hbee: None,
+        }];
+        let plan = planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect("The planner failed on a limit query");
+        assert_eq!(plan.stages[0].limit, Some(10));
+        assert_eq!(plan.stages[0].zones[0].hbee.len(), nb_split);
+        assert!(plan.stages[0].zones[0].hbee[0].sql.ends_with("LIMIT 10"));
+
+        // the limit cannot be pushed through an aggregation
+        let steps = vec![BuzzStep {
+            sql: "SELECT data_col, COUNT(*) FROM test GROUP BY data_col LIMIT 10"
+                .to_owned(),
+            name: "query".to_owned(),
+            step_type: BuzzStepType::HBee,
+            partition_filter: None,
+            bytes_per_hbee: None,
+        }];
+        let plan = planner
+            .plan("mock_query_id".to_owned(), steps, 1)
+            .await
+            .expect("The planner failed on a limit query with aggregation");
+        assert_eq!(plan.stages[0].limit, None);
+        assert!(!plan.stages[0].zones[0].hbee[0].sql.contains("LIMIT"));
+    }
+
+    #[tokio::test]
+    async fn test_top_k_query() {
+        let mut planner = QueryPlanner::new();
+        planner.add_catalog(
+            "test",
+            CatalogTable::new(Box::new(MockSplittableTable::new(5, 0))),
+        );
+        planner.add_catalog(
+            "test_part",
+            CatalogTable::new(Box::new(MockSplittableTable::new(5, 1))),
+        );
+        async fn hbee_sql(planner: &mut QueryPlanner, sql: &str) -> String {
+            let steps = vec![BuzzStep {
+                sql: sql.to_owned(),
+                name: "query".to_owned(),
+                step_type: BuzzStepType::HBee,
+                partition_filter: None,
+                bytes_per_hbee: None,
+            }];
+            let plan = planner
+                .plan("mock_query_id".to_owned(), steps, 1)
+                .await
+                .expect("The planner failed on a top-k query");
+            assert_eq!(plan.stages[0].limit, None);
+            plan.stages[0].zones[0].hbee[0].sql.clone()
+        }
+
+        // raw rows
+        let sql = hbee_sql(
+            &mut planner,
+            "SELECT * FROM test ORDER BY data_col DESC LIMIT 10",
+        )
+        .await;
+        assert!(sql.contains("ORDER BY data_col DESC"));
+        assert!(sql.ends_with("LIMIT 10"));
+
+        // ordered by group key
+        let sql = hbee_sql(
+            &mut planner,
+            "SELECT data_col, COUNT(*) AS cnt FROM test GROUP BY data_col ORDER BY data_col LIMIT 10",
+        ).await;
+        assert!(sql.contains("ORDER BY data_col ASC"));
+        assert!(sql.ends_with("LIMIT 10"));
+
+        // ordered by all the group keys
+        let sql = hbee_sql(
+            &mut planner,
+            "SELECT data_col, part_key_1, COUNT(*) AS cnt FROM test_part GROUP BY data_col, part_key_1 ORDER BY part_key_1, data_col LIMIT 10",
+        ).await;
+        assert!(sql.ends_with("LIMIT 10"));
+
+        // ordered by some of the group keys only, the hbees might keep different groups
+        let sql = hbee_sql(
+            &mut planner,
+            "SELECT data_col, part_key_1, COUNT(*) AS cnt FROM test_part GROUP BY data_col, part_key_1 ORDER BY data_col LIMIT 10",
+        ).await;
+        assert!(!sql.contains("LIMIT"));
+
+        // ordered by aggregate, the top-k of each hbee might miss some groups
+        let sql = hbee_sql(
+            &mut planner,
+            "SELECT data_col, COUNT(*) AS cnt FROM test GROUP BY data_col ORDER BY cnt DESC LIMIT 10",
+        ).await;
+        assert!(!sql.contains("LIMIT"));
+    }
 }
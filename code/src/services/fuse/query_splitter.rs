@@ -0,0 +1,929 @@
+use super::top_k::TopK;
+use crate::error::{BuzzError, Result};
+use crate::models::query::{BuzzStep, BuzzStepType};
+use crate::sketch::SketchFunction;
+use crate::{internal_err, not_impl_err};
+use datafusion::sql::parser::{DFParser, Statement as DFStatement};
+use sqlparser::ast::{
+    BinaryOperator, DataType, Expr, Function, Ident, ObjectName, OrderByExpr, Query,
+    Select, SelectItem, SetExpr, SetOperator, Statement, TableWithJoins, Value,
+};
+
+/// Prefix for the intermediate columns that hold the group by keys
+const GROUP_PREFIX: &str = "__buzz_grp";
+/// Prefix for the intermediate columns that hold the partial aggregations
+const AGG_PREFIX: &str = "__buzz_agg";
+/// Name of the intermediate column that holds the values counted distinctly
+const DISTINCT_COL: &str = "__buzz_dst";
+/// Suffix of the HComb step that deduplicates the values counted distinctly
+const DISTINCT_STEP_SUFFIX: &str = "_distinct";
+
+/// The SQL statements resulting from the split of a query into a map and a reduce part
+#[derive(Debug)]
+pub struct SplitQuery {
+    pub hbee_sql: String,
+    /// For `COUNT(DISTINCT)` queries, the query that deduplicates the hbee results
+    /// by group keys and distinct values. The final query then reads from its output.
+    pub distinct_sql: Option<String>,
+    pub hcomb_sql: String,
+}
+
+/// Derives the HBee and HComb steps from a single step containing the complete query.
+/// The HBee step keeps the name of the original step so the HComb step can refer to it.
+/// `COUNT(DISTINCT)` queries are split in two HComb steps: the first one deduplicates
+/// the values and can be shuffled between several hcombs, the second one counts them.
+pub fn split_step(step: BuzzStep) -> Result<Vec<BuzzStep>> {
+    let split = split(&step.sql, &step.name)?;
+    let hcomb_step = |sql: String, name: String| BuzzStep {
+        sql,
+        name,
+        partition_filter: None,
+        bytes_per_hbee: None,
+        step_type: BuzzStepType::HComb,
+    };
+    let mut steps = vec![];
+    if let Some(distinct_sql) = split.distinct_sql {
+        steps.push(hcomb_step(
+            distinct_sql,
+            format!("{}{}", step.name, DISTINCT_STEP_SUFFIX),
+        ));
+    }
+    steps.push(hcomb_step(split.hcomb_sql, format!("{}_final", step.name)));
+    steps.insert(
+        0,
+        BuzzStep {
+            sql: split.hbee_sql,
+            name: step.name,
+            partition_filter: step.partition_filter,
+            bytes_per_hbee: step.bytes_per_hbee,
+            step_type: BuzzStepType::HBee,
+        },
+    );
+    Ok(steps)
+}
+
+/// Splits the given SQL query into a partial query to be run by the hbees and a final
+/// query to be run by the hcomb on the intermediate table named `intermediate_table`.
+/// - COUNT becomes a SUM of counts, SUM/MIN/MAX are applied twice
+/// - AVG is decomposed into a SUM and a COUNT
+/// - GROUP BY keys are forwarded as intermediate columns and grouped again by the hcomb
+/// - COUNT(DISTINCT col) adds `col` to the hbee group by keys, the distinct query groups
+///   again by these keys and the final query counts the deduplicated values of `col`
+pub fn split(sql: &str, intermediate_table: &str) -> Result<SplitQuery> {
+    let query = parse_query(sql)?;
+    let select = match &query.body {
+        SetExpr::Select(select) => select.as_ref(),
+        _ => {
+            return Err(not_impl_err!(
+                "Only simple SELECT statements can be split automatically"
+            ))
+        }
+    };
+
+    let mut hcomb_select = select.clone();
+    hcomb_select.from = from_table(intermediate_table)?;
+    hcomb_select.selection = None;
+
+    let mut hbee_select = select.clone();
+    let mut hcomb_query = query.clone();
+    let mut distinct_sql = None;
+
+    if select.group_by.is_empty() && !has_aggregate_select(select) {
+        // no aggregation, the hcomb only needs to apply the ordering and the limit
+        hcomb_select.projection = vec![SelectItem::Wildcard];
+    } else {
+        let mut ctx = SplitContext::new(select);
+        hcomb_select.projection = select
+            .projection
+            .iter()
+            .map(|item| ctx.final_item(item))
+            .collect::<Result<_>>()?;
+        hcomb_select.having = select
+            .having
+            .as_ref()
+            .map(|having| ctx.to_final(having))
+            .transpose()?;
+        hcomb_query.order_by = query
+            .order_by
+            .iter()
+            .map(|order_by| {
+                Ok(OrderByExpr {
+                    expr: ctx.to_final(&order_by.expr)?,
+                    ..order_by.clone()
+                })
+            })
+            .collect::<Result<_>>()?;
+        hcomb_select.group_by = ctx
+            .group_keys
+            .iter()
+            .map(|(_, col)| Expr::Identifier(Ident::new(col)))
+            .collect();
+        if let Some((distinct_expr, distinct_col)) = &ctx.distinct {
+            hbee_select.group_by.push(distinct_expr.clone());
+            distinct_sql = Some(ctx.distinct_query(intermediate_table, distinct_col)?);
+            hcomb_select.from =
+                from_table(&format!("{}{}", intermediate_table, DISTINCT_STEP_SUFFIX))?;
+        }
+        hbee_select.distinct = false;
+        hbee_select.projection = ctx.hbee_items;
+        hbee_select.having = None;
+    }
+
+    hcomb_query.body = SetExpr::Select(Box::new(hcomb_select));
+    let mut hbee_query = query.clone();
+    hbee_query.body = SetExpr::Select(Box::new(hbee_select));
+    hbee_query.order_by = vec![];
+    hbee_query.limit = None;
+
+    Ok(SplitQuery {
+        hbee_sql: hbee_query.to_string(),
+        distinct_sql,
+        hcomb_sql: hcomb_query.to_string(),
+    })
+}
+
+/// Adds a LIMIT clause to the given SQL query, or lowers the existing one.
+/// If the existing limit is not a literal, the query is left unchanged.
+pub fn push_limit(sql: &str, limit: usize) -> Result<String> {
+    let mut query = parse_query(sql)?;
+    match &query.limit {
+        None => query.limit = Some(Expr::Value(Value::Number(limit.to_string()))),
+        Some(Expr::Value(Value::Number(existing))) => {
+            if existing.parse::<usize>().map_or(false, |n| n > limit) {
+                query.limit = Some(Expr::Value(Value::Number(limit.to_string())));
+            }
+        }
+        Some(_) => {}
+    }
+    Ok(query.to_string())
+}
+
+/// Adds the ORDER BY and LIMIT clauses of the given top-k to the SQL query.
+/// The query should not be ordered or limited already.
+pub fn push_top_k(sql: &str, top_k: &TopK) -> Result<String> {
+    let mut query = parse_query(sql)?;
+    if !query.order_by.is_empty() || query.limit.is_some() {
+        return Err(internal_err!(
+            "Cannot push a top-k into an ordered or limited query: {}",
+            sql
+        ));
+    }
+    query.order_by = top_k
+        .order_by
+        .iter()
+        .map(|key| OrderByExpr {
+            expr: Expr::Identifier(Ident::new(&key.column)),
+            asc: Some(key.asc),
+            nulls_first: Some(key.nulls_first),
+        })
+        .collect();
+    query.limit = Some(Expr::Value(Value::Number(top_k.limit.to_string())));
+    Ok(query.to_string())
+}
+
+/// Splits a `SELECT ... UNION ALL SELECT ...` query into the SQL of each of its branches,
+/// so that each branch can be distributed over its own catalog. A query that is not
+/// a union is returned as is.
+pub fn union_branches(sql: &str) -> Result<Vec<String>> {
+    let query = parse_query(sql)?;
+    if let SetExpr::Select(_) = &query.body {
+        return Ok(vec![sql.to_owned()]);
+    }
+    if !query.order_by.is_empty() || query.limit.is_some() || !query.ctes.is_empty() {
+        return Err(not_impl_err!(
+            "ORDER BY, LIMIT and WITH clauses cannot be applied to a UNION ALL in an HBee step, they should be moved to the HComb step"
+        ));
+    }
+    let mut branches = vec![];
+    add_union_branches(&query.body, &mut branches)?;
+    Ok(branches)
+}
+
+fn add_union_branches(set_expr: &SetExpr, branches: &mut Vec<String>) -> Result<()> {
+    match set_expr {
+        SetExpr::Select(select) => {
+            branches.push(select.to_string());
+            Ok(())
+        }
+        SetExpr::Query(query) if query.order_by.is_empty() && query.limit.is_none() => {
+            add_union_branches(&query.body, branches)
+        }
+        SetExpr::SetOperation {
+            op: SetOperator::Union,
+            all: true,
+            left,
+            right,
+        } => {
+            add_union_branches(left, branches)?;
+            add_union_branches(right, branches)
+        }
+        SetExpr::SetOperation {
+            op: SetOperator::Union,
+            all: false,
+            ..
+        } => Err(not_impl_err!(
+            "UNION requires a deduplication that cannot run in an HBee step, use UNION ALL instead"
+        )),
+        other => Err(not_impl_err!(
+            "Only SELECT statements combined with UNION ALL are supported in HBee steps, found {}",
+            other
+        )),
+    }
+}
+
+/// Accumulates the partial expressions computed by the hbees and
+/// the final expressions that combine them in the hcomb.
+struct SplitContext {
+    /// The group by expressions (in SQL form) and the name of the associated intermediate column
+    group_keys: Vec<(String, String)>,
+    /// The aliases of the projection, they can be referred to in the ORDER BY clause
+    aliases: Vec<String>,
+    /// The projection of the hbee query
+    hbee_items: Vec<SelectItem>,
+    /// The partial expressions (in SQL form) already added to the hbee projection,
+    /// with their intermediate column and the aggregation that merges them if any
+    partials: Vec<(String, Ident, Option<&'static str>)>,
+    /// The expression counted distinctly and its intermediate column
+    distinct: Option<(Expr, Ident)>,
+}
+
+impl SplitContext {
+    fn new(select: &Select) -> Self {
+        let mut group_keys = vec![];
+        let mut hbee_items = vec![];
+        for (i, group_expr) in select.group_by.iter().enumerate() {
+            let col = match group_expr {
+                Expr::Identifier(ident) => {
+                    hbee_items.push(SelectItem::UnnamedExpr(group_expr.clone()));
+                    ident.value.clone()
+                }
+                _ => {
+                    let col = format!("{}{}", GROUP_PREFIX, i);
+                    hbee_items.push(SelectItem::ExprWithAlias {
+                        expr: group_expr.clone(),
+                        alias: Ident::new(&col),
+                    });
+                    col
+                }
+            };
+            group_keys.push((group_expr.to_string(), col));
+        }
+        let aliases = select
+            .projection
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::ExprWithAlias { alias, .. } => Some(alias.value.clone()),
+                _ => None,
+            })
+            .collect();
+        Self {
+            group_keys,
+            aliases,
+            hbee_items,
+            partials: vec![],
+            distinct: None,
+        }
+    }
+
+    fn final_item(&mut self, item: &SelectItem) -> Result<SelectItem> {
+        match item {
+            SelectItem::UnnamedExpr(expr) => {
+                Ok(SelectItem::UnnamedExpr(self.to_final(expr)?))
+            }
+            SelectItem::ExprWithAlias { expr, alias } => Ok(SelectItem::ExprWithAlias {
+                expr: self.to_final(expr)?,
+                alias: alias.clone(),
+            }),
+            _ => Err(BuzzError::BadRequest(
+                "Wildcards cannot be used in aggregation queries".to_owned(),
+            )),
+        }
+    }
+
+    /// Converts an expression of the original query into its equivalent on the intermediate table
+    fn to_final(&mut self, expr: &Expr) -> Result<Expr> {
+        let expr_str = expr.to_string();
+        if let Some((_, col)) = self.group_keys.iter().find(|(key, _)| key == &expr_str) {
+            return Ok(Expr::Identifier(Ident::new(col)));
+        }
+        match expr {
+            Expr::Function(fun) if is_aggregate(fun) => self.final_aggregate(fun),
+            Expr::Function(fun) if is_unsplittable_aggregate(fun) => Err(not_impl_err!(
+                "The aggregate function {} cannot be split automatically, only {} and the approximate sketches can",
+                fun.name,
+                SPLITTABLE_AGGREGATES.join(", ")
+            )),
+            Expr::Function(fun) => Ok(Expr::Function(Function {
+                args: fun
+                    .args
+                    .iter()
+                    .map(|arg| self.to_final(arg))
+                    .collect::<Result<_>>()?,
+                ..fun.clone()
+            })),
+            Expr::BinaryOp { left, op, right } => Ok(Expr::BinaryOp {
+                left: Box::new(self.to_final(left)?),
+                op: op.clone(),
+                right: Box::new(self.to_final(right)?),
+            }),
+            Expr::UnaryOp { op, expr } => Ok(Expr::UnaryOp {
+                op: op.clone(),
+                expr: Box::new(self.to_final(expr)?),
+            }),
+            Expr::Nested(expr) => Ok(Expr::Nested(Box::new(self.to_final(expr)?))),
+            Expr::Cast { expr, data_type } => Ok(Expr::Cast {
+                expr: Box::new(self.to_final(expr)?),
+                data_type: data_type.clone(),
+            }),
+            Expr::IsNull(expr) => Ok(Expr::IsNull(Box::new(self.to_final(expr)?))),
+            Expr::IsNotNull(expr) => {
+                Ok(Expr::IsNotNull(Box::new(self.to_final(expr)?)))
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Ok(Expr::InList {
+                expr: Box::new(self.to_final(expr)?),
+                list: list
+                    .iter()
+                    .map(|item| self.to_final(item))
+                    .collect::<Result<_>>()?,
+                negated: *negated,
+            }),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => Ok(Expr::Between {
+                expr: Box::new(self.to_final(expr)?),
+                negated: *negated,
+                low: Box::new(self.to_final(low)?),
+                high: Box::new(self.to_final(high)?),
+            }),
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => Ok(Expr::Case {
+                operand: operand
+                    .as_ref()
+                    .map(|operand| self.to_final(operand).map(Box::new))
+                    .transpose()?,
+                conditions: conditions
+                    .iter()
+                    .map(|condition| self.to_final(condition))
+                    .collect::<Result<_>>()?,
+                results: results
+                    .iter()
+                    .map(|result| self.to_final(result))
+                    .collect::<Result<_>>()?,
+                else_result: else_result
+                    .as_ref()
+                    .map(|else_result| self.to_final(else_result).map(Box::new))
+                    .transpose()?,
+            }),
+            Expr::Extract { field, expr } => Ok(Expr::Extract {
+                field: field.clone(),
+                expr: Box::new(self.to_final(expr)?),
+            }),
+            Expr::Collate { expr, collation } => Ok(Expr::Collate {
+                expr: Box::new(self.to_final(expr)?),
+                collation: collation.clone(),
+            }),
+            Expr::Identifier(ident) if self.aliases.contains(&ident.value) => {
+                Ok(expr.clone())
+            }
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                Err(BuzzError::BadRequest(format!(
+                    "Column {} must appear in the GROUP BY clause or be used in an aggregate function",
+                    expr
+                )))
+            }
+            Expr::Value(_) | Expr::TypedString { .. } => Ok(expr.clone()),
+            other => Err(BuzzError::BadRequest(format!(
+                "The expression {} cannot be split automatically",
+                other
+            ))),
+        }
+    }
+
+    fn final_aggregate(&mut self, fun: &Function) -> Result<Expr> {
+        if fun.distinct {
+            return self.final_distinct(fun);
+        }
+        if fun.over.is_some() {
+            return Err(not_impl_err!(
+                "Window functions cannot be split automatically: {}",
+                Expr::Function(fun.clone())
+            ));
+        }
+        let fun_name = fun.name.to_string().to_uppercase();
+        let final_expr = match fun_name.as_str() {
+            "COUNT" => aggregate("SUM", self.add_partial(fun.clone(), Some("SUM")), fun),
+            "SUM" => aggregate("SUM", self.add_partial(fun.clone(), Some("SUM")), fun),
+            "MIN" => aggregate("MIN", self.add_partial(fun.clone(), Some("MIN")), fun),
+            "MAX" => aggregate("MAX", self.add_partial(fun.clone(), Some("MAX")), fun),
+            "AVG" => {
+                let sum_col = self.add_partial(with_name(fun, "SUM"), Some("SUM"));
+                let count_col = self.add_partial(with_name(fun, "COUNT"), Some("SUM"));
+                Expr::BinaryOp {
+                    left: Box::new(to_double(aggregate("SUM", sum_col, fun))),
+                    op: BinaryOperator::Divide,
+                    right: Box::new(to_double(aggregate("SUM", count_col, fun))),
+                }
+            }
+            _ => match SketchFunction::from_name(&fun_name) {
+                Some(sketch) => self.final_sketch(sketch, fun)?,
+                None => {
+                    unreachable!("Only aggregates listed in is_aggregate() can be split")
+                }
+            },
+        };
+        Ok(final_expr)
+    }
+
+    /// The hbees compute the serialized sketch of the values, the hcomb merges them
+    fn final_sketch(&mut self, sketch: SketchFunction, fun: &Function) -> Result<Expr> {
+        let nb_args = if sketch.has_param() { 2 } else { 1 };
+        if fun.args.len() != nb_args {
+            return Err(BuzzError::BadRequest(format!(
+                "{} expects {} argument(s), found {}",
+                sketch.name(),
+                nb_args,
+                fun.args.len()
+            )));
+        }
+        let partial = Function {
+            name: ObjectName(vec![Ident::new(sketch.partial_name())]),
+            args: vec![fun.args[0].clone()],
+            ..fun.clone()
+        };
+        let mut args = vec![Expr::Identifier(self.add_partial(partial, None))];
+        args.extend(fun.args[1..].iter().cloned());
+        Ok(Expr::Function(Function {
+            name: ObjectName(vec![Ident::new(sketch.merge_name())]),
+            args,
+            ..fun.clone()
+        }))
+    }
+
+    /// The hbees group by the counted expression, so the final count is a count of the
+    /// values deduplicated by the distinct query. Only one expression can be counted.
+    fn final_distinct(&mut self, fun: &Function) -> Result<Expr> {
+        let fun_name = fun.name.to_string().to_uppercase();
+        if fun_name != "COUNT" || fun.args.len() != 1 || fun.args[0] == Expr::Wildcard {
+            return Err(not_impl_err!(
+                "Only COUNT(DISTINCT expr) can be split automatically, found {}",
+                Expr::Function(fun.clone())
+            ));
+        }
+        let arg = &fun.args[0];
+        let col = match &self.distinct {
+            Some((expr, col)) if expr == arg => col.clone(),
+            Some((expr, _)) => {
+                return Err(not_impl_err!(
+                    "Only one expression can be counted distinctly, found {} and {}",
+                    expr,
+                    arg
+                ))
+            }
+            None => {
+                let col = Ident::new(DISTINCT_COL);
+                self.hbee_items.push(SelectItem::ExprWithAlias {
+                    expr: arg.clone(),
+                    alias: col.clone(),
+                });
+                self.distinct = Some((arg.clone(), col.clone()));
+                col
+            }
+        };
+        let count = Function {
+            distinct: false,
+            ..fun.clone()
+        };
+        Ok(aggregate("COUNT", col, &count))
+    }
+
+    /// The query that groups the hbee results by the group keys and the distinct values,
+    /// merging the other partial aggregations so they can be merged again by the final query
+    fn distinct_query(
+        &self,
+        intermediate_table: &str,
+        distinct_col: &Ident,
+    ) -> Result<String> {
+        let mut keys = self
+            .group_keys
+            .iter()
+            .map(|(_, col)| Expr::Identifier(Ident::new(col)))
+            .collect::<Vec<_>>();
+        keys.push(Expr::Identifier(distinct_col.clone()));
+        let mut projection = keys
+            .iter()
+            .cloned()
+            .map(SelectItem::UnnamedExpr)
+            .collect::<Vec<_>>();
+        for (partial, col, merge) in &self.partials {
+            let merge = merge.ok_or_else(|| {
+                not_impl_err!(
+                    "{} cannot be split automatically together with COUNT(DISTINCT)",
+                    partial
+                )
+            })?;
+            projection.push(SelectItem::ExprWithAlias {
+                expr: Expr::Function(Function {
+                    name: ObjectName(vec![Ident::new(merge)]),
+                    args: vec![Expr::Identifier(col.clone())],
+                    over: None,
+                    distinct: false,
+                }),
+                alias: col.clone(),
+            });
+        }
+        let mut select = parse_select(&format!("SELECT * FROM {}", intermediate_table))?;
+        select.projection = projection;
+        select.group_by = keys;
+        Ok(SetExpr::Select(select).to_string())
+    }
+
+    /// Adds the partial aggregation to the hbee projection if it is not there yet,
+    /// `merge` is the aggregation that combines several partial results
+    fn add_partial(&mut self, partial: Function, merge: Option<&'static str>) -> Ident {
+        let partial = Expr::Function(partial);
+        let partial_str = partial.to_string();
+        if let Some((_, col, _)) = self.partials.iter().find(|(p, ..)| p == &partial_str)
+        {
+            return col.clone();
+        }
+        let col = Ident::new(format!("{}{}", AGG_PREFIX, self.partials.len()));
+        self.hbee_items.push(SelectItem::ExprWithAlias {
+            expr: partial,
+            alias: col.clone(),
+        });
+        self.partials.push((partial_str, col.clone(), merge));
+        col
+    }
+}
+
+const SPLITTABLE_AGGREGATES: [&str; 5] = ["COUNT", "SUM", "MIN", "MAX", "AVG"];
+
+/// Aggregates that cannot be computed from partial aggregations of the same kind
+const UNSPLITTABLE_AGGREGATES: [&str; 14] = [
+    "STDDEV",
+    "STDDEV_POP",
+    "STDDEV_SAMP",
+    "VARIANCE",
+    "VAR_POP",
+    "VAR_SAMP",
+    "MEDIAN",
+    "CORR",
+    "COVAR_POP",
+    "COVAR_SAMP",
+    "ARRAY_AGG",
+    "STRING_AGG",
+    "BOOL_AND",
+    "BOOL_OR",
+];
+
+fn is_aggregate(fun: &Function) -> bool {
+    let name = fun.name.to_string();
+    SPLITTABLE_AGGREGATES.contains(&name.to_uppercase().as_str())
+        || SketchFunction::from_name(&name).is_some()
+}
+
+fn is_unsplittable_aggregate(fun: &Function) -> bool {
+    UNSPLITTABLE_AGGREGATES.contains(&fun.name.to_string().to_uppercase().as_str())
+}
+
+fn has_aggregate_select(select: &Select) -> bool {
+    select.projection.iter().any(|item| match item {
+        SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+            has_aggregate(expr)
+        }
+        _ => false,
+    }) || select.having.as_ref().map(has_aggregate).unwrap_or(false)
+}
+
+fn has_aggregate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Function(fun) => {
+            is_aggregate(fun)
+                || is_unsplittable_aggregate(fun)
+                || fun.args.iter().any(has_aggregate)
+        }
+        Expr::BinaryOp { left, right, .. } => has_aggregate(left) || has_aggregate(right),
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Cast { expr, .. }
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Extract { expr, .. }
+        | Expr::Collate { expr, .. } => has_aggregate(expr),
+        Expr::InList { expr, list, .. } => {
+            has_aggregate(expr) || list.iter().any(has_aggregate)
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => has_aggregate(expr) || has_aggregate(low) || has_aggregate(high),
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            operand
+                .as_ref()
+                .map_or(false, |operand| has_aggregate(operand))
+                || conditions.iter().any(has_aggregate)
+                || results.iter().any(has_aggregate)
+                || else_result
+                    .as_ref()
+                    .map_or(false, |expr| has_aggregate(expr))
+        }
+        _ => false,
+    }
+}
+
+/// Builds an aggregation of the given column, other attributes are taken from `template`
+fn aggregate(name: &str, col: Ident, template: &Function) -> Expr {
+    Expr::Function(Function {
+        name: ObjectName(vec![Ident::new(name)]),
+        args: vec![Expr::Identifier(col)],
+        ..template.clone()
+    })
+}
+
+fn with_name(fun: &Function, name: &str) -> Function {
+    Function {
+        name: ObjectName(vec![Ident::new(name)]),
+        ..fun.clone()
+    }
+}
+
+fn to_double(expr: Expr) -> Expr {
+    Expr::Cast {
+        expr: Box::new(expr),
+        data_type: DataType::Double,
+    }
+}
+
+fn parse_query(sql: &str) -> Result<Box<Query>> {
+    let mut statements = DFParser::parse_sql(sql)
+        .map_err(|e| BuzzError::BadRequest(format!("Invalid SQL: {}", e)))?;
+    if statements.len() != 1 {
+        return Err(BuzzError::BadRequest(format!(
+            "Expected exactly one SQL statement, found {}",
+            statements.len()
+        )));
+    }
+    match statements.pop() {
+        Some(DFStatement::Statement(Statement::Query(query))) => Ok(query),
+        _ => Err(BuzzError::BadRequest(
+            "Only queries can be split automatically".to_owned(),
+        )),
+    }
+}
+
+fn from_table(table: &str) -> Result<Vec<TableWithJoins>> {
+    Ok(parse_select(&format!("SELECT * FROM {}", table))?.from)
+}
+
+fn parse_select(sql: &str) -> Result<Box<Select>> {
+    match parse_query(sql)?.body {
+        SetExpr::Select(select) => Ok(select),
+        _ => Err(BuzzError::BadRequest(format!(
+            "Expected a SELECT statement: {}",
+            sql
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::fuse::top_k::SortKey;
+
+    #[test]
+    fn test_split_count() {
+        let split = split(
+            "SELECT payment_type, COUNT(payment_type) AS cnt FROM nyc_taxi WHERE month='2009/01' GROUP BY payment_type",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(
+            split.hbee_sql,
+            "SELECT payment_type, COUNT(payment_type) AS __buzz_agg0 FROM nyc_taxi WHERE month = '2009/01' GROUP BY payment_type"
+        );
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT payment_type, SUM(__buzz_agg0) AS cnt FROM nyc_taxi_map GROUP BY payment_type"
+        );
+    }
+
+    #[test]
+    fn test_split_avg() {
+        let split = split(
+            "SELECT AVG(fare) AS avg_fare, SUM(fare) FROM nyc_taxi",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(
+            split.hbee_sql,
+            "SELECT SUM(fare) AS __buzz_agg0, COUNT(fare) AS __buzz_agg1 FROM nyc_taxi"
+        );
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT CAST(SUM(__buzz_agg0) AS DOUBLE) / CAST(SUM(__buzz_agg1) AS DOUBLE) AS avg_fare, SUM(__buzz_agg0) FROM nyc_taxi_map"
+        );
+    }
+
+    #[test]
+    fn test_split_sketches() {
+        let split = split(
+            "SELECT approx_count_distinct(vendor) AS vendors, approx_percentile(fare, 0.9) FROM nyc_taxi",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(
+            split.hbee_sql,
+            "SELECT approx_count_distinct_partial(vendor) AS __buzz_agg0, approx_percentile_partial(fare) AS __buzz_agg1 FROM nyc_taxi"
+        );
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT approx_count_distinct_merge(__buzz_agg0) AS vendors, approx_percentile_merge(__buzz_agg1, 0.9) FROM nyc_taxi_map"
+        );
+
+        let err = split("SELECT approx_top_k(vendor) FROM nyc_taxi", "nyc_taxi_map")
+            .unwrap_err();
+        assert!(matches!(err, BuzzError::BadRequest(_)));
+    }
+
+    #[test]
+    fn test_split_count_distinct() {
+        let split = split(
+            "SELECT vendor, COUNT(DISTINCT payment_type) AS payment_types, SUM(fare) FROM nyc_taxi GROUP BY vendor ORDER BY payment_types DESC",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(
+            split.hbee_sql,
+            "SELECT vendor, payment_type AS __buzz_dst, SUM(fare) AS __buzz_agg0 FROM nyc_taxi GROUP BY vendor, payment_type"
+        );
+        assert_eq!(
+            split.distinct_sql.unwrap(),
+            "SELECT vendor, __buzz_dst, SUM(__buzz_agg0) AS __buzz_agg0 FROM nyc_taxi_map GROUP BY vendor, __buzz_dst"
+        );
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT vendor, COUNT(__buzz_dst) AS payment_types, SUM(__buzz_agg0) FROM nyc_taxi_map_distinct GROUP BY vendor ORDER BY payment_types DESC"
+        );
+    }
+
+    #[test]
+    fn test_split_group_expr() {
+        let split = split(
+            "SELECT vendor || 'x', MAX(fare) FROM nyc_taxi GROUP BY vendor || 'x' ORDER BY MAX(fare) LIMIT 10",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(
+            split.hbee_sql,
+            "SELECT vendor || 'x' AS __buzz_grp0, MAX(fare) AS __buzz_agg0 FROM nyc_taxi GROUP BY vendor || 'x'"
+        );
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT __buzz_grp0, MAX(__buzz_agg0) FROM nyc_taxi_map GROUP BY __buzz_grp0 ORDER BY MAX(__buzz_agg0) LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn test_split_nested_expr() {
+        let split = split(
+            "SELECT CASE WHEN vendor IN ('a', 'b') THEN 'ab' ELSE vendor END AS v, SUM(CASE WHEN fare BETWEEN 1 AND 10 THEN 1 ELSE 0 END) FROM nyc_taxi GROUP BY vendor HAVING MAX(fare) NOT BETWEEN 0 AND 1",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(
+            split.hbee_sql,
+            "SELECT vendor, SUM(CASE WHEN fare BETWEEN 1 AND 10 THEN 1 ELSE 0 END) AS __buzz_agg0, MAX(fare) AS __buzz_agg1 FROM nyc_taxi GROUP BY vendor"
+        );
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT CASE WHEN vendor IN ('a', 'b') THEN 'ab' ELSE vendor END AS v, SUM(__buzz_agg0) FROM nyc_taxi_map GROUP BY vendor HAVING MAX(__buzz_agg1) NOT BETWEEN 0 AND 1"
+        );
+
+        split(
+            "SELECT CASE WHEN fare > 10 THEN 1 ELSE 0 END, COUNT(*) FROM nyc_taxi GROUP BY vendor",
+            "map",
+        )
+        .expect_err("fare is not a group by key");
+    }
+
+    #[test]
+    fn test_split_no_aggregate() {
+        let split = split(
+            "SELECT fare FROM nyc_taxi WHERE fare > 10 ORDER BY fare LIMIT 5",
+            "nyc_taxi_map",
+        )
+        .unwrap();
+        assert_eq!(split.hbee_sql, "SELECT fare FROM nyc_taxi WHERE fare > 10");
+        assert_eq!(
+            split.hcomb_sql,
+            "SELECT * FROM nyc_taxi_map ORDER BY fare LIMIT 5"
+        );
+    }
+
+    #[test]
+    fn test_split_errors() {
+        split("SELECT fare, COUNT(*) FROM nyc_taxi", "map")
+            .expect_err("fare is not a group by key");
+        split(
+            "SELECT COUNT(DISTINCT fare), COUNT(DISTINCT tip) FROM nyc_taxi",
+            "map",
+        )
+        .expect_err("only one expression can be counted distinctly");
+        split("SELECT SUM(DISTINCT fare) FROM nyc_taxi", "map")
+            .expect_err("only count distinct is supported");
+        split(
+            "SELECT COUNT(DISTINCT fare), approx_count_distinct(tip) FROM nyc_taxi",
+            "map",
+        )
+        .expect_err("sketches cannot be merged twice");
+        split("SELECT * FROM", "map").expect_err("invalid SQL");
+
+        let err = split(
+            "SELECT vendor, STDDEV(fare) FROM nyc_taxi GROUP BY vendor",
+            "map",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("STDDEV cannot be split"));
+        split("SELECT VARIANCE(fare) FROM nyc_taxi", "map")
+            .expect_err("variances cannot be split");
+    }
+
+    #[test]
+    fn test_union_branches() {
+        assert_eq!(
+            union_branches("SELECT a FROM t1 WHERE a > 0").unwrap(),
+            vec!["SELECT a FROM t1 WHERE a > 0"]
+        );
+        assert_eq!(
+            union_branches(
+                "SELECT a FROM t1 UNION ALL SELECT a FROM t2 WHERE a > 0 UNION ALL (SELECT a FROM t3)"
+            )
+            .unwrap(),
+            vec![
+                "SELECT a FROM t1",
+                "SELECT a FROM t2 WHERE a > 0",
+                "SELECT a FROM t3"
+            ]
+        );
+        union_branches("SELECT a FROM t1 UNION SELECT a FROM t2")
+            .expect_err("UNION without ALL is not supported");
+        union_branches("SELECT a FROM t1 UNION ALL SELECT a FROM t2 LIMIT 10")
+            .expect_err("the union cannot be limited");
+    }
+
+    #[test]
+    fn test_push_limit() {
+        assert_eq!(
+            push_limit("SELECT * FROM test WHERE a > 1", 100).unwrap(),
+            "SELECT * FROM test WHERE a > 1 LIMIT 100"
+        );
+        assert_eq!(
+            push_limit("SELECT * FROM test LIMIT 1000", 100).unwrap(),
+            "SELECT * FROM test LIMIT 100"
+        );
+        assert_eq!(
+            push_limit("SELECT * FROM test LIMIT 10", 100).unwrap(),
+            "SELECT * FROM test LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn test_push_top_k() {
+        let top_k = TopK {
+            order_by: vec![
+                SortKey {
+                    column: "fare".to_owned(),
+                    asc: false,
+                    nulls_first: true,
+                },
+                SortKey {
+                    column: "__buzz_grp0".to_owned(),
+                    asc: true,
+                    nulls_first: false,
+                },
+            ],
+            limit: 20,
+        };
+        assert_eq!(
+            push_top_k("SELECT fare, __buzz_grp0 FROM test", &top_k).unwrap(),
+            "SELECT fare, __buzz_grp0 FROM test ORDER BY fare DESC NULLS FIRST, __buzz_grp0 ASC NULLS LAST LIMIT 20"
+        );
+        push_top_k("SELECT * FROM test LIMIT 10", &top_k)
+            .expect_err("the query is already limited");
+    }
+}
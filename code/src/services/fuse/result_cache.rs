@@ -0,0 +1,470 @@
+//! Cache of the query results, so that identical queries on an unchanged
+//! set of files are answered without starting any hcomb or hbee.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, ErrorKind};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::query_planner::DistributedPlan;
+use crate::error::Result;
+use crate::internal_err;
+use arrow::datatypes::Schema;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+
+/// The storage behind the result cache, entries are opaque bytes
+pub trait ResultStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn put(&self, key: &str, value: Vec<u8>) -> Result<()>;
+    /// Removes the entry, does nothing if it does not exist
+    fn remove(&self, key: &str) -> Result<()>;
+    fn clear(&self) -> Result<()>;
+}
+
+/// Stores each entry in its own file of the given directory
+pub struct LocalDiskStore {
+    dir: PathBuf,
+}
+
+impl LocalDiskStore {
+    pub fn try_new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+}
+
+impl ResultStore for LocalDiskStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        // write then rename so that a concurrent reader never sees a partial entry
+        let tmp_path = self.dir.join(format!("{}.tmp", key));
+        fs::write(&tmp_path, value)?;
+        fs::rename(&tmp_path, self.dir.join(key))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.dir.join(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn clear(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            fs::remove_file(entry?.path())?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the entries in memory, for the fuses that run several queries
+#[derive(Default)]
+pub struct InMemoryStore {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl ResultStore for InMemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.entries.lock().unwrap().insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.entries.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+/// The results of the output stages of a query, by stage name
+pub type StageResults = Vec<(String, Vec<RecordBatch>)>;
+
+/// Caches the results of the queries by plan. The plan contains the exact list of
+/// files selected by the catalogs, so new files in a catalog change the key.
+pub struct ResultCache {
+    store: Box<dyn ResultStore>,
+    ttl: Duration,
+}
+
+impl ResultCache {
+    /// Entries older than `ttl` are not served anymore
+    pub fn new(store: Box<dyn ResultStore>, ttl: Duration) -> Self {
+        Self { store, ttl }
+    }
+
+    /// The results of the plan, if they were cached less than `ttl` ago
+    pub fn get(&self, plan: &DistributedPlan) -> Result<Option<StageResults>> {
+        let fingerprint = plan.fingerprint()?;
+        let key = Self::key(&fingerprint);
+        let entry = match self.store.get(&key)? {
+            Some(bytes) => decode_entry(&bytes)?,
+            None => return Ok(None),
+        };
+        if entry.created + self.ttl.as_secs() as i64 <= chrono::Utc::now().timestamp() {
+            self.store.remove(&key)?;
+            return Ok(None);
+        }
+        // guard against hash collisions
+        if entry.fingerprint != fingerprint {
+            return Ok(None);
+        }
+        Ok(Some(entry.results))
+    }
+
+    pub fn put(&self, plan: &DistributedPlan, results: &StageResults) -> Result<()> {
+        let fingerprint = plan.fingerprint()?;
+        let key = Self::key(&fingerprint);
+        let bytes = encode_entry(chrono::Utc::now().timestamp(), &fingerprint, results)?;
+        self.store.put(&key, bytes)
+    }
+
+    /// Removes the results of the plan from the cache
+    pub fn invalidate(&self, plan: &DistributedPlan) -> Result<()> {
+        self.store.remove(&Self::key(&plan.fingerprint()?))
+    }
+
+    /// Removes all the results from the cache
+    pub fn invalidate_all(&self) -> Result<()> {
+        self.store.clear()
+    }
+
+    fn key(fingerprint: &str) -> String {
+        hash_hex(fingerprint.as_bytes())
+    }
+}
+
+impl DistributedPlan {
+    /// A description of everything that determines the results of the plan:
+    /// the optimized hbee and hcomb plans, the contents of the broadcast tables
+    /// and the files read by each hbee.
+    /// The query id is not part of it as it changes with each run.
+    pub fn fingerprint(&self) -> Result<String> {
+        let mut fp = String::new();
+        for stage in &self.stages {
+            // writing into a String cannot fail
+            writeln!(
+                fp,
+                "stage {} {:?} {:?}",
+                stage.name, stage.upstream, stage.limit
+            )
+            .unwrap();
+            writeln!(fp, "hcomb {}", stage.hcomb_plan).unwrap();
+            for hbee_step in &stage.hbee_steps {
+                writeln!(fp, "hbee_step {} {}", hbee_step.name, hbee_step.plan).unwrap();
+            }
+            for zone in &stage.zones {
+                writeln!(fp, "zone {}", zone.hcomb.sql).unwrap();
+                for input in &zone.hcomb.inputs {
+                    writeln!(fp, "input {} {}", input.source, input.table.nb_hbee())
+                        .unwrap();
+                }
+                for hbee in &zone.hbee {
+                    // a broadcast table can be registered again with other contents
+                    let broadcasts = hbee
+                        .broadcasts
+                        .iter()
+                        .map(|table| {
+                            Ok(format!("{}:{}", table.name(), hash_hex(&table.to_ipc()?)))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    writeln!(
+                        fp,
+                        "hbee {} {} {:?} {:?}",
+                        hbee.source, hbee.sql, broadcasts, hbee.shuffle_keys
+                    )
+                    .unwrap();
+                    let location = hbee.table.location();
+                    for file in hbee.table.files() {
+                        writeln!(
+                            fp,
+                            "file {}/{} {} {:?}",
+                            location, file.key, file.length, file.row_groups
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+        Ok(fp)
+    }
+}
+
+fn hash_hex(bytes: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+struct CacheEntry {
+    /// Creation timestamp in seconds
+    created: i64,
+    fingerprint: String,
+    results: StageResults,
+}
+
+/// Little endian encoding of the entry, the batches of each stage
+/// are stored in the Arrow IPC stream format
+fn encode_entry(
+    created: i64,
+    fingerprint: &str,
+    results: &StageResults,
+) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&created.to_le_bytes());
+    write_slice(&mut bytes, fingerprint.as_bytes());
+    bytes.extend_from_slice(&(results.len() as u32).to_le_bytes());
+    for (name, batches) in results {
+        write_slice(&mut bytes, name.as_bytes());
+        let schema = match batches.first() {
+            Some(batch) => batch.schema(),
+            None => std::sync::Arc::new(Schema::empty()),
+        };
+        let mut ipc = vec![];
+        {
+            let mut writer = StreamWriter::try_new(&mut ipc, &schema)?;
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+        }
+        write_slice(&mut bytes, &ipc);
+    }
+    Ok(bytes)
+}
+
+fn decode_entry(bytes: &[u8]) -> Result<CacheEntry> {
+    let mut cursor = bytes;
+    let created = read_u64(&mut cursor)? as i64;
+    let fingerprint = read_string(&mut cursor)?;
+    let nb_results = read_u32(&mut cursor)?;
+    let mut results = vec![];
+    for _ in 0..nb_results {
+        let name = read_string(&mut cursor)?;
+        let ipc = read_slice(&mut cursor)?;
+        let batches = StreamReader::try_new(Cursor::new(ipc))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        results.push((name, batches));
+    }
+    Ok(CacheEntry {
+        created,
+        fingerprint,
+        results,
+    })
+}
+
+fn write_slice(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+fn take<'a>(cursor: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if cursor.len() < len {
+        return Err(internal_err!("Result cache entry is truncated"));
+    }
+    let (head, tail) = cursor.split_at(len);
+    *cursor = tail;
+    Ok(head)
+}
+
+fn read_u32(cursor: &mut &[u8]) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(take(cursor, 4)?);
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(cursor: &mut &[u8]) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(take(cursor, 8)?);
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_slice<'a>(cursor: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_u64(cursor)? as usize;
+    take(cursor, len)
+}
+
+fn read_string(cursor: &mut &[u8]) -> Result<String> {
+    String::from_utf8(read_slice(cursor)?.to_vec())
+        .map_err(|_| internal_err!("Result cache entry contains invalid utf8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::{BroadcastTable, LocalParquetTable};
+    use crate::services::fuse::query_planner::{
+        HBeePlan, HCombPlan, StagePlan, ZonePlan,
+    };
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field};
+    use std::sync::Arc;
+
+    fn plan(stage_name: &str) -> DistributedPlan {
+        DistributedPlan {
+            stages: vec![StagePlan {
+                name: stage_name.to_owned(),
+                upstream: None,
+                zones: vec![],
+                is_output: true,
+                hcomb_plan: "TableScan: mapper projection=None".to_owned(),
+                hbee_steps: vec![],
+                limit: None,
+            }],
+            nb_hbee: 0,
+        }
+    }
+
+    fn results() -> StageResults {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2, 3]))])
+                .unwrap();
+        vec![
+            ("reducer".to_owned(), vec![batch.clone(), batch]),
+            ("empty_reducer".to_owned(), vec![]),
+        ]
+    }
+
+    fn assert_results_eq(expected: &StageResults, actual: &StageResults) {
+        assert_eq!(expected.len(), actual.len());
+        for ((exp_name, exp_batches), (name, batches)) in expected.iter().zip(actual) {
+            assert_eq!(exp_name, name);
+            assert_eq!(exp_batches.len(), batches.len());
+            for (exp_batch, batch) in exp_batches.iter().zip(batches) {
+                assert_eq!(exp_batch.schema(), batch.schema());
+                assert_eq!(
+                    format!("{:?}", exp_batch.columns()),
+                    format!("{:?}", batch.columns())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_cache_hit_and_invalidation() -> Result<()> {
+        let cache = ResultCache::new(
+            Box::new(InMemoryStore::default()),
+            Duration::from_secs(3600),
+        );
+        assert!(cache.get(&plan("reducer"))?.is_none());
+
+        cache.put(&plan("reducer"), &results())?;
+        let cached = cache
+            .get(&plan("reducer"))?
+            .expect("results should be cached");
+        assert_results_eq(&results(), &cached);
+        // a different plan does not match
+        assert!(cache.get(&plan("other_reducer"))?.is_none());
+
+        cache.invalidate(&plan("reducer"))?;
+        assert!(cache.get(&plan("reducer"))?.is_none());
+
+        cache.put(&plan("reducer"), &results())?;
+        cache.invalidate_all()?;
+        assert!(cache.get(&plan("reducer"))?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_contents_in_fingerprint() -> Result<()> {
+        let plan_with_broadcast = |values: Vec<i64>| {
+            let mut plan = plan("reducer");
+            let schema =
+                Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int64Array::from(values))],
+            )
+            .unwrap();
+            plan.stages[0].zones.push(ZonePlan {
+                hbee: vec![HBeePlan {
+                    sql: "SELECT * FROM test JOIN lookup ON test.a = lookup.a".to_owned(),
+                    source: "mapper".to_owned(),
+                    table: LocalParquetTable::new(
+                        "/data".to_owned(),
+                        vec![],
+                        schema.clone(),
+                    ),
+                    query_id: "query".to_owned(),
+                    broadcasts: vec![BroadcastTable::new(
+                        "lookup".to_owned(),
+                        schema,
+                        vec![batch],
+                    )],
+                    shuffle_keys: vec![],
+                }],
+                hcomb: HCombPlan {
+                    sql: "SELECT * FROM mapper".to_owned(),
+                    inputs: vec![],
+                },
+            });
+            plan
+        };
+        assert_eq!(
+            plan_with_broadcast(vec![1, 2]).fingerprint()?,
+            plan_with_broadcast(vec![1, 2]).fingerprint()?
+        );
+        assert_ne!(
+            plan_with_broadcast(vec![1, 2]).fingerprint()?,
+            plan_with_broadcast(vec![1, 3]).fingerprint()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_expiration() -> Result<()> {
+        let cache =
+            ResultCache::new(Box::new(InMemoryStore::default()), Duration::from_secs(0));
+        cache.put(&plan("reducer"), &results())?;
+        assert!(cache.get(&plan("reducer"))?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_disk_store() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "buzz-result-cache-{}",
+            chrono::Utc::now().timestamp_nanos()
+        ));
+        let cache = ResultCache::new(
+            Box::new(LocalDiskStore::try_new(&dir)?),
+            Duration::from_secs(3600),
+        );
+        cache.put(&plan("reducer"), &results())?;
+        let cached = cache
+            .get(&plan("reducer"))?
+            .expect("results should be cached");
+        assert_results_eq(&results(), &cached);
+
+        cache.invalidate(&plan("reducer"))?;
+        assert!(cache.get(&plan("reducer"))?.is_none());
+        // removing a missing entry is not an error
+        cache.invalidate(&plan("reducer"))?;
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! Detection of the `ORDER BY ... LIMIT` HComb steps whose partial top-k
+//! can be computed by each hbee without changing the result of the query
+
+use std::collections::HashSet;
+
+use datafusion::logical_plan::{Expr, LogicalPlan};
+
+/// A column of the hbee output used to sort the results
+#[derive(Debug, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    pub asc: bool,
+    pub nulls_first: bool,
+}
+
+/// The first `limit` rows of the hbee output, ordered by `order_by`
+#[derive(Debug, PartialEq)]
+pub struct TopK {
+    pub order_by: Vec<SortKey>,
+    pub limit: usize,
+}
+
+/// If the HComb step keeps the first rows of its input in a given order, returns
+/// the top-k that each hbee can compute beforehand so that it sends only k rows.
+/// This is possible if:
+/// - the HComb step orders the raw hbee results, without filtering them
+/// - or it orders them by all the group keys (and only by them), and the hbees send at
+///   most one row per group. If some group keys are not ordered, the hbees could keep
+///   different groups among the ones that tie, and the hcomb would then aggregate some
+///   groups from the partial results of only some of the hbees.
+pub fn top_k(hcomb_plan: &LogicalPlan, hbee_plan: &LogicalPlan) -> Option<TopK> {
+    if !is_unordered(hbee_plan) {
+        return None;
+    }
+    let (limit, input) = match hcomb_plan {
+        LogicalPlan::Limit { n, input } => (*n, input.as_ref()),
+        _ => return None,
+    };
+    let (sort_exprs, mut plan) = match input {
+        LogicalPlan::Sort { expr, input } => (expr, input.as_ref()),
+        _ => return None,
+    };
+    let mut order_by = sort_exprs
+        .iter()
+        .map(|expr| match expr {
+            Expr::Sort {
+                expr,
+                asc,
+                nulls_first,
+            } => match expr.as_ref() {
+                Expr::Column(name) => Some(SortKey {
+                    column: name.clone(),
+                    asc: *asc,
+                    nulls_first: *nulls_first,
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // resolve the sort columns down to the columns of the hbee output
+    loop {
+        match plan {
+            LogicalPlan::Projection { expr, input, .. } => {
+                for key in &mut order_by {
+                    key.column = source_column(expr, &key.column)?;
+                }
+                plan = input.as_ref();
+            }
+            LogicalPlan::TableScan { .. } => {
+                return Some(TopK { order_by, limit });
+            }
+            LogicalPlan::Aggregate {
+                group_expr, input, ..
+            } => {
+                if !matches!(input.as_ref(), LogicalPlan::TableScan { .. }) {
+                    return None;
+                }
+                let group_keys = group_expr
+                    .iter()
+                    .map(|expr| match expr {
+                        Expr::Column(name) => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect::<Option<HashSet<_>>>()?;
+                let order_keys = order_by
+                    .iter()
+                    .map(|key| key.column.clone())
+                    .collect::<HashSet<_>>();
+                if order_keys == group_keys && is_unique_per(hbee_plan, &group_keys) {
+                    return Some(TopK { order_by, limit });
+                }
+                return None;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// The column that the projection outputs as `name`, if it is a plain column
+fn source_column(projection: &[Expr], name: &str) -> Option<String> {
+    projection.iter().find_map(|expr| match expr {
+        Expr::Column(col) if col == name => Some(col.clone()),
+        Expr::Alias(inner, alias) if alias == name => match inner.as_ref() {
+            Expr::Column(col) => Some(col.clone()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// True if the plan does not already order or limit its results
+fn is_unordered(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::Limit { .. } | LogicalPlan::Sort { .. } => false,
+        LogicalPlan::Projection { input, .. } => is_unordered(input),
+        _ => true,
+    }
+}
+
+/// True if the plan is an aggregation that outputs at most one row for each value of `keys`,
+/// that is if all its group keys are output as columns of `keys`
+fn is_unique_per(plan: &LogicalPlan, keys: &HashSet<String>) -> bool {
+    // the output names of the group keys, from the aggregation up to the plan output
+    fn group_columns(plan: &LogicalPlan) -> Option<Vec<String>> {
+        match plan {
+            LogicalPlan::Aggregate { group_expr, .. } => Some(
+                plan.schema().fields()[..group_expr.len()]
+                    .iter()
+                    .map(|field| field.name().clone())
+                    .collect(),
+            ),
+            LogicalPlan::Projection { expr, input, .. } => group_columns(input)?
+                .iter()
+                .map(|col| {
+                    expr.iter().find_map(|expr| match expr {
+                        Expr::Column(name) if name == col => Some(name.clone()),
+                        Expr::Alias(inner, alias) => match inner.as_ref() {
+                            Expr::Column(name) if name == col => Some(alias.clone()),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+    match group_columns(plan) {
+        Some(columns) => columns.iter().all(|col| keys.contains(col)),
+        None => false,
+    }
+}
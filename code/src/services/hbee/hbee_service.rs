@@ -1,12 +1,14 @@
 use std::sync::Arc;
 use std::time::Instant;
 
+use super::partitioner;
 use super::Collector;
 use crate::clients::RangeCache;
-use crate::datasource::{HBeeTableDesc, HBeeTable};
-use crate::error::Result;
+use crate::datasource::{BroadcastTable, HBeeTable, HBeeTableDesc};
+use crate::error::{BuzzError, Result};
 use crate::internal_err;
 use crate::models::HCombAddress;
+use crate::sketch::register_sketch_udafs;
 use arrow::record_batch::RecordBatch;
 use datafusion::execution::context::{ExecutionConfig, ExecutionContext};
 use datafusion::physical_plan::{merge::MergeExec, ExecutionPlan};
@@ -22,8 +24,10 @@ impl HBeeService {
         let config = ExecutionConfig::new()
             .with_batch_size(2048)
             .with_concurrency(1);
+        let mut execution_context = ExecutionContext::with_config(config);
+        register_sketch_udafs(&mut execution_context);
         Self {
-            execution_context: ExecutionContext::with_config(config),
+            execution_context,
             range_cache: Arc::new(RangeCache::new().await),
             collector,
         }
@@ -37,11 +41,13 @@ impl HBeeService {
         table: HBeeTableDesc, 
         sql: String,
         source: String,
-        address: HCombAddress,
+        broadcasts: Vec<BroadcastTable>,
+        shuffle_keys: Vec<String>,
+        addresses: Vec<HCombAddress>,
     ) -> Result<()> {
         println!("[hbee] execute query");
         let start = Instant::now();
-        let query_res = self.query(table, sql, source).await;
+        let query_res = self.query(table, sql, source, broadcasts).await;
         let cache_stats = self.range_cache.statistics();
         println!("[hbee] query_duration={}, waiting_download_ms={}, downloaded_bytes={}, processed_bytes={}, download_count={}",
             start.elapsed().as_millis(), 
@@ -51,20 +57,80 @@ impl HBeeService {
             cache_stats.download_count(),
         );
         let start = Instant::now();
-        let exec_res = self.collector.send_back(query_id, query_res, address).await;
+        let exec_res = self
+            .send_back(query_id, query_res, shuffle_keys, addresses)
+            .await;
         println!("[hbee] collector duration: {}", start.elapsed().as_millis());
         exec_res
     }
 
+    /// Send the results to the hcomb, or if the stage has several hcombs,
+    /// send each hcomb the partition of the results that it owns.
+    async fn send_back(
+        &self,
+        query_id: String,
+        query_res: Result<Vec<RecordBatch>>,
+        shuffle_keys: Vec<String>,
+        mut addresses: Vec<HCombAddress>,
+    ) -> Result<()> {
+        if addresses.len() == 0 {
+            return Err(internal_err!("At least one hcomb address is required"));
+        } else if addresses.len() == 1 {
+            return self
+                .collector
+                .send_back(query_id, query_res, addresses.remove(0))
+                .await;
+        }
+        let partitions = query_res.and_then(|batches| {
+            partitioner::hash_partition(&batches, &shuffle_keys, addresses.len())
+        });
+        match partitions {
+            Ok(partitions) => {
+                let sends = addresses.into_iter().zip(partitions).map(
+                    |(address, partition)| {
+                        self.collector
+                            .send_back(query_id.clone(), Ok(partition), address)
+                    },
+                );
+                futures::future::try_join_all(sends).await?;
+                Ok(())
+            }
+            Err(err) => {
+                // all hcombs are waiting for this hbee, they should all fail
+                let reason = format!("{}", err);
+                let fails = addresses.into_iter().map(|address| {
+                    self.collector.send_back(
+                        query_id.clone(),
+                        Err(BuzzError::HBee(reason.clone())),
+                        address,
+                    )
+                });
+                futures::future::join_all(fails).await;
+                Err(err)
+            }
+        }
+    }
+
     /// Execute the logical plan and collect the results
     /// Collecting the results might increase latency and mem consumption but:
     /// - reduces connection duration from hbee to hcomb, thus decreasing load on hcomb
     /// - allows to collect exec errors at once, effectively choosing between do_put and FAIL action
-    async fn query(&mut self,table: HBeeTableDesc, sql: String, source: String) -> Result<Vec<RecordBatch>> {
+    async fn query(
+        &mut self,
+        table: HBeeTableDesc,
+        sql: String,
+        source: String,
+        broadcasts: Vec<BroadcastTable>,
+    ) -> Result<Vec<RecordBatch>> {
         let start = Instant::now();
         let provider = HBeeTable::new(Arc::new(table), Arc::clone(&self.range_cache));
         self.execution_context
             .register_table(&source, Box::new(provider));
+        // the small tables joined with the catalog are sent along with the plan
+        for broadcast in broadcasts {
+            self.execution_context
+                .register_table(broadcast.name(), Box::new(broadcast.to_mem_table()?));
+        }
         let physical_plan;
         {
             let df = self.execution_context.sql(&sql)?;
@@ -1,5 +1,6 @@
 mod collector;
 mod hbee_service;
+mod partitioner;
 
 pub use collector::{Collector, HttpCollector, NoopCollector};
 pub use hbee_service::HBeeService;
@@ -0,0 +1,201 @@
+//! Hash partitioning of the hbee results among the hcombs of a stage
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::not_impl_err;
+use arrow::array::*;
+use arrow::compute::kernels::take::take;
+use arrow::datatypes::{DataType, TimeUnit};
+use arrow::record_batch::RecordBatch;
+
+/// Split the batches into `nb_partitions` according to the hash of the `keys` columns.
+/// The rows with the same key values always end up in the same partition,
+/// whatever the hbee that computed them.
+pub fn hash_partition(
+    batches: &[RecordBatch],
+    keys: &[String],
+    nb_partitions: usize,
+) -> Result<Vec<Vec<RecordBatch>>> {
+    let mut partitions = (0..nb_partitions).map(|_| vec![]).collect::<Vec<_>>();
+    for batch in batches {
+        let key_columns = keys
+            .iter()
+            .map(|key| Ok(Arc::clone(batch.column(batch.schema().index_of(key)?))))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut indices = (0..nb_partitions).map(|_| vec![]).collect::<Vec<_>>();
+        for row in 0..batch.num_rows() {
+            let mut hasher = DefaultHasher::new();
+            for column in &key_columns {
+                hash_value(column, row, &mut hasher)?;
+            }
+            let partition = (hasher.finish() % nb_partitions as u64) as usize;
+            indices[partition].push(row as u32);
+        }
+
+        for (partition, partition_indices) in indices.into_iter().enumerate() {
+            if partition_indices.is_empty() {
+                continue;
+            }
+            let partition_indices = UInt32Array::from(partition_indices);
+            let columns = batch
+                .columns()
+                .iter()
+                .map(|column| take(column.as_ref(), &partition_indices, None))
+                .collect::<arrow::error::Result<Vec<_>>>()?;
+            partitions[partition].push(RecordBatch::try_new(batch.schema(), columns)?);
+        }
+    }
+    Ok(partitions)
+}
+
+macro_rules! hash_array_value {
+    ($ARRAY:expr, $ROW:expr, $HASHER:expr, $ARRAY_TYPE:ident) => {{
+        let array = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        array.value($ROW).hash($HASHER);
+    }};
+}
+
+fn hash_value(array: &ArrayRef, row: usize, hasher: &mut DefaultHasher) -> Result<()> {
+    if array.is_null(row) {
+        0u8.hash(hasher);
+        return Ok(());
+    }
+    1u8.hash(hasher);
+    match array.data_type() {
+        DataType::Boolean => hash_array_value!(array, row, hasher, BooleanArray),
+        DataType::Int8 => hash_array_value!(array, row, hasher, Int8Array),
+        DataType::Int16 => hash_array_value!(array, row, hasher, Int16Array),
+        DataType::Int32 => hash_array_value!(array, row, hasher, Int32Array),
+        DataType::Int64 => hash_array_value!(array, row, hasher, Int64Array),
+        DataType::UInt8 => hash_array_value!(array, row, hasher, UInt8Array),
+        DataType::UInt16 => hash_array_value!(array, row, hasher, UInt16Array),
+        DataType::UInt32 => hash_array_value!(array, row, hasher, UInt32Array),
+        DataType::UInt64 => hash_array_value!(array, row, hasher, UInt64Array),
+        DataType::Date32(_) => hash_array_value!(array, row, hasher, Date32Array),
+        DataType::Date64(_) => hash_array_value!(array, row, hasher, Date64Array),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            hash_array_value!(array, row, hasher, TimestampSecondArray)
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            hash_array_value!(array, row, hasher, TimestampMillisecondArray)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            hash_array_value!(array, row, hasher, TimestampMicrosecondArray)
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            hash_array_value!(array, row, hasher, TimestampNanosecondArray)
+        }
+        DataType::Float32 => {
+            let array = array.as_any().downcast_ref::<Float32Array>().unwrap();
+            array.value(row).to_bits().hash(hasher);
+        }
+        DataType::Float64 => {
+            let array = array.as_any().downcast_ref::<Float64Array>().unwrap();
+            array.value(row).to_bits().hash(hasher);
+        }
+        DataType::Utf8 => hash_array_value!(array, row, hasher, StringArray),
+        DataType::LargeUtf8 => hash_array_value!(array, row, hasher, LargeStringArray),
+        other => {
+            return Err(not_impl_err!(
+                "Cannot shuffle on a column of type {:?}",
+                other
+            ))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::{Field, Schema};
+
+    #[test]
+    fn test_hash_partition() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Utf8, true),
+            Field::new("value", DataType::Int64, false),
+        ]));
+        let batch = |keys: Vec<Option<&str>>, values: Vec<i64>| {
+            RecordBatch::try_new(
+                Arc::clone(&schema),
+                vec![
+                    Arc::new(StringArray::from(keys)),
+                    Arc::new(Int64Array::from(values)),
+                ],
+            )
+            .unwrap()
+        };
+        let batches = vec![
+            batch(
+                vec![Some("a"), Some("b"), None, Some("c")],
+                vec![1, 2, 3, 4],
+            ),
+            batch(vec![Some("c"), Some("a"), Some("d")], vec![5, 6, 7]),
+        ];
+
+        let partitions = hash_partition(&batches, &["key".to_owned()], 3)?;
+        assert_eq!(partitions.len(), 3);
+
+        let nb_rows = partitions
+            .iter()
+            .flatten()
+            .map(|batch| batch.num_rows())
+            .sum::<usize>();
+        assert_eq!(nb_rows, 7);
+
+        // each key should be found in a single partition
+        let partition_keys = partitions
+            .iter()
+            .map(|partition| {
+                partition
+                    .iter()
+                    .flat_map(|batch| {
+                        let keys = batch
+                            .column(0)
+                            .as_any()
+                            .downcast_ref::<StringArray>()
+                            .unwrap();
+                        (0..keys.len())
+                            .map(|i| {
+                                if keys.is_null(i) {
+                                    None
+                                } else {
+                                    Some(keys.value(i).to_owned())
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<std::collections::HashSet<_>>()
+            })
+            .collect::<Vec<_>>();
+        for key in &[Some("a"), Some("b"), Some("c"), Some("d"), None] {
+            let key = key.map(|k| k.to_owned());
+            let found_in = partition_keys
+                .iter()
+                .filter(|keys| keys.contains(&key))
+                .count();
+            assert_eq!(found_in, 1, "key {:?} should be in a single partition", key);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_partition_unknown_key() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "value",
+            DataType::Int64,
+            false,
+        )]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2]))])
+                .unwrap();
+        hash_partition(&[batch], &["key".to_owned()], 2)
+            .expect_err("The key column does not exist");
+    }
+}
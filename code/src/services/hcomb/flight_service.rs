@@ -86,14 +86,13 @@ impl FlightService for FlightServiceImpl {
             .map_err(|_| {
                 Status::invalid_argument("Plan could not be parsed from bytes")
             })?;
-        let (provider, sql, source) =
-            serde::deserialize_hcomb(plan_node).map_err(|_| {
-                Status::invalid_argument("Plan could not be converted from proto")
-            })?;
+        let (inputs, sql) = serde::deserialize_hcomb(plan_node).map_err(|_| {
+            Status::invalid_argument("Plan could not be converted from proto")
+        })?;
         // execute query
         let results = self
             .hcomb_service
-            .execute_query(provider, sql, source)
+            .execute_query(inputs, sql)
             .await
             .map_err(|e| Status::internal(format!("Query failed: {}", e)))?;
         // serialize response
@@ -146,8 +145,10 @@ impl FlightService for FlightServiceImpl {
         let action = request.into_inner();
         match actions::ActionType::from_string(action.r#type) {
             actions::ActionType::Fail => {
-                let fail_action: actions::Fail =
-                    serde_json::from_slice(&action.body).unwrap();
+                let fail_action: actions::Fail = serde_json::from_slice(&action.body)
+                    .map_err(|e| {
+                        Status::invalid_argument(format!("Invalid fail action: {}", e))
+                    })?;
                 self.hcomb_service.fail(
                     &fail_action.query_id,
                     BuzzError::HBee(format!(
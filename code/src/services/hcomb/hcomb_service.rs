@@ -7,6 +7,8 @@ use super::results_service::ResultsService;
 use crate::datasource::{HCombTable, HCombTableDesc};
 use crate::error::{BuzzError, Result};
 use crate::internal_err;
+use crate::plan_utils;
+use crate::sketch::register_sketch_udafs;
 use arrow::error::{ArrowError, Result as ArrowResult};
 use arrow::record_batch::RecordBatch;
 use datafusion::execution::context::{ExecutionConfig, ExecutionContext};
@@ -45,35 +47,51 @@ impl HCombService {
                 }
             }
         });
+        let mut execution_context = ExecutionContext::with_config(config);
+        register_sketch_udafs(&mut execution_context);
         Self {
             results_service: Arc::new(ResultsService::new()),
-            execution_context: Mutex::new(ExecutionContext::with_config(config)),
+            execution_context: Mutex::new(execution_context),
             last_query,
         }
     }
 
-    /// Executes the hcomb plan
-    /// Returns the query id and the result stream
+    /// Executes the hcomb plan on the results of the given inputs,
+    /// each of them is registered under its source name.
+    /// Returns the query id of the first input and the result stream
     pub async fn execute_query(
         &self,
-        provider_desc: HCombTableDesc,
+        inputs: Vec<(HCombTableDesc, String)>,
         sql: String,
-        source: String,
     ) -> Result<(String, SendableRecordBatchStream)> {
         println!("[hcomb] execute query...");
         self.last_query
             .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
-        let query_id = provider_desc.query_id().to_owned();
-        let batch_stream = self
-            .results_service
-            .new_query(query_id.clone(), provider_desc.nb_hbee());
-        let provider = HCombTable::new(provider_desc, Box::pin(batch_stream));
+        let query_id = inputs
+            .first()
+            .map(|(provider_desc, _)| provider_desc.query_id().to_owned())
+            .ok_or_else(|| internal_err!("HComb query should have at least one input"))?;
         let physical_plan;
         {
             let mut exec_context_guard = self.execution_context.lock().unwrap();
-            exec_context_guard.register_table(&source, Box::new(provider));
+            let nb_inputs = inputs.len();
+            for (provider_desc, source) in inputs {
+                // the results of each input are tracked separately
+                let batch_stream = self.results_service.new_query(
+                    provider_desc.query_id().to_owned(),
+                    provider_desc.nb_hbee(),
+                );
+                let provider = HCombTable::new(provider_desc, Box::pin(batch_stream));
+                exec_context_guard.register_table(&source, Box::new(provider));
+            }
             let df = exec_context_guard.sql(&sql)?;
             let plan = df.to_logical_plan();
+            // stop waiting for the hbees once enough rows were collected
+            if let Some(limit) = plan_utils::limit_only(&plan) {
+                if nb_inputs == 1 {
+                    self.results_service.set_limit(&query_id, limit);
+                }
+            }
             physical_plan = exec_context_guard.create_physical_plan(&plan)?;
         }
 
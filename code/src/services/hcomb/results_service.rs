@@ -9,6 +9,8 @@ use tokio::sync::mpsc;
 struct IntermediateRes {
     tx: Option<mpsc::UnboundedSender<ArrowResult<RecordBatch>>>,
     remaining_tasks: usize,
+    /// If set, the stream is closed as soon as this number of rows is reached
+    remaining_rows: Option<usize>,
 }
 
 pub struct ResultsService {
@@ -33,23 +35,63 @@ impl ResultsService {
             sender_map_guard.insert(
                 query_id,
                 IntermediateRes {
-                    tx: Some(tx),
+                    // without tasks to wait for, the stream is complete right away
+                    tx: if nb_hbees > 0 { Some(tx) } else { None },
                     remaining_tasks: nb_hbees,
+                    remaining_rows: None,
                 },
             );
         }
         rx
     }
 
+    /// Close the result stream of the query once it has received `limit` rows,
+    /// even if some tasks are not finished yet.
+    pub fn set_limit(&self, query_id: &str, limit: usize) {
+        let mut sender_map = self.tx_map.lock().unwrap();
+        match sender_map.get_mut(query_id) {
+            Some(res) => {
+                res.remaining_rows = Some(limit);
+                if limit == 0 {
+                    res.tx = None;
+                }
+            }
+            None => {
+                println!(
+                    "[hcomb] Query '{}' not registered in IntermediateResults",
+                    query_id
+                );
+            }
+        }
+    }
+
     pub fn add_result(&self, query_id: &str, data: ArrowResult<RecordBatch>) {
-        let sender_map = self.tx_map.lock().unwrap();
-        let res_opt = sender_map.get(query_id);
+        let mut sender_map = self.tx_map.lock().unwrap();
+        let res_opt = sender_map.get_mut(query_id);
         match res_opt {
             Some(res) => {
-                let send_res = res.tx.as_ref().unwrap().send(data);
+                let tx = match &res.tx {
+                    Some(tx) => tx,
+                    None => {
+                        println!("[hcomb] Result chan of query '{}' already complete, ignoring result", query_id);
+                        return;
+                    }
+                };
+                let nb_rows = data.as_ref().map(|batch| batch.num_rows()).unwrap_or(0);
+                let send_res = tx.send(data);
                 if send_res.is_err() {
                     println!("[hcomb] Result chan closed because query '{}' failed, ignoring result", query_id);
                 }
+                if let Some(remaining_rows) = res.remaining_rows.as_mut() {
+                    *remaining_rows = remaining_rows.saturating_sub(nb_rows);
+                    if *remaining_rows == 0 {
+                        println!(
+                            "[hcomb] Query '{}' reached its limit, closing result chan",
+                            query_id
+                        );
+                        res.tx = None;
+                    }
+                }
             }
             None => {
                 println!(
@@ -65,7 +107,7 @@ impl ResultsService {
         let res_opt = sender_map.get_mut(query_id);
         match res_opt {
             Some(res) => {
-                res.remaining_tasks -= 1;
+                res.remaining_tasks = res.remaining_tasks.saturating_sub(1);
                 if res.remaining_tasks == 0 {
                     res.tx = None;
                 }
@@ -79,3 +121,35 @@ impl ResultsService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use tokio::stream::StreamExt;
+
+    fn batch(nb_rows: usize) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1; nb_rows]))])
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_results_with_limit() {
+        let results_service = ResultsService::new();
+        let stream = results_service.new_query("query".to_owned(), 10);
+        results_service.set_limit("query", 5);
+
+        results_service.add_result("query", Ok(batch(3)));
+        results_service.task_finished("query");
+        results_service.add_result("query", Ok(batch(3)));
+        // the stream is closed even if 8 tasks remain, later results are ignored
+        results_service.add_result("query", Ok(batch(3)));
+
+        let batches = stream.collect::<Vec<_>>().await;
+        assert_eq!(batches.len(), 2);
+    }
+}
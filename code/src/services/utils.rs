@@ -4,27 +4,37 @@ use datafusion::datasource::TableProvider;
 use datafusion::logical_plan::LogicalPlan;
 
 /// Search a TableProvider of the given type in the plan.
-/// Only works with linear plans (only one datasource).
+/// Fails if the plan does not contain exactly one datasource of that type.
 pub fn find_table_name<'a, T: TableProvider + 'static>(
     plan: &'a LogicalPlan,
 ) -> Result<&'a str> {
+    let table_names = find_table_names::<T>(plan);
+    match table_names.len() {
+        0 => Err(not_impl_err!(
+            "Expected root to be a {}",
+            std::any::type_name::<T>()
+        )),
+        1 => Ok(table_names[0]),
+        _ => Err(not_impl_err!(
+            "Operations with more than one {} are not supported",
+            std::any::type_name::<T>()
+        )),
+    }
+}
+
+/// Search all the TableProviders of the given type in the plan.
+pub fn find_table_names<'a, T: TableProvider + 'static>(
+    plan: &'a LogicalPlan,
+) -> Vec<&'a str> {
     let new_inputs = datafusion::optimizer::utils::inputs(&plan);
-    if new_inputs.len() > 1 {
-        Err(not_impl_err!(
-            "Operations with more than one inputs are not supported",
-        ))
-    } else if new_inputs.len() == 1 {
-        // recurse
-        find_table_name::<T>(new_inputs[0])
+    if new_inputs.is_empty() {
+        as_table_name::<T>(&plan).into_iter().collect()
     } else {
-        if let Some(result_table) = as_table_name::<T>(&plan) {
-            Ok(result_table)
-        } else {
-            Err(not_impl_err!(
-                "Expected root to be a {}",
-                std::any::type_name::<T>()
-            ))
-        }
+        // recurse
+        new_inputs
+            .into_iter()
+            .flat_map(|input| find_table_names::<T>(input))
+            .collect()
     }
 }
 
@@ -50,7 +60,7 @@ mod tests {
 
     use super::*;
     use crate::datasource::CatalogTable;
-    use arrow::datatypes::Schema;
+    use arrow::datatypes::{DataType, Field, Schema};
     use datafusion::datasource::empty::EmptyTable;
     use datafusion::execution::context::ExecutionContext;
     use datafusion::logical_plan::{sum, Expr};
@@ -95,4 +105,22 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn search_tables_join_plan() -> Result<()> {
+        let mut ctx = ExecutionContext::new();
+        let schema_1 = Schema::new(vec![Field::new("a", DataType::Int32, false)]);
+        let schema_2 = Schema::new(vec![Field::new("b", DataType::Int32, false)]);
+        ctx.register_table("test_tbl_1", Box::new(EmptyTable::new(Arc::new(schema_1))));
+        ctx.register_table("test_tbl_2", Box::new(EmptyTable::new(Arc::new(schema_2))));
+        let df = ctx.sql("SELECT * FROM test_tbl_1 JOIN test_tbl_2 ON a = b")?;
+        let log_plan = df.to_logical_plan();
+
+        let found_names = find_table_names::<EmptyTable>(&log_plan);
+        assert_eq!(found_names, vec!["test_tbl_1", "test_tbl_2"]);
+        find_table_name::<EmptyTable>(&log_plan)
+            .expect_err("Two tables should have been found");
+
+        Ok(())
+    }
 }
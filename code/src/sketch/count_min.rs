@@ -0,0 +1,189 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use super::{to_label, ByteReader, ByteWriter, Sketch};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::scalar::ScalarValue;
+
+const DEPTH: usize = 4;
+const WIDTH: usize = 512;
+/// The number of most frequent values that are tracked, the top-k can be at most this large
+pub const MAX_TOP_K: usize = 100;
+/// The candidates are pruned when they reach twice the number of tracked values
+const MAX_CANDIDATES: usize = 2 * MAX_TOP_K;
+
+/// Count-min sketch that tracks the most frequent values to approximate the heavy hitters.
+/// The counts are overestimated by at most `e / WIDTH` times the total count (with a high probability).
+#[derive(Debug, Clone)]
+pub struct CountMinTopK {
+    counters: Vec<u64>,
+    /// The values that might be among the most frequent ones
+    candidates: HashSet<String>,
+}
+
+impl Default for CountMinTopK {
+    fn default() -> Self {
+        Self {
+            counters: vec![0; DEPTH * WIDTH],
+            candidates: HashSet::new(),
+        }
+    }
+}
+
+impl CountMinTopK {
+    pub fn insert_label(&mut self, label: String) {
+        for index in Self::indexes(&label) {
+            self.counters[index] += 1;
+        }
+        self.candidates.insert(label);
+        if self.candidates.len() > MAX_CANDIDATES {
+            self.prune();
+        }
+    }
+
+    /// The estimated number of occurences of the value
+    pub fn estimate(&self, label: &str) -> u64 {
+        Self::indexes(label)
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The `k` most frequent values with their estimated count, most frequent first
+    pub fn top_k(&self, k: usize) -> Vec<(String, u64)> {
+        let mut counts = self
+            .candidates
+            .iter()
+            .map(|label| (label.clone(), self.estimate(label)))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(k);
+        counts
+    }
+
+    fn prune(&mut self) {
+        self.candidates = self
+            .top_k(MAX_TOP_K)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+    }
+
+    /// One counter per row, selected by double hashing
+    fn indexes(label: &str) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        label.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32);
+        (0..DEPTH).map(move |row| {
+            row * WIDTH
+                + (h1.wrapping_add((row as u64).wrapping_mul(h2)) % WIDTH as u64) as usize
+        })
+    }
+}
+
+impl Sketch for CountMinTopK {
+    fn insert(&mut self, value: &ScalarValue) -> Result<()> {
+        if let Some(label) = to_label(value)? {
+            self.insert_label(label);
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (counter, other_counter) in self.counters.iter_mut().zip(&other.counters) {
+            *counter += other_counter;
+        }
+        self.candidates.extend(other.candidates.iter().cloned());
+        if self.candidates.len() > MAX_CANDIDATES {
+            self.prune();
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::default();
+        for counter in &self.counters {
+            writer.u64(*counter);
+        }
+        writer.u32(self.candidates.len() as u32);
+        for label in &self.candidates {
+            writer.slice(label.as_bytes());
+        }
+        writer.bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+        let counters = (0..DEPTH * WIDTH)
+            .map(|_| reader.u64())
+            .collect::<Result<Vec<_>>>()?;
+        let nb_candidates = reader.u32()?;
+        let candidates = (0..nb_candidates)
+            .map(|_| {
+                String::from_utf8(reader.slice()?.to_vec()).map_err(|_| {
+                    DataFusionError::Execution("Invalid value in top-k sketch".to_owned())
+                })
+            })
+            .collect::<Result<HashSet<_>>>()?;
+        Ok(Self {
+            counters,
+            candidates,
+        })
+    }
+
+    fn return_type() -> DataType {
+        DataType::Utf8
+    }
+
+    /// The most frequent values formatted as `value1:count1, value2:count2, ...`
+    fn evaluate(&self, param: Option<f64>) -> Result<ScalarValue> {
+        let k = match param {
+            Some(k) if k >= 1. && k as usize <= MAX_TOP_K => k as usize,
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "approx_top_k requires a number of values between 1 and {}",
+                    MAX_TOP_K
+                )))
+            }
+        };
+        let top_k = self
+            .top_k(k)
+            .into_iter()
+            .map(|(label, count)| format!("{}:{}", label, count))
+            .collect::<Vec<_>>();
+        Ok(ScalarValue::Utf8(Some(top_k.join(", "))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_k() -> Result<()> {
+        let mut left = CountMinTopK::default();
+        let mut right = CountMinTopK::default();
+        for i in 0..1000 {
+            left.insert(&ScalarValue::Utf8(Some(format!("rare_{}", i))))?;
+            right.insert(&ScalarValue::Int64(Some(i)))?;
+            if i % 2 == 0 {
+                left.insert(&ScalarValue::Utf8(Some("frequent".to_owned())))?;
+            }
+            if i % 4 == 0 {
+                right.insert(&ScalarValue::Utf8(Some("frequent".to_owned())))?;
+                right.insert(&ScalarValue::Utf8(Some("common".to_owned())))?;
+            }
+        }
+
+        let mut merged = CountMinTopK::from_bytes(&left.to_bytes())?;
+        merged.merge(&right);
+        let top_k = merged.top_k(2);
+        assert_eq!(top_k[0].0, "frequent");
+        assert!(top_k[0].1 >= 750);
+        assert_eq!(top_k[1].0, "common");
+        assert!(top_k[1].1 >= 250);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use super::{hash_value, ByteReader, ByteWriter, Sketch};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::scalar::ScalarValue;
+
+/// The number of bits of the hash used to select a register
+const PRECISION: u32 = 12;
+const NB_REGISTERS: usize = 1 << PRECISION;
+
+/// HyperLogLog sketch to approximate the number of distinct values (~1.6% standard error)
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; NB_REGISTERS],
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn insert_hash(&mut self, hash: u64) {
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // the sentinel bit bounds the rank if the remaining bits are all 0
+        let remaining = (hash << PRECISION) | (1 << (PRECISION - 1));
+        let rank = remaining.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        let m = NB_REGISTERS as f64;
+        let alpha = 0.7213 / (1. + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|&reg| 2f64.powi(-(reg as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&reg| reg == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+impl Sketch for HyperLogLog {
+    fn insert(&mut self, value: &ScalarValue) -> Result<()> {
+        if let Some(hash) = hash_value(value)? {
+            self.insert_hash(hash);
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (reg, other_reg) in self.registers.iter_mut().zip(&other.registers) {
+            *reg = std::cmp::max(*reg, *other_reg);
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::default();
+        writer.slice(&self.registers);
+        writer.bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let registers = ByteReader::new(bytes).slice()?.to_vec();
+        if registers.len() != NB_REGISTERS {
+            return Err(DataFusionError::Execution(format!(
+                "Invalid HyperLogLog sketch with {} registers",
+                registers.len()
+            )));
+        }
+        Ok(Self { registers })
+    }
+
+    fn return_type() -> DataType {
+        DataType::UInt64
+    }
+
+    fn evaluate(&self, _param: Option<f64>) -> Result<ScalarValue> {
+        Ok(ScalarValue::UInt64(Some(self.count())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_distinct() -> Result<()> {
+        let mut left = HyperLogLog::default();
+        let mut right = HyperLogLog::default();
+        for i in 0..30_000 {
+            left.insert(&ScalarValue::Int64(Some(i)))?;
+        }
+        // half of the values are shared with the left sketch
+        for i in 15_000..45_000 {
+            right.insert(&ScalarValue::Int64(Some(i)))?;
+        }
+        right.insert(&ScalarValue::Int64(None))?;
+
+        let mut merged = HyperLogLog::from_bytes(&left.to_bytes())?;
+        merged.merge(&right);
+        let count = merged.count() as f64;
+        assert!((count - 45_000.).abs() / 45_000. < 0.05, "count: {}", count);
+
+        let mut small = HyperLogLog::default();
+        for i in 0..100 {
+            small.insert(&ScalarValue::Utf8(Some(format!("value_{}", i % 10))))?;
+        }
+        assert_eq!(small.count(), 10);
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Mergeable sketches and the aggregate functions that compute them.
+//!
+//! The hbees compute partial sketches that are serialized into `Utf8` columns,
+//! the hcombs merge them and evaluate the final approximation.
+
+mod count_min;
+mod hll;
+mod tdigest;
+mod udaf;
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+pub use count_min::CountMinTopK;
+pub use hll::HyperLogLog;
+pub use tdigest::TDigest;
+pub use udaf::{register_sketch_udafs, SketchFunction};
+
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::scalar::ScalarValue;
+
+/// A summary of a set of values that can be merged with the summaries of other sets
+pub trait Sketch: Default + Debug + Send + Sync + Sized {
+    /// Add a value to the summary, nulls are ignored
+    fn insert(&mut self, value: &ScalarValue) -> Result<()>;
+    fn merge(&mut self, other: &Self);
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> Result<Self>;
+    /// The type of the approximation computed from the sketch
+    fn return_type() -> DataType;
+    /// Compute the approximation, `param` is the second argument of the function if any
+    fn evaluate(&self, param: Option<f64>) -> Result<ScalarValue>;
+}
+
+/// A stable hash of the value, `None` if the value is null
+fn hash_value(value: &ScalarValue) -> Result<Option<u64>> {
+    let mut hasher = DefaultHasher::new();
+    match value {
+        ScalarValue::Boolean(Some(v)) => v.hash(&mut hasher),
+        ScalarValue::Int8(Some(v)) => (*v as i64).hash(&mut hasher),
+        ScalarValue::Int16(Some(v)) => (*v as i64).hash(&mut hasher),
+        ScalarValue::Int32(Some(v)) => (*v as i64).hash(&mut hasher),
+        ScalarValue::Int64(Some(v)) => v.hash(&mut hasher),
+        ScalarValue::UInt8(Some(v)) => (*v as u64).hash(&mut hasher),
+        ScalarValue::UInt16(Some(v)) => (*v as u64).hash(&mut hasher),
+        ScalarValue::UInt32(Some(v)) => (*v as u64).hash(&mut hasher),
+        ScalarValue::UInt64(Some(v)) => v.hash(&mut hasher),
+        ScalarValue::Float32(Some(v)) => (*v as f64).to_bits().hash(&mut hasher),
+        ScalarValue::Float64(Some(v)) => v.to_bits().hash(&mut hasher),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => {
+            v.hash(&mut hasher)
+        }
+        _ if is_null(value) => return Ok(None),
+        other => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Sketches are not supported for values of type {:?}",
+                other.get_datatype()
+            )))
+        }
+    }
+    Ok(Some(hasher.finish()))
+}
+
+/// The value as a float, `None` if the value is null
+fn to_f64(value: &ScalarValue) -> Result<Option<f64>> {
+    let float = match value {
+        ScalarValue::Int8(v) => v.map(|v| v as f64),
+        ScalarValue::Int16(v) => v.map(|v| v as f64),
+        ScalarValue::Int32(v) => v.map(|v| v as f64),
+        ScalarValue::Int64(v) => v.map(|v| v as f64),
+        ScalarValue::UInt8(v) => v.map(|v| v as f64),
+        ScalarValue::UInt16(v) => v.map(|v| v as f64),
+        ScalarValue::UInt32(v) => v.map(|v| v as f64),
+        ScalarValue::UInt64(v) => v.map(|v| v as f64),
+        ScalarValue::Float32(v) => v.map(|v| v as f64),
+        ScalarValue::Float64(v) => *v,
+        other => {
+            return Err(DataFusionError::Execution(format!(
+                "Expected a numeric value, found {:?}",
+                other.get_datatype()
+            )))
+        }
+    };
+    Ok(float)
+}
+
+/// The value as a string, `None` if the value is null
+fn to_label(value: &ScalarValue) -> Result<Option<String>> {
+    if is_null(value) {
+        return Ok(None);
+    }
+    let label = match value {
+        ScalarValue::Boolean(Some(v)) => v.to_string(),
+        ScalarValue::Int8(Some(v)) => v.to_string(),
+        ScalarValue::Int16(Some(v)) => v.to_string(),
+        ScalarValue::Int32(Some(v)) => v.to_string(),
+        ScalarValue::Int64(Some(v)) => v.to_string(),
+        ScalarValue::UInt8(Some(v)) => v.to_string(),
+        ScalarValue::UInt16(Some(v)) => v.to_string(),
+        ScalarValue::UInt32(Some(v)) => v.to_string(),
+        ScalarValue::UInt64(Some(v)) => v.to_string(),
+        ScalarValue::Float32(Some(v)) => v.to_string(),
+        ScalarValue::Float64(Some(v)) => v.to_string(),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => v.clone(),
+        other => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Sketches are not supported for values of type {:?}",
+                other.get_datatype()
+            )))
+        }
+    };
+    Ok(Some(label))
+}
+
+fn is_null(value: &ScalarValue) -> bool {
+    match value {
+        ScalarValue::Boolean(v) => v.is_none(),
+        ScalarValue::Utf8(v) | ScalarValue::LargeUtf8(v) => v.is_none(),
+        other => matches!(to_f64(other), Ok(None)),
+    }
+}
+
+/// Little endian encoding of the sketches
+#[derive(Default)]
+struct ByteWriter {
+    bytes: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn slice(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value);
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(DataFusionError::Execution("Sketch is truncated".to_owned()));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    fn slice(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
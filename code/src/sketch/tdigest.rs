@@ -0,0 +1,222 @@
+use super::{to_f64, ByteReader, ByteWriter, Sketch};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::scalar::ScalarValue;
+
+/// The higher the compression, the more centroids are kept and the more accurate the quantiles
+const COMPRESSION: f64 = 100.;
+/// The number of points buffered before they are merged into the centroids
+const BUFFER_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Merging t-digest to approximate the quantiles of a distribution,
+/// with a better accuracy for the extreme quantiles.
+#[derive(Debug, Clone, Default)]
+pub struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub fn insert_f64(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        if self.is_empty() {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = f64::min(self.min, value);
+            self.max = f64::max(self.max, value);
+        }
+        self.buffer.push(value);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.compress();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.centroids.is_empty() && self.buffer.is_empty()
+    }
+
+    fn total_weight(&self) -> f64 {
+        self.centroids.iter().map(|c| c.weight).sum::<f64>() + self.buffer.len() as f64
+    }
+
+    /// Merge the buffered points and the centroids that are close enough
+    fn compress(&mut self) {
+        let total_weight = self.total_weight();
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(
+            self.buffer
+                .drain(..)
+                .map(|mean| Centroid { mean, weight: 1. }),
+        );
+        all.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap());
+
+        let mut merged: Vec<Centroid> = Vec::with_capacity(all.len());
+        let mut weight_before = 0.;
+        for centroid in all {
+            if let Some(last) = merged.last_mut() {
+                let new_weight = last.weight + centroid.weight;
+                let q = (weight_before + new_weight / 2.) / total_weight;
+                let max_weight = 4. * total_weight * q * (1. - q) / COMPRESSION;
+                if new_weight <= f64::max(max_weight, 1.) {
+                    last.mean +=
+                        (centroid.mean - last.mean) * centroid.weight / new_weight;
+                    last.weight = new_weight;
+                    continue;
+                }
+                weight_before += last.weight;
+            }
+            merged.push(centroid);
+        }
+        self.centroids = merged;
+    }
+
+    /// The approximate value at quantile `q` (between 0 and 1)
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut digest = self.clone();
+        digest.compress();
+        let centroids = &digest.centroids;
+        let target = q.max(0.).min(1.) * digest.total_weight();
+
+        // interpolate between the centers of the centroids, bounded by min and max
+        let mut prev_position = 0.;
+        let mut prev_value = digest.min;
+        let mut cumulated = 0.;
+        for centroid in centroids {
+            let position = cumulated + centroid.weight / 2.;
+            if target < position {
+                let ratio = (target - prev_position) / (position - prev_position);
+                return Some(prev_value + ratio * (centroid.mean - prev_value));
+            }
+            prev_position = position;
+            prev_value = centroid.mean;
+            cumulated += centroid.weight;
+        }
+        let ratio = if cumulated > prev_position {
+            (target - prev_position) / (cumulated - prev_position)
+        } else {
+            1.
+        };
+        Some(prev_value + ratio * (digest.max - prev_value))
+    }
+}
+
+impl Sketch for TDigest {
+    fn insert(&mut self, value: &ScalarValue) -> Result<()> {
+        if let Some(value) = to_f64(value)? {
+            self.insert_f64(value);
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Self) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = f64::min(self.min, other.min);
+            self.max = f64::max(self.max, other.max);
+        }
+        self.centroids.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut digest = self.clone();
+        digest.compress();
+        let mut writer = ByteWriter::default();
+        writer.f64(digest.min);
+        writer.f64(digest.max);
+        writer.u32(digest.centroids.len() as u32);
+        for centroid in &digest.centroids {
+            writer.f64(centroid.mean);
+            writer.f64(centroid.weight);
+        }
+        writer.bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+        let min = reader.f64()?;
+        let max = reader.f64()?;
+        let nb_centroids = reader.u32()? as usize;
+        let centroids = (0..nb_centroids)
+            .map(|_| {
+                Ok(Centroid {
+                    mean: reader.f64()?,
+                    weight: reader.f64()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            centroids,
+            buffer: vec![],
+            min,
+            max,
+        })
+    }
+
+    fn return_type() -> DataType {
+        DataType::Float64
+    }
+
+    fn evaluate(&self, param: Option<f64>) -> Result<ScalarValue> {
+        let q = param.ok_or_else(|| {
+            DataFusionError::Execution(
+                "approx_percentile requires a percentile between 0 and 1".to_owned(),
+            )
+        })?;
+        if !(0. ..=1.).contains(&q) {
+            return Err(DataFusionError::Execution(format!(
+                "Percentile should be between 0 and 1, found {}",
+                q
+            )));
+        }
+        Ok(ScalarValue::Float64(self.quantile(q)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantiles() -> Result<()> {
+        let mut left = TDigest::default();
+        let mut right = TDigest::default();
+        for i in 0..10_000 {
+            left.insert(&ScalarValue::Int64(Some(i)))?;
+            right.insert(&ScalarValue::Float64(Some((i + 10_000) as f64)))?;
+        }
+        right.insert(&ScalarValue::Float64(None))?;
+
+        let mut merged = TDigest::from_bytes(&left.to_bytes())?;
+        merged.merge(&right);
+        let median = merged.quantile(0.5).unwrap();
+        assert!((median - 10_000.).abs() < 200., "median: {}", median);
+        let p99 = merged.quantile(0.99).unwrap();
+        assert!((p99 - 19_800.).abs() < 50., "p99: {}", p99);
+        assert_eq!(merged.quantile(0.), Some(0.));
+        assert_eq!(merged.quantile(1.), Some(19_999.));
+
+        assert_eq!(TDigest::default().quantile(0.5), None);
+        Ok(())
+    }
+}
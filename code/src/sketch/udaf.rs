@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use super::{to_f64, CountMinTopK, HyperLogLog, Sketch, TDigest};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::ExecutionContext;
+use datafusion::physical_plan::functions::{
+    AccumulatorFunctionImplementation, ReturnTypeFunction, Signature, StateTypeFunction,
+};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+
+/// The sketch aggregations that can be split between hbees and hcombs.
+/// Each of them is registered in 3 versions:
+/// - `name(value[, param])` computes the approximation in a single step
+/// - `name_partial(value)` computes the serialized sketch, in the hbees
+/// - `name_merge(sketch[, param])` merges the sketches and computes the approximation, in the hcombs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SketchFunction {
+    /// `approx_count_distinct(value)`
+    ApproxCountDistinct,
+    /// `approx_percentile(value, percentile)`, with the percentile between 0 and 1
+    ApproxPercentile,
+    /// `approx_top_k(value, k)`, the k most frequent values and their approximate count
+    ApproxTopK,
+}
+
+impl SketchFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "approx_count_distinct" => Some(Self::ApproxCountDistinct),
+            "approx_percentile" => Some(Self::ApproxPercentile),
+            "approx_top_k" => Some(Self::ApproxTopK),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ApproxCountDistinct => "approx_count_distinct",
+            Self::ApproxPercentile => "approx_percentile",
+            Self::ApproxTopK => "approx_top_k",
+        }
+    }
+
+    pub fn partial_name(&self) -> String {
+        format!("{}_partial", self.name())
+    }
+
+    pub fn merge_name(&self) -> String {
+        format!("{}_merge", self.name())
+    }
+
+    /// True if the function takes a constant parameter as second argument
+    pub fn has_param(&self) -> bool {
+        *self != Self::ApproxCountDistinct
+    }
+
+    fn udafs(&self) -> Vec<AggregateUDF> {
+        match self {
+            Self::ApproxCountDistinct => udafs::<HyperLogLog>(*self),
+            Self::ApproxPercentile => udafs::<TDigest>(*self),
+            Self::ApproxTopK => udafs::<CountMinTopK>(*self),
+        }
+    }
+}
+
+/// Register all the sketch aggregations on the given context
+pub fn register_sketch_udafs(ctx: &mut ExecutionContext) {
+    let functions = [
+        SketchFunction::ApproxCountDistinct,
+        SketchFunction::ApproxPercentile,
+        SketchFunction::ApproxTopK,
+    ];
+    for function in functions.iter() {
+        for udaf in function.udafs() {
+            ctx.register_udaf(udaf);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    /// values in, approximation out
+    Single,
+    /// values in, sketch out
+    Partial,
+    /// sketches in, approximation out
+    Merge,
+}
+
+fn udafs<S: Sketch + 'static>(function: SketchFunction) -> Vec<AggregateUDF> {
+    let nb_args = if function.has_param() { 2 } else { 1 };
+    vec![
+        udaf::<S>(function.name(), nb_args, Mode::Single),
+        udaf::<S>(&function.partial_name(), 1, Mode::Partial),
+        udaf::<S>(&function.merge_name(), nb_args, Mode::Merge),
+    ]
+}
+
+fn udaf<S: Sketch + 'static>(name: &str, nb_args: usize, mode: Mode) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |_| {
+        Ok(Arc::new(match mode {
+            Mode::Partial => DataType::Utf8,
+            Mode::Single | Mode::Merge => S::return_type(),
+        }))
+    });
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(move || Ok(Box::new(SketchAccumulator::<S>::new(mode))));
+    // the parameter is part of the state as the final accumulator does not see the arguments
+    let state_type: StateTypeFunction =
+        Arc::new(|_| Ok(Arc::new(vec![DataType::Utf8, DataType::Float64])));
+    AggregateUDF::new(
+        name,
+        &Signature::Any(nb_args),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+#[derive(Debug)]
+struct SketchAccumulator<S: Sketch> {
+    sketch: S,
+    param: Option<f64>,
+    mode: Mode,
+}
+
+impl<S: Sketch> SketchAccumulator<S> {
+    fn new(mode: Mode) -> Self {
+        Self {
+            sketch: S::default(),
+            param: None,
+            mode,
+        }
+    }
+
+    fn merge_encoded(&mut self, encoded: &ScalarValue) -> Result<()> {
+        match encoded {
+            ScalarValue::Utf8(Some(encoded)) => {
+                let bytes = base64::decode(encoded).map_err(|_| {
+                    DataFusionError::Execution("Invalid sketch encoding".to_owned())
+                })?;
+                self.sketch.merge(&S::from_bytes(&bytes)?);
+                Ok(())
+            }
+            ScalarValue::Utf8(None) => Ok(()),
+            other => Err(DataFusionError::Execution(format!(
+                "Expected a serialized sketch, found {:?}",
+                other.get_datatype()
+            ))),
+        }
+    }
+
+    fn encoded(&self) -> ScalarValue {
+        ScalarValue::Utf8(Some(base64::encode(&self.sketch.to_bytes())))
+    }
+}
+
+impl<S: Sketch> Accumulator for SketchAccumulator<S> {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![self.encoded(), ScalarValue::Float64(self.param)])
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        if let Some(param) = values.get(1) {
+            self.param = to_f64(param)?;
+        }
+        match self.mode {
+            Mode::Single | Mode::Partial => self.sketch.insert(&values[0]),
+            Mode::Merge => self.merge_encoded(&values[0]),
+        }
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        if let ScalarValue::Float64(Some(param)) = &states[1] {
+            self.param = Some(*param);
+        }
+        self.merge_encoded(&states[0])
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        match self.mode {
+            Mode::Partial => Ok(self.encoded()),
+            Mode::Single | Mode::Merge => self.sketch.evaluate(self.param),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float64Array, Int64Array, StringArray, UInt64Array};
+    use arrow::datatypes::{Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::datasource::MemTable;
+
+    fn context(batches: Vec<RecordBatch>) -> ExecutionContext {
+        let mut ctx = ExecutionContext::new();
+        register_sketch_udafs(&mut ctx);
+        let table = MemTable::try_new(batches[0].schema(), vec![batches]).unwrap();
+        ctx.register_table("test", Box::new(table));
+        ctx
+    }
+
+    fn values(from: i64, to: i64) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from((from..to).collect::<Vec<_>>()))],
+        )
+        .unwrap()
+    }
+
+    async fn query(ctx: &mut ExecutionContext, sql: &str) -> Vec<RecordBatch> {
+        let df = ctx.sql(sql).unwrap();
+        let plan = ctx.optimize(&df.to_logical_plan()).unwrap();
+        let plan = ctx.create_physical_plan(&plan).unwrap();
+        datafusion::physical_plan::collect(plan).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_single_step() {
+        let mut ctx = context(vec![values(0, 1000), values(500, 1500)]);
+        let results = query(
+            &mut ctx,
+            "SELECT approx_count_distinct(a), approx_percentile(a, 0.5) FROM test",
+        )
+        .await;
+        let distinct = results[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap()
+            .value(0);
+        assert!(
+            (distinct as i64 - 1500).abs() < 50,
+            "distinct: {}",
+            distinct
+        );
+        let median = results[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap()
+            .value(0);
+        assert!((median - 750.).abs() < 20., "median: {}", median);
+    }
+
+    #[tokio::test]
+    async fn test_partial_and_merge() {
+        // each hbee computes a partial sketch
+        let mut partials = vec![];
+        for (from, to) in &[(0, 1000), (500, 1500)] {
+            let mut ctx = context(vec![values(*from, *to)]);
+            partials.extend(
+                query(
+                    &mut ctx,
+                    "SELECT approx_count_distinct_partial(a) AS s FROM test",
+                )
+                .await,
+            );
+        }
+        assert_eq!(partials[0].schema().field(0).data_type(), &DataType::Utf8);
+
+        // the hcomb merges them
+        let mut ctx = context(partials);
+        let results =
+            query(&mut ctx, "SELECT approx_count_distinct_merge(s) FROM test").await;
+        let distinct = results[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap()
+            .value(0);
+        assert!(
+            (distinct as i64 - 1500).abs() < 50,
+            "distinct: {}",
+            distinct
+        );
+    }
+
+    #[tokio::test]
+    async fn test_top_k() {
+        let mut ctx = context(vec![values(0, 100), values(0, 10), values(0, 5)]);
+        let results = query(&mut ctx, "SELECT approx_top_k(a, 3) FROM test").await;
+        let top_k = results[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .value(0);
+        // 0 to 4 appear 3 times, the other values less
+        let top_k = top_k.split(", ").collect::<Vec<_>>();
+        assert_eq!(top_k.len(), 3);
+        for value_count in top_k {
+            let mut parts = value_count.split(':');
+            let value = parts.next().unwrap().parse::<i64>().unwrap();
+            let count = parts.next().unwrap().parse::<u64>().unwrap();
+            assert!(value < 5, "unexpected value {}", value);
+            assert!(count >= 3);
+        }
+    }
+}